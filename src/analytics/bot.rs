@@ -0,0 +1,213 @@
+//! Telling automated requests (link unfurlers, crawlers, uptime monitors,
+//! security scanners, HTTP libraries) apart from people following a link.
+
+use actix_web::http::header::HeaderMap;
+
+/// Lowercase names of automated clients, matched as whole words: `bot`
+/// matches `Pingdom.com_bot` but not the `CUBOT` phone brand
+const BOT_NAMES: &[&str] = &[
+    // Generic crawler names
+    "bot",
+    "robot",
+    "crawler",
+    "crawling",
+    "spider",
+    "slurp",
+    "scraper",
+    // Link unfurlers and previews (iMessage sends facebookexternalhit)
+    "slackbot",
+    "facebookexternalhit",
+    "facebot",
+    "whatsapp",
+    "skypeuripreview",
+    "embedly",
+    "preview",
+    "vkshare",
+    "pinterestbot",
+    "bingpreview",
+    "google-inspectiontool",
+    "google-pagerenderer",
+    // Uptime monitors
+    "uptimerobot",
+    "pingdom",
+    "statuscake",
+    "site24x7",
+    "checkly",
+    // Security scanners
+    "nessus",
+    "nmap",
+    "nikto",
+    "masscan",
+    "zgrab",
+    "censys",
+    "sqlmap",
+    "scanner",
+    "nuclei",
+    // Headless browsers
+    "headlesschrome",
+    "phantomjs",
+];
+
+/// Lowercase product names of HTTP libraries and command-line clients,
+/// matched as a whole product token (`curl/8.4.0`, or a bare `Ruby`)
+const HTTP_LIBRARIES: &[&str] = &[
+    "curl",
+    "wget",
+    "httpie",
+    "python-requests",
+    "python-urllib",
+    "python-httpx",
+    "aiohttp",
+    "go-http-client",
+    "java",
+    "okhttp",
+    "apache-httpclient",
+    "libwww-perl",
+    "axios",
+    "node-fetch",
+    "undici",
+    "guzzlehttp",
+    "ruby",
+];
+
+/// Product tokens with a version whose name ends in one of these are
+/// crawlers even when not listed, e.g. `AhrefsBot/7.0`
+const BOT_PRODUCT_SUFFIXES: &[&str] = &["bot", "crawler", "spider"];
+
+/// Whether `name` occurs in `text` with no letter or digit on either side
+fn contains_word(text: &str, name: &str) -> bool {
+    text.match_indices(name).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + name.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+/// Whether a product token of the User-Agent (`name/version`, separated by
+/// spaces) names an automated client
+fn has_bot_product(user_agent: &str) -> bool {
+    user_agent
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ';')
+        .any(|token| match token.split_once('/') {
+            Some((name, _)) => {
+                HTTP_LIBRARIES.contains(&name)
+                    || BOT_PRODUCT_SUFFIXES
+                        .iter()
+                        .any(|suffix| name.len() > suffix.len() && name.ends_with(suffix))
+            }
+            None => HTTP_LIBRARIES.contains(&token),
+        })
+}
+
+/// Whether a request looks automated: a known-bot User-Agent, or a browser
+/// prefetch / link preview rather than a click. Requests without a
+/// User-Agent are given the benefit of the doubt.
+pub fn is_bot(user_agent: &str, headers: &HeaderMap) -> bool {
+    let user_agent = user_agent.to_ascii_lowercase();
+    if BOT_NAMES
+        .iter()
+        .any(|name| contains_word(&user_agent, name))
+        || has_bot_product(&user_agent)
+    {
+        return true;
+    }
+
+    ["purpose", "sec-purpose", "x-purpose", "x-moz"]
+        .into_iter()
+        .filter_map(|name| headers.get(name)?.to_str().ok())
+        .any(|value| {
+            let value = value.to_ascii_lowercase();
+            value.contains("prefetch") || value.contains("preview")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn recognises_unfurlers_monitors_scanners_and_libraries() {
+        for user_agent in [
+            "Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)",
+            "Twitterbot/1.0",
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_11_1) AppleWebKit/601.2.4 \
+             (KHTML, like Gecko) Version/9.0.1 Safari/601.2.4 facebookexternalhit/1.1 \
+             Facebot Twitterbot/1.0",
+            "Mozilla/5.0 (compatible; Discordbot/2.0; +https://discordapp.com)",
+            "WhatsApp/2.23.20.0",
+            "Mozilla/5.0+(compatible; UptimeRobot/2.0; http://www.uptimerobot.com/)",
+            "Mozilla/5.00 (Nikto/2.1.6) (Evasions:None) (Test:000001)",
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+            "curl/8.4.0",
+            "python-requests/2.31.0",
+            "Go-http-client/1.1",
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) \
+             HeadlessChrome/119.0.0.0 Safari/537.36",
+            "Mozilla/5.0 (compatible; AhrefsBot/7.0; +http://ahrefs.com/robot/)",
+            "Pingdom.com_bot_version_1.4_(http://www.pingdom.com/)",
+            "Ruby",
+            "Python/3.11 aiohttp/3.9.1",
+        ] {
+            assert!(is_bot(user_agent, &HeaderMap::new()), "{user_agent}");
+        }
+    }
+
+    #[test]
+    fn browsers_are_people() {
+        for user_agent in [
+            "",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0",
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 \
+             (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1",
+            "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 \
+             (KHTML, like Gecko) Chrome/119.0.0.0 Mobile Safari/537.36",
+        ] {
+            assert!(!is_bot(user_agent, &HeaderMap::new()), "{user_agent}");
+        }
+    }
+
+    #[test]
+    fn bot_names_inside_other_words_are_people() {
+        for user_agent in [
+            // CUBOT phones
+            "Mozilla/5.0 (Linux; Android 10; CUBOT_X30) AppleWebKit/537.36 \
+             (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+            "Mozilla/5.0 (Linux; Android 11; CUBOT NOTE 20 PRO Build/RP1A.200720.011) \
+             AppleWebKit/537.36 (KHTML, like Gecko) Version/4.0 Chrome/119.0.6045.163 \
+             Mobile Safari/537.36",
+            "Mozilla/5.0 (Linux; Android 12; KingKong Power; CUBOT) AppleWebKit/537.36 \
+             (KHTML, like Gecko) Chrome/118.0.0.0 Mobile Safari/537.36",
+            // Device and app names that merely contain a marker
+            "Mozilla/5.0 (Linux; Android 9; Flare Ruby) AppleWebKit/537.36 \
+             (KHTML, like Gecko) Chrome/117.0.0.0 Mobile Safari/537.36",
+            "Mozilla/5.0 (SMART-TV; Linux; Tizen 6.5) AppleWebKit/537.36 (KHTML, like Gecko) \
+             Chrome/85.0.4183.93 Smart Monitor Safari/537.36",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) \
+             Chrome/120.0.0.0 Safari/537.36 Javelin/1.0",
+        ] {
+            assert!(!is_bot(user_agent, &HeaderMap::new()), "{user_agent}");
+        }
+    }
+
+    #[test]
+    fn prefetches_and_previews_are_not_clicks() {
+        let firefox =
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        assert!(is_bot(firefox, &headers(&[("sec-purpose", "prefetch")])));
+        assert!(is_bot(firefox, &headers(&[("x-purpose", "preview")])));
+        assert!(!is_bot(firefox, &headers(&[("accept", "text/html")])));
+    }
+}
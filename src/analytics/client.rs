@@ -0,0 +1,129 @@
+//! Browser, OS and device class parsed from the User-Agent header, and the
+//! host part of the Referer header.
+
+/// What a User-Agent says about the client
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Client {
+    /// Browser family, e.g. `Chrome`
+    pub browser: Option<String>,
+    /// Operating system family, e.g. `iOS` or `Windows`
+    pub os: Option<String>,
+    /// `desktop`, `mobile`, `tablet`, `bot` or `other`
+    pub device: &'static str,
+}
+
+impl Client {
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        let Some(parsed) = woothee::parser::Parser::new().parse(user_agent) else {
+            return Self {
+                device: "other",
+                ..Default::default()
+            };
+        };
+        let known =
+            |value: &str| (value != woothee::woothee::VALUE_UNKNOWN).then(|| value.to_string());
+
+        let os = known(parsed.os).map(|os| os_family(&os));
+        let device = match parsed.category {
+            "pc" => "desktop",
+            "crawler" => "bot",
+            "smartphone" | "mobilephone" if is_tablet(parsed.os, user_agent) => "tablet",
+            "smartphone" | "mobilephone" => "mobile",
+            _ => "other",
+        };
+
+        Self {
+            browser: known(parsed.name),
+            os,
+            device,
+        }
+    }
+}
+
+/// Android tablets leave `Mobile` out of their User-Agent
+fn is_tablet(os: &str, user_agent: &str) -> bool {
+    os == "iPad" || (os == "Android" && !user_agent.contains("Mobile"))
+}
+
+/// Collapse OS versions and device names into one family name
+fn os_family(os: &str) -> String {
+    match os {
+        "iPhone" | "iPad" | "iPod" => "iOS".to_string(),
+        "Mac OSX" => "macOS".to_string(),
+        os if os.starts_with("Windows Phone") => "Windows Phone".to_string(),
+        os if os.starts_with("Windows") => "Windows".to_string(),
+        os => os.to_string(),
+    }
+}
+
+/// Lowercased host of a Referer (or any) URL, without a leading `www.`
+pub fn referrer_host(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url.trim()).ok()?;
+    let host = parsed.host_str()?.to_ascii_lowercase();
+    Some(
+        host.strip_prefix("www.")
+            .map(str::to_string)
+            .unwrap_or(host),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPHONE: &str =
+        "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 \
+                          (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1";
+    const IPAD: &str = "Mozilla/5.0 (iPad; CPU OS 16_6 like Mac OS X) AppleWebKit/605.1.15 \
+                        (KHTML, like Gecko) Version/16.6 Mobile/15E148 Safari/604.1";
+    const ANDROID_TABLET: &str = "Mozilla/5.0 (Linux; Android 13; SM-X700) AppleWebKit/537.36 \
+                                  (KHTML, like Gecko) Chrome/119.0.0.0 Safari/537.36";
+    const WINDOWS_FIREFOX: &str =
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+    const GOOGLEBOT: &str =
+        "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+
+    #[test]
+    fn classifies_common_clients() {
+        assert_eq!(
+            Client::from_user_agent(IPHONE),
+            Client {
+                browser: Some("Safari".to_string()),
+                os: Some("iOS".to_string()),
+                device: "mobile",
+            }
+        );
+        assert_eq!(Client::from_user_agent(IPAD).device, "tablet");
+        assert_eq!(Client::from_user_agent(ANDROID_TABLET).device, "tablet");
+
+        let firefox = Client::from_user_agent(WINDOWS_FIREFOX);
+        assert_eq!(firefox.browser.as_deref(), Some("Firefox"));
+        assert_eq!(firefox.os.as_deref(), Some("Windows"));
+        assert_eq!(firefox.device, "desktop");
+
+        assert_eq!(Client::from_user_agent(GOOGLEBOT).device, "bot");
+    }
+
+    #[test]
+    fn unknown_user_agents_are_other() {
+        for ua in ["", "curl-like-but-not/1.0 something"] {
+            let client = Client::from_user_agent(ua);
+            assert_eq!(client.browser, None);
+            assert_eq!(client.device, "other");
+        }
+    }
+
+    #[test]
+    fn referrer_host_strips_www_and_path() {
+        assert_eq!(
+            referrer_host("https://www.Google.com/search?q=rus").as_deref(),
+            Some("google.com")
+        );
+        assert_eq!(
+            referrer_host("android-app://com.slack/").as_deref(),
+            Some("com.slack")
+        );
+        assert_eq!(referrer_host("not a url"), None);
+        assert_eq!(referrer_host(""), None);
+    }
+}
@@ -0,0 +1,139 @@
+//! Offline IP geolocation from a MaxMind-format (`.mmdb`) database, such as
+//! GeoLite2-City or GeoLite2-Country.
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+
+/// Where a click came from, as far as the database knows
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2 country code, e.g. `GB`
+    pub country: Option<String>,
+    /// ISO 3166-2 code of the top-level subdivision, e.g. `GB-ENG`
+    pub region: Option<String>,
+    /// English city name
+    pub city: Option<String>,
+}
+
+/// Loaded geolocation database
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl std::fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("database_type", &self.reader.metadata.database_type)
+            .field("build_epoch", &self.reader.metadata.build_epoch)
+            .finish()
+    }
+}
+
+impl GeoIp {
+    /// Read the whole database into memory
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MaxMindDBError> {
+        Ok(Self {
+            reader: Reader::open_readfile(path)?,
+        })
+    }
+
+    /// Look up a client address as reported by the connection info: a bare
+    /// IP, or an IP with a port. Unknown and unparsable addresses give an
+    /// empty [`Location`].
+    pub fn locate(&self, client_ip: &str) -> Location {
+        let Some(ip) = parse_ip(client_ip) else {
+            return Location::default();
+        };
+        let Ok(record) = self.reader.lookup::<geoip2::City>(ip) else {
+            return Location::default();
+        };
+
+        let country = record.country.and_then(|c| c.iso_code).map(str::to_string);
+        let region = record
+            .subdivisions
+            .as_ref()
+            .and_then(|s| s.first())
+            .and_then(|s| s.iso_code)
+            .zip(country.as_deref())
+            .map(|(subdivision, country)| format!("{}-{}", country, subdivision));
+        let city = record
+            .city
+            .and_then(|c| c.names)
+            .and_then(|names| names.get("en").map(|n| n.to_string()));
+
+        Location {
+            country,
+            region,
+            city,
+        }
+    }
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    let ip = value
+        .parse::<IpAddr>()
+        .or_else(|_| value.parse::<SocketAddr>().map(|a| a.ip()))
+        .or_else(|_| value.trim_matches(['[', ']']).parse::<IpAddr>())
+        .ok()?;
+    Some(ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::GEOIP_FIXTURE as FIXTURE;
+
+    #[test]
+    fn locates_known_networks() {
+        let geo = GeoIp::open(FIXTURE).unwrap();
+        assert_eq!(
+            geo.locate("81.2.69.142"),
+            Location {
+                country: Some("GB".into()),
+                region: Some("GB-ENG".into()),
+                city: Some("London".into()),
+            }
+        );
+        assert_eq!(
+            geo.locate("89.160.20.112").city.as_deref(),
+            Some("Linköping")
+        );
+        assert_eq!(
+            geo.locate("203.0.113.9"),
+            Location {
+                country: Some("AU".into()),
+                region: None,
+                city: None,
+            }
+        );
+    }
+
+    #[test]
+    fn accepts_ports_and_mapped_addresses() {
+        let geo = GeoIp::open(FIXTURE).unwrap();
+        for addr in [
+            "216.160.83.56:52100",
+            "::ffff:216.160.83.56",
+            "[::ffff:216.160.83.56]:443",
+        ] {
+            assert_eq!(geo.locate(addr).region.as_deref(), Some("US-WA"), "{addr}");
+        }
+    }
+
+    #[test]
+    fn unknown_addresses_have_no_location() {
+        let geo = GeoIp::open(FIXTURE).unwrap();
+        for addr in ["127.0.0.1", "2001:db8::1", "not an ip", ""] {
+            assert_eq!(geo.locate(addr), Location::default(), "{addr}");
+        }
+    }
+
+    #[test]
+    fn open_fails_for_missing_or_invalid_files() {
+        assert!(GeoIp::open("/nonexistent/geoip.mmdb").is_err());
+        assert!(GeoIp::open(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Clicks pushed to dashboards as they happen.
+//!
+//! Every recorded click is published on a broadcast channel held in
+//! `AppState`; each open `GET /api/clicks/stream` subscribes to it and
+//! forwards the owner's clicks as Server-Sent Events. Nothing is buffered
+//! for clients that are not connected.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::ClickDetails;
+
+/// Clicks a slow subscriber may fall behind before it skips ahead
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+/// A click as sent to live listeners
+#[derive(Debug, Clone, Serialize)]
+pub struct ClickEvent {
+    /// Owner of the link; only their streams receive the event
+    #[serde(skip)]
+    pub user_id: i64,
+    pub short_code: String,
+    /// UTC `YYYY-MM-DD HH:MM:SS`, as in the click history
+    pub clicked_at: String,
+    /// The link's human and bot click counters after this click
+    pub clicks: u64,
+    pub bot_clicks: u64,
+    pub bot: bool,
+    pub referrer_host: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub device: String,
+    pub country: Option<String>,
+}
+
+impl ClickEvent {
+    pub fn new(
+        user_id: i64,
+        short_code: &str,
+        details: &ClickDetails,
+        (clicks, bot_clicks): (u64, u64),
+    ) -> Self {
+        Self {
+            user_id,
+            short_code: short_code.to_string(),
+            clicked_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            clicks,
+            bot_clicks,
+            bot: details.is_bot,
+            referrer_host: details.referrer_host.clone(),
+            browser: details.client.browser.clone(),
+            os: details.client.os.clone(),
+            device: details.client.device.to_string(),
+            country: details.location.country.clone(),
+        }
+    }
+}
+
+/// Sender end of the click channel, created once per `AppState`
+pub fn channel() -> broadcast::Sender<ClickEvent> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// One Server-Sent Events message carrying `event`
+pub fn sse_message(event: &ClickEvent) -> String {
+    let data = serde_json::to_string(event).unwrap_or_else(|_| "{}".to_string());
+    format!("event: click\ndata: {data}\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_message_is_one_json_line_without_the_owner() {
+        let details = ClickDetails::default();
+        let event = ClickEvent::new(7, "abc123", &details, (3, 1));
+        let message = sse_message(&event);
+        assert!(message.starts_with("event: click\ndata: {"));
+        assert!(message.ends_with("}\n\n"));
+        assert_eq!(message.trim_end().lines().count(), 2);
+        assert!(message.contains(r#""short_code":"abc123""#));
+        assert!(message.contains(r#""clicks":3"#));
+        assert!(!message.contains("user_id"));
+    }
+
+    #[test]
+    fn subscribers_get_clicks_sent_after_they_joined() {
+        let sender = channel();
+        // Nobody is listening yet; the click is simply dropped
+        let details = ClickDetails::default();
+        let _ = sender.send(ClickEvent::new(7, "early1", &details, (1, 0)));
+
+        let mut receiver = sender.subscribe();
+        sender
+            .send(ClickEvent::new(7, "late22", &details, (2, 0)))
+            .unwrap();
+        assert_eq!(receiver.try_recv().unwrap().short_code, "late22");
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Click analytics: what is recorded about each redirect and how it is
+//! summarised for the stats API.
+
+pub mod bot;
+pub mod client;
+pub mod geo;
+pub mod live;
+pub mod proxy;
+pub mod rollup;
+pub mod timeseries;
+pub mod visitor;
+
+pub use bot::is_bot;
+pub use client::{referrer_host, Client};
+pub use geo::{GeoIp, Location};
+pub use proxy::TrustedProxies;
+pub use visitor::{unique_visitors, visitor_hash};
+
+use actix_web::HttpRequest;
+use rusqlite::{params, Connection};
+
+use crate::config::Config;
+use crate::models::{BreakdownEntry, ClickBreakdown};
+
+/// Number of values listed per dimension in a [`ClickBreakdown`]
+pub const BREAKDOWN_LIMIT: u32 = 20;
+
+/// What is stored about a single click besides its time. The client IP is
+/// only kept long enough to compute the visitor hash and location.
+#[derive(Debug, Default, Clone)]
+pub struct ClickDetails {
+    pub referrer_host: Option<String>,
+    pub client: Client,
+    pub location: Location,
+    /// Crawler, link unfurler, monitor or other automated client
+    pub is_bot: bool,
+    client_ip: Option<String>,
+    user_agent: String,
+}
+
+impl ClickDetails {
+    /// Collect click details from a redirect request. Referrers from
+    /// `host_url` itself (e.g. the password prompt) are not recorded; the
+    /// location is only filled in when a GeoIP database is loaded. The client
+    /// IP is the connection's peer unless that is one of the configured
+    /// trusted proxies.
+    pub fn from_request(req: &HttpRequest, config: &Config, geoip: Option<&GeoIp>) -> Self {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+        };
+        let user_agent = header(actix_web::http::header::USER_AGENT).to_string();
+        let own_host = referrer_host(&config.host_url);
+        let referrer_host = referrer_host(header(actix_web::http::header::REFERER))
+            .filter(|h| Some(h) != own_host.as_ref());
+        let client_ip = config
+            .trusted_proxies
+            .client_ip(req)
+            .map(|ip| ip.to_string());
+        let location = match (geoip, client_ip.as_deref()) {
+            (Some(geoip), Some(ip)) => geoip.locate(ip),
+            _ => Location::default(),
+        };
+
+        let client = Client::from_user_agent(&user_agent);
+        let is_bot = client.device == "bot" || is_bot(&user_agent, req.headers());
+
+        Self {
+            referrer_host,
+            client,
+            location,
+            is_bot,
+            client_ip,
+            user_agent,
+        }
+    }
+
+    /// Salted hash identifying the visitor for today, if the IP is known
+    pub fn visitor_hash(&self, db: &Connection) -> Option<String> {
+        let ip = self.client_ip.as_deref()?;
+        visitor_hash(db, ip, &self.user_agent)
+            .map_err(|e| tracing::warn!(error = %e, "Failed to compute visitor hash"))
+            .ok()
+    }
+}
+
+/// Clicks on `url_id` grouped by referrer, browser, OS, device class,
+/// country, region and matched geo rule, most frequent first. Bots are left out; clicks
+/// already purged from `click_history` are counted through the daily rollups.
+pub fn click_breakdown(db: &Connection, url_id: i64) -> rusqlite::Result<ClickBreakdown> {
+    let group = |dimension| top_values(db, url_id, dimension, BREAKDOWN_LIMIT);
+    Ok(ClickBreakdown {
+        referrers: group("referrer")?,
+        browsers: group("browser")?,
+        operating_systems: group("os")?,
+        devices: group("device")?,
+        countries: group("country")?,
+        regions: group("region")?,
+        geo_rules: group("geo_rule")?,
+    })
+}
+
+/// The `limit` most frequent values of one rollup dimension (such as
+/// `referrer`) among human clicks on `url_id`
+pub fn top_values(
+    db: &Connection,
+    url_id: i64,
+    dimension: &str,
+    limit: u32,
+) -> rusqlite::Result<Vec<BreakdownEntry>> {
+    let (column, _, missing) = rollup::DIMENSIONS
+        .into_iter()
+        .find(|(_, name, _)| *name == dimension)
+        .expect("known rollup dimension");
+    let mut stmt = db.prepare(&format!(
+        "SELECT value, SUM(clicks) AS clicks FROM (
+             SELECT COALESCE({column}, ?2) AS value, COUNT(*) AS clicks
+             FROM click_history WHERE url_id = ?1 AND is_bot = 0 GROUP BY value
+             UNION ALL
+             SELECT value, clicks FROM click_rollup_dimensions
+             WHERE url_id = ?1 AND dimension = ?3
+         )
+         GROUP BY value ORDER BY clicks DESC, value LIMIT ?4"
+    ))?;
+    let entries = stmt
+        .query_map(params![url_id, missing, dimension, limit], |row| {
+            Ok(BreakdownEntry {
+                value: row.get(0)?,
+                clicks: row.get(1)?,
+            })
+        })?
+        .collect();
+    entries
+}
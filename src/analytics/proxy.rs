@@ -0,0 +1,222 @@
+//! The visitor's address behind reverse proxies.
+//!
+//! `X-Forwarded-For` and `Forwarded` are set by whoever sends the request, so
+//! they are only believed when the connection itself comes from a proxy
+//! listed in `TRUSTED_PROXIES`. Otherwise the connection's peer address is
+//! the visitor.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use actix_web::http::header::{HeaderMap, FORWARDED};
+use actix_web::HttpRequest;
+
+/// Addresses and CIDR ranges of reverse proxies whose forwarding headers
+/// are believed. Empty by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<(IpAddr, u8)>);
+
+impl TrustedProxies {
+    /// Parse a comma-separated list such as `127.0.0.1, 10.0.0.0/8, fd00::/8`
+    pub fn parse(list: &str) -> Result<Self, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (addr, prefix) = match entry.split_once('/') {
+                    Some((addr, prefix)) => (addr, Some(prefix)),
+                    None => (entry, None),
+                };
+                let addr: IpAddr = addr
+                    .parse()
+                    .map_err(|_| format!("'{}' is not an IP address or CIDR range", entry))?;
+                let addr = addr.to_canonical();
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                let prefix = match prefix {
+                    Some(prefix) => prefix
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|p| *p <= max)
+                        .ok_or_else(|| format!("'{}' has an invalid prefix length", entry))?,
+                    None => max,
+                };
+                Ok((addr, prefix))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Whether `ip` is one of the trusted proxies
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|(network, prefix)| match (network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => same_prefix(
+                u32::from(*network).into(),
+                u32::from(ip).into(),
+                32,
+                *prefix,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                same_prefix(u128::from(*network), u128::from(ip), 128, *prefix)
+            }
+            _ => false,
+        })
+    }
+
+    /// The address the request came from. When the connection is from a
+    /// trusted proxy, the forwarding chain is followed back to the first
+    /// address that is not one; if the chain names something that is not an
+    /// IP address (such as `unknown`) the visitor is unknown.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip().to_canonical();
+        if !self.contains(peer) {
+            return Some(peer);
+        }
+        let hops = forwarded_for(req.headers());
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            client = parse_hop(hop)?;
+            if !self.contains(client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+impl fmt::Display for TrustedProxies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .0
+            .iter()
+            .map(|(addr, prefix)| format!("{}/{}", addr, prefix))
+            .collect();
+        f.write_str(&entries.join(", "))
+    }
+}
+
+fn same_prefix(network: u128, ip: u128, bits: u8, prefix: u8) -> bool {
+    // Shifting out all 128 bits (a /0 range) leaves nothing to compare
+    let host_bits = u32::from(bits - prefix);
+    network.checked_shr(host_bits).unwrap_or(0) == ip.checked_shr(host_bits).unwrap_or(0)
+}
+
+/// Addresses the request was forwarded for, client first. `Forwarded` is
+/// used when present, `X-Forwarded-For` otherwise.
+fn forwarded_for(headers: &HeaderMap) -> Vec<String> {
+    let values = |name: &str| {
+        headers
+            .get_all(name)
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let forwarded: Vec<String> = values(FORWARDED.as_str())
+        .iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for")
+                    .then(|| value.trim_matches('"').to_string())
+            })
+        })
+        .collect();
+    if forwarded.is_empty() {
+        values("x-forwarded-for")
+    } else {
+        forwarded
+    }
+}
+
+/// One forwarding hop: an IP address, optionally with a port, IPv6 in
+/// brackets when it has one
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let ip = hop
+        .parse::<IpAddr>()
+        .or_else(|_| hop.parse::<std::net::SocketAddr>().map(|a| a.ip()))
+        .or_else(|_| hop.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()?;
+    Some(ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn client_ip(trusted: &str, peer: &str, headers: &[(&str, &str)]) -> Option<String> {
+        let mut req = TestRequest::default().peer_addr(format!("{peer}:4711").parse().unwrap());
+        for header in headers {
+            req = req.append_header(*header);
+        }
+        TrustedProxies::parse(trusted)
+            .unwrap()
+            .client_ip(&req.to_http_request())
+            .map(|ip| ip.to_string())
+    }
+
+    #[test]
+    fn parses_addresses_and_ranges() {
+        let trusted = TrustedProxies::parse(" 127.0.0.1, 10.0.0.0/8,fd00::/8 ,").unwrap();
+        for ip in ["127.0.0.1", "10.1.2.3", "::ffff:10.9.9.9", "fd12::1"] {
+            assert!(trusted.contains(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["127.0.0.2", "11.0.0.1", "fe80::1"] {
+            assert!(!trusted.contains(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(TrustedProxies::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("203.0.113.9".parse().unwrap()));
+        assert_eq!(
+            TrustedProxies::parse("").unwrap(),
+            TrustedProxies::default()
+        );
+        assert!(TrustedProxies::parse("localhost").is_err());
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn forwarding_headers_are_ignored_from_untrusted_peers() {
+        let spoofed = [
+            ("X-Forwarded-For", "198.51.100.7"),
+            ("Forwarded", "for=198.51.100.8"),
+        ];
+        assert_eq!(
+            client_ip("", "203.0.113.9", &spoofed).as_deref(),
+            Some("203.0.113.9")
+        );
+        assert_eq!(
+            client_ip("10.0.0.0/8", "203.0.113.9", &spoofed).as_deref(),
+            Some("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_are_skipped_from_the_right() {
+        // The visitor prepended a fake address; the proxies' own entries are skipped
+        let headers = [("X-Forwarded-For", "1.1.1.1, 203.0.113.9, 10.0.0.2")];
+        assert_eq!(
+            client_ip("10.0.0.0/8", "10.0.0.1", &headers).as_deref(),
+            Some("203.0.113.9")
+        );
+        let headers = [(
+            "Forwarded",
+            "for=198.51.100.1;proto=https, for=\"[2001:db8::7]:4711\"",
+        )];
+        assert_eq!(
+            client_ip("10.0.0.1", "10.0.0.1", &headers).as_deref(),
+            Some("2001:db8::7")
+        );
+        assert_eq!(
+            client_ip("10.0.0.1", "10.0.0.1", &[]).as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(
+            client_ip("10.0.0.1", "10.0.0.1", &[("X-Forwarded-For", "unknown")]),
+            None
+        );
+    }
+}
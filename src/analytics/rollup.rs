@@ -0,0 +1,291 @@
+//! Daily rollups of purged click history.
+//!
+//! Raw `click_history` rows are deleted after `CLICK_RETENTION_DAYS`. Just
+//! before that, [`roll_up_clicks`] adds them to per-link, per-UTC-day human
+//! and bot counts and unique visitors in `click_rollups`, and per-dimension
+//! counts of human clicks in `click_rollup_dimensions`. Visitor hashes are
+//! not kept, so only whole days are rolled up: a visitor's clicks on one day
+//! are always counted together.
+//! Clicks are moved, never copied, so a link's all-time figures are always
+//! the raw rows plus the rollups.
+
+use rusqlite::{params, Connection};
+
+/// Recorded click dimensions kept in the rollups: `click_history` column,
+/// dimension name, and the value counted when the column is empty
+pub const DIMENSIONS: [(&str, &str, &str); 8] = [
+    ("referrer_host", "referrer", "direct"),
+    ("browser", "browser", "unknown"),
+    ("os", "os", "unknown"),
+    ("device", "device", "unknown"),
+    ("country", "country", "unknown"),
+    ("region", "region", "unknown"),
+    ("city", "city", "unknown"),
+    ("geo_rule", "geo_rule", "fallback"),
+];
+
+/// Add every click recorded before the UTC day `cutoff_day` (`YYYY-MM-DD`)
+/// to the rollups. The caller deletes those rows afterwards, in the same
+/// transaction. Returns the number of clicks rolled up.
+pub fn roll_up_clicks(db: &Connection, cutoff_day: &str) -> rusqlite::Result<usize> {
+    let clicks: usize = db.query_row(
+        "SELECT COUNT(*) FROM click_history WHERE clicked_at < ?1",
+        params![cutoff_day],
+        |row| row.get(0),
+    )?;
+    if clicks == 0 {
+        return Ok(0);
+    }
+
+    db.execute(
+        "INSERT INTO click_rollups (url_id, day, clicks, bot_clicks, visitors)
+         SELECT url_id, date(clicked_at), SUM(is_bot = 0), SUM(is_bot = 1),
+                COUNT(DISTINCT CASE WHEN is_bot = 0 THEN visitor_hash END)
+         FROM click_history
+         WHERE clicked_at < ?1
+         GROUP BY url_id, date(clicked_at)
+         ON CONFLICT (url_id, day) DO UPDATE SET
+             clicks = clicks + excluded.clicks,
+             bot_clicks = bot_clicks + excluded.bot_clicks,
+             visitors = visitors + excluded.visitors",
+        params![cutoff_day],
+    )?;
+    for (column, dimension, missing) in DIMENSIONS {
+        db.execute(
+            &format!(
+                "INSERT INTO click_rollup_dimensions (url_id, day, dimension, value, clicks)
+                 SELECT url_id, date(clicked_at), ?2, COALESCE({column}, ?3), COUNT(*)
+                 FROM click_history
+                 WHERE clicked_at < ?1 AND is_bot = 0
+                 GROUP BY url_id, date(clicked_at), COALESCE({column}, ?3)
+                 ON CONFLICT (url_id, dimension, value, day)
+                 DO UPDATE SET clicks = clicks + excluded.clicks"
+            ),
+            params![cutoff_day, dimension, missing],
+        )?;
+    }
+    Ok(clicks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::make_test_state;
+
+    fn insert_click(db: &Connection, url_id: i64, clicked_at: &str, browser: Option<&str>) {
+        db.execute(
+            "INSERT INTO click_history (url_id, clicked_at, browser, device)
+             VALUES (?1, ?2, ?3, 'desktop')",
+            params![url_id, clicked_at, browser],
+        )
+        .unwrap();
+    }
+
+    fn rollup(db: &Connection) -> Vec<(i64, String, i64)> {
+        let mut stmt = db
+            .prepare("SELECT url_id, day, clicks FROM click_rollups ORDER BY url_id, day")
+            .unwrap();
+        stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    fn dimension(db: &Connection, dimension: &str) -> Vec<(String, i64)> {
+        let mut stmt = db
+            .prepare(
+                "SELECT value, SUM(clicks) FROM click_rollup_dimensions
+                 WHERE dimension = ?1 GROUP BY value ORDER BY value",
+            )
+            .unwrap();
+        stmt.query_map([dimension], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rolls_up_clicks_before_cutoff_per_day_and_dimension() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass')",
+            [],
+        )
+        .unwrap();
+        let user_id = db.last_insert_rowid();
+        for code in ["aaa111", "bbb222"] {
+            db.execute(
+                "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, 'https://example.com', ?2)",
+                params![user_id, code],
+            )
+            .unwrap();
+        }
+
+        insert_click(&db, 1, "2024-01-01 08:00:00", Some("Firefox"));
+        insert_click(&db, 1, "2024-01-01 20:00:00", None);
+        insert_click(&db, 1, "2024-01-02 08:00:00", Some("Firefox"));
+        insert_click(&db, 2, "2024-01-01 09:00:00", Some("Safari"));
+        insert_click(&db, 1, "2024-01-03 08:00:00", Some("Chrome"));
+        db.execute(
+            "UPDATE click_history SET city = 'London' WHERE clicked_at < '2024-01-01 12:00:00'",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(roll_up_clicks(&db, "2024-01-03").unwrap(), 4);
+        assert_eq!(
+            rollup(&db),
+            [
+                (1, "2024-01-01".to_string(), 2),
+                (1, "2024-01-02".to_string(), 1),
+                (2, "2024-01-01".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            dimension(&db, "browser"),
+            [
+                ("Firefox".to_string(), 2),
+                ("Safari".to_string(), 1),
+                ("unknown".to_string(), 1),
+            ]
+        );
+        assert_eq!(dimension(&db, "referrer"), [("direct".to_string(), 4)]);
+        assert_eq!(dimension(&db, "device"), [("desktop".to_string(), 4)]);
+        assert_eq!(
+            dimension(&db, "city"),
+            [("London".to_string(), 2), ("unknown".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn bots_are_rolled_up_separately() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass')",
+            [],
+        )
+        .unwrap();
+        let user_id = db.last_insert_rowid();
+        db.execute(
+            "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, 'https://example.com', 'aaa111')",
+            params![user_id],
+        )
+        .unwrap();
+        insert_click(&db, 1, "2024-01-01 08:00:00", Some("Firefox"));
+        db.execute(
+            "INSERT INTO click_history (url_id, clicked_at, browser, device, is_bot)
+             VALUES (1, '2024-01-01 09:00:00', 'Slackbot', 'bot', 1)",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(roll_up_clicks(&db, "2024-01-02").unwrap(), 2);
+        let (clicks, bot_clicks): (i64, i64) = db
+            .query_row("SELECT clicks, bot_clicks FROM click_rollups", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((clicks, bot_clicks), (1, 1));
+        assert_eq!(dimension(&db, "browser"), [("Firefox".to_string(), 1)]);
+    }
+
+    #[test]
+    fn counts_unique_human_visitors_per_day() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass')",
+            [],
+        )
+        .unwrap();
+        let user_id = db.last_insert_rowid();
+        db.execute(
+            "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, 'https://example.com', 'aaa111')",
+            params![user_id],
+        )
+        .unwrap();
+        db.execute_batch(
+            "INSERT INTO click_history (url_id, clicked_at, visitor_hash, is_bot) VALUES
+                 (1, '2024-01-01 08:00:00', 'aaaa', 0),
+                 (1, '2024-01-01 09:00:00', 'aaaa', 0),
+                 (1, '2024-01-01 10:00:00', 'bbbb', 0),
+                 (1, '2024-01-01 11:00:00', 'cccc', 1),
+                 (1, '2024-01-01 12:00:00', NULL, 0),
+                 (1, '2024-01-02 08:00:00', 'dddd', 0);",
+        )
+        .unwrap();
+
+        roll_up_clicks(&db, "2024-01-03").unwrap();
+        let mut stmt = db
+            .prepare("SELECT day, clicks, visitors FROM click_rollups ORDER BY day")
+            .unwrap();
+        let days: Vec<(String, i64, i64)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            days,
+            [
+                ("2024-01-01".to_string(), 4, 2),
+                ("2024-01-02".to_string(), 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn clicks_on_the_cutoff_day_are_left_for_a_later_purge() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass')",
+            [],
+        )
+        .unwrap();
+        let user_id = db.last_insert_rowid();
+        db.execute(
+            "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, 'https://example.com', 'aaa111')",
+            params![user_id],
+        )
+        .unwrap();
+        insert_click(&db, 1, "2024-01-01 23:59:59", None);
+        insert_click(&db, 1, "2024-01-02 00:00:00", None);
+
+        assert_eq!(roll_up_clicks(&db, "2024-01-02").unwrap(), 1);
+        assert_eq!(rollup(&db), [(1, "2024-01-01".to_string(), 1)]);
+    }
+
+    #[test]
+    fn later_purges_add_to_existing_days() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass')",
+            [],
+        )
+        .unwrap();
+        let user_id = db.last_insert_rowid();
+        db.execute(
+            "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, 'https://example.com', 'aaa111')",
+            params![user_id],
+        )
+        .unwrap();
+
+        // A click recorded late, after its day was already rolled up
+        insert_click(&db, 1, "2024-01-01 08:00:00", Some("Firefox"));
+        roll_up_clicks(&db, "2024-01-02").unwrap();
+        db.execute(
+            "DELETE FROM click_history WHERE clicked_at < '2024-01-02'",
+            [],
+        )
+        .unwrap();
+        insert_click(&db, 1, "2024-01-01 20:00:00", Some("Firefox"));
+        roll_up_clicks(&db, "2024-01-02").unwrap();
+
+        assert_eq!(rollup(&db), [(1, "2024-01-01".to_string(), 2)]);
+        assert_eq!(dimension(&db, "browser"), [("Firefox".to_string(), 2)]);
+        assert_eq!(roll_up_clicks(&db, "2023-12-31").unwrap(), 0);
+    }
+}
@@ -0,0 +1,574 @@
+//! Click counts bucketed by hour, day, week or month in a caller-chosen
+//! timezone, for plotting trends server-side.
+//!
+//! Clicks are first counted per 15-minute UTC slot in SQL (every real-world
+//! UTC offset is a multiple of 15 minutes), then each slot is moved into its
+//! local bucket. Buckets follow local wall-clock time, so a day bucket is 23
+//! or 25 hours long across a DST change.
+//!
+//! Clicks older than the retention period only survive as per-UTC-day counts
+//! in `click_rollups`; each such day is counted at its noon UTC, which puts
+//! it on the right local date for every common timezone.
+//!
+//! Visitor hashes rotate every UTC day, so a unique visitor is a distinct
+//! (UTC day, visitor hash) pair: someone clicking on two days counts in both,
+//! also when a local bucket spans more than one UTC day.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
+use rusqlite::{params, Connection};
+
+use crate::db::to_db_timestamp;
+use crate::models::{ClickTimeseries, TimeseriesBucket};
+
+/// Most buckets returned by one request
+pub const MAX_BUCKETS: usize = 5000;
+
+/// Width of each bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Hour,
+    Day,
+    /// ISO weeks, starting on Monday
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// Parse an `interval` parameter (`hour`, `day`, `week` or `month`)
+    pub fn from_param(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "hour" => Some(Self::Hour),
+            "day" => Some(Self::Day),
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    /// Start of the bucket holding local time `t`
+    fn floor(self, t: NaiveDateTime) -> NaiveDateTime {
+        let date = t.date();
+        match self {
+            Self::Hour => date.and_hms_opt(t.hour(), 0, 0).unwrap_or(t),
+            Self::Day => date.and_time(NaiveTime::MIN),
+            Self::Week => (date - Duration::days(date.weekday().num_days_from_monday() as i64))
+                .and_time(NaiveTime::MIN),
+            Self::Month => date.with_day(1).unwrap_or(date).and_time(NaiveTime::MIN),
+        }
+    }
+
+    /// Start of the bucket after the one starting at `start`
+    fn next(self, start: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Self::Hour => start + Duration::hours(1),
+            Self::Day => start + Duration::days(1),
+            Self::Week => start + Duration::weeks(1),
+            Self::Month => {
+                let date = start.date();
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                NaiveDate::from_ymd_opt(year, month, 1)
+                    .unwrap_or(date)
+                    .and_time(NaiveTime::MIN)
+            }
+        }
+    }
+}
+
+/// Which clicks to count
+#[derive(Debug, Clone, Copy)]
+pub enum ClickScope {
+    /// One link
+    Url(i64),
+    /// Every link owned by a user
+    User(i64),
+}
+
+impl ClickScope {
+    /// SQL condition on a `url_id` column, and its `?1` parameter
+    fn filter(self, column: &str) -> (String, i64) {
+        match self {
+            Self::Url(id) => (format!("{column} = ?1"), id),
+            Self::User(id) => (
+                format!("{column} IN (SELECT id FROM urls WHERE user_id = ?1)"),
+                id,
+            ),
+        }
+    }
+}
+
+/// Parse a timezone parameter: an IANA name such as `Europe/Berlin`
+pub fn parse_timezone(value: &str) -> Result<Tz, String> {
+    value.trim().parse::<Tz>().map_err(|_| {
+        format!(
+            "Unknown timezone '{}'; use an IANA name like Europe/Berlin",
+            value
+        )
+    })
+}
+
+/// Parse a range bound given as RFC 3339 or a `YYYY-MM-DD` date in `tz`. A
+/// date used as the end of the range includes that whole day.
+pub fn parse_bound(field: &str, value: &str, tz: Tz, end: bool) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end { date + Duration::days(1) } else { date };
+        return Ok(local_instant(tz, date.and_time(NaiveTime::MIN)).with_timezone(&Utc));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|ts| ts.with_timezone(&Utc))
+        .map_err(|_| format!("{} must be an RFC 3339 timestamp or YYYY-MM-DD date", field))
+}
+
+/// Time of the oldest recorded click in `scope`, if any
+pub fn first_click(db: &Connection, scope: ClickScope) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let (filter, id) = scope.filter("ch.url_id");
+    let (rollup_filter, _) = scope.filter("r.url_id");
+    let first: Option<String> = db.query_row(
+        &format!(
+            "SELECT MIN(first) FROM (
+                 SELECT MIN(ch.clicked_at) AS first FROM click_history ch WHERE {filter}
+                 UNION ALL
+                 SELECT MIN(r.day) || ' 12:00:00' FROM click_rollups r WHERE {rollup_filter}
+             )"
+        ),
+        params![id],
+        |row| row.get(0),
+    )?;
+    Ok(first.as_deref().and_then(parse_db_timestamp))
+}
+
+/// Local start of every bucket overlapping `[from, to)`, or an error when
+/// there would be more than [`MAX_BUCKETS`]
+pub fn bucket_starts(
+    interval: Interval,
+    tz: Tz,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<NaiveDateTime>, String> {
+    let end = to.with_timezone(&tz).naive_local();
+    let mut start = interval.floor(from.with_timezone(&tz).naive_local());
+    let mut starts = Vec::new();
+    while start < end || starts.is_empty() {
+        if starts.len() == MAX_BUCKETS {
+            return Err(format!(
+                "The range covers more than {} {} buckets; narrow from/to or use a larger interval",
+                MAX_BUCKETS,
+                interval.as_str()
+            ));
+        }
+        // Hours skipped by a DST change have no bucket
+        if interval != Interval::Hour
+            || !matches!(tz.from_local_datetime(&start), LocalResult::None)
+        {
+            starts.push(start);
+        }
+        start = interval.next(start);
+    }
+    Ok(starts)
+}
+
+/// 15-minute UTC slot of `ch.clicked_at`, as a `YYYY-MM-DD HH:MM:00` string
+const SLOT_SQL: &str = "strftime('%Y-%m-%d %H:', ch.clicked_at)
+    || printf('%02d:00', CAST(strftime('%M', ch.clicked_at) AS INTEGER) / 15 * 15)";
+
+/// Clicks counted into one bucket
+#[derive(Debug, Default)]
+struct BucketCounts {
+    clicks: u64,
+    bot_clicks: u64,
+    /// Unique visitors of rolled-up days; those of raw clicks are counted
+    /// from their hashes
+    rolled_up_visitors: u64,
+}
+
+impl BucketCounts {
+    fn add(&mut self, other: &Self) {
+        self.clicks += other.clicks;
+        self.bot_clicks += other.bot_clicks;
+        self.rolled_up_visitors += other.rolled_up_visitors;
+    }
+}
+
+/// Count human and bot clicks and unique visitors in `scope` during
+/// `[from, to)` into the given buckets
+pub fn click_timeseries(
+    db: &Connection,
+    scope: ClickScope,
+    interval: Interval,
+    tz: Tz,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    starts: &[NaiveDateTime],
+) -> rusqlite::Result<ClickTimeseries> {
+    let (filter, id) = scope.filter("ch.url_id");
+    let (rollup_filter, _) = scope.filter("r.url_id");
+    let mut stmt = db.prepare(&format!(
+        "SELECT {SLOT_SQL} AS slot, SUM(ch.is_bot = 0), SUM(ch.is_bot = 1), 0
+         FROM click_history ch
+         WHERE {filter} AND ch.clicked_at >= ?2 AND ch.clicked_at < ?3
+         GROUP BY slot
+         UNION ALL
+         SELECT r.day || ' 12:00:00' AS slot, SUM(r.clicks), SUM(r.bot_clicks), SUM(r.visitors)
+         FROM click_rollups r
+         WHERE {rollup_filter} AND r.day || ' 12:00:00' >= ?2 AND r.day || ' 12:00:00' < ?3
+         GROUP BY slot"
+    ))?;
+    let slots = stmt.query_map(
+        params![id, to_db_timestamp(from), to_db_timestamp(to)],
+        |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                BucketCounts {
+                    clicks: row.get(1)?,
+                    bot_clicks: row.get(2)?,
+                    rolled_up_visitors: row.get(3)?,
+                },
+            ))
+        },
+    )?;
+
+    let mut counts: BTreeMap<NaiveDateTime, BucketCounts> = BTreeMap::new();
+    for slot in slots {
+        let (slot, slot_counts) = slot?;
+        if let Some(ts) = parse_db_timestamp(&slot) {
+            let local = interval.floor(ts.with_timezone(&tz).naive_local());
+            counts.entry(local).or_default().add(&slot_counts);
+        }
+    }
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT DISTINCT {SLOT_SQL} AS slot, ch.visitor_hash
+         FROM click_history ch
+         WHERE {filter} AND ch.clicked_at >= ?2 AND ch.clicked_at < ?3
+               AND ch.is_bot = 0 AND ch.visitor_hash IS NOT NULL"
+    ))?;
+    let slot_visitors = stmt.query_map(
+        params![id, to_db_timestamp(from), to_db_timestamp(to)],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+    )?;
+    let mut visitors: HashMap<NaiveDateTime, HashSet<(NaiveDate, String)>> = HashMap::new();
+    let mut all_visitors: HashSet<(NaiveDate, String)> = HashSet::new();
+    for row in slot_visitors {
+        let (slot, hash) = row?;
+        if let Some(ts) = parse_db_timestamp(&slot) {
+            let local = interval.floor(ts.with_timezone(&tz).naive_local());
+            let visitor = (ts.date_naive(), hash);
+            visitors.entry(local).or_default().insert(visitor.clone());
+            all_visitors.insert(visitor);
+        }
+    }
+
+    let unique_visitors =
+        all_visitors.len() as u64 + counts.values().map(|c| c.rolled_up_visitors).sum::<u64>();
+    let buckets: Vec<TimeseriesBucket> = starts
+        .iter()
+        .map(|start| {
+            let counts = counts.remove(start).unwrap_or_default();
+            let raw_visitors = visitors.get(start).map_or(0, HashSet::len) as u64;
+            TimeseriesBucket {
+                start: local_instant(tz, *start).to_rfc3339(),
+                clicks: counts.clicks,
+                bot_clicks: counts.bot_clicks,
+                unique_visitors: raw_visitors + counts.rolled_up_visitors,
+            }
+        })
+        .collect();
+
+    Ok(ClickTimeseries {
+        interval: interval.as_str().to_string(),
+        timezone: tz.name().to_string(),
+        from: from.with_timezone(&tz).to_rfc3339(),
+        to: to.with_timezone(&tz).to_rfc3339(),
+        total: buckets.iter().map(|b| b.clicks).sum(),
+        bot_total: buckets.iter().map(|b| b.bot_clicks).sum(),
+        unique_visitors,
+        buckets,
+    })
+}
+
+/// The instant a local wall-clock time refers to; the earlier one when the
+/// clock was turned back, the first valid time after it when turned forward
+fn local_instant(tz: Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    let mut local = local;
+    loop {
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => return t,
+            LocalResult::None => local += Duration::minutes(15),
+        }
+    }
+}
+
+fn parse_db_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|ts| ts.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        parse_db_timestamp(value).unwrap()
+    }
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn db_with_clicks(clicks: &[&str]) -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE urls (id INTEGER PRIMARY KEY, user_id INTEGER);
+             CREATE TABLE click_history (
+                 id INTEGER PRIMARY KEY, url_id INTEGER, clicked_at TEXT, visitor_hash TEXT,
+                 is_bot INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE TABLE click_rollups (
+                 url_id INTEGER, day TEXT, clicks INTEGER, bot_clicks INTEGER NOT NULL DEFAULT 0,
+                 visitors INTEGER NOT NULL DEFAULT 0
+             );
+             INSERT INTO urls (id, user_id) VALUES (1, 7), (2, 7), (3, 8);",
+        )
+        .unwrap();
+        for (i, clicked_at) in clicks.iter().enumerate() {
+            db.execute(
+                "INSERT INTO click_history (url_id, clicked_at) VALUES (?1, ?2)",
+                params![(i % 3) as i64 + 1, clicked_at],
+            )
+            .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn floors_to_bucket_starts() {
+        let t = local("2024-03-14 17:45:12");
+        assert_eq!(Interval::Hour.floor(t), local("2024-03-14 17:00:00"));
+        assert_eq!(Interval::Day.floor(t), local("2024-03-14 00:00:00"));
+        assert_eq!(Interval::Week.floor(t), local("2024-03-11 00:00:00"));
+        assert_eq!(Interval::Month.floor(t), local("2024-03-01 00:00:00"));
+        assert_eq!(
+            Interval::Month.next(local("2024-12-01 00:00:00")),
+            local("2025-01-01 00:00:00")
+        );
+    }
+
+    #[test]
+    fn bounds_accept_dates_in_the_timezone_and_rfc3339() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            parse_bound("from", "2024-07-01", tz, false).unwrap(),
+            utc("2024-06-30 22:00:00")
+        );
+        assert_eq!(
+            parse_bound("to", "2024-07-01", tz, true).unwrap(),
+            utc("2024-07-01 22:00:00")
+        );
+        assert_eq!(
+            parse_bound("from", "2024-07-01T12:00:00+02:00", tz, false).unwrap(),
+            utc("2024-07-01 10:00:00")
+        );
+        assert!(parse_bound("from", "July 1st", tz, false).is_err());
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn days_follow_the_requested_timezone() {
+        // 23:30 UTC on the 1st is already the 2nd in Berlin
+        let db = db_with_clicks(&[
+            "2024-07-01 10:00:00",
+            "2024-07-01 23:30:00",
+            "2024-07-02 08:00:00",
+        ]);
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let from = parse_bound("from", "2024-07-01", tz, false).unwrap();
+        let to = parse_bound("to", "2024-07-03", tz, true).unwrap();
+        let starts = bucket_starts(Interval::Day, tz, from, to).unwrap();
+        let series = click_timeseries(
+            &db,
+            ClickScope::User(7),
+            Interval::Day,
+            tz,
+            from,
+            to,
+            &starts,
+        )
+        .unwrap();
+
+        let counts: Vec<(&str, u64)> = series
+            .buckets
+            .iter()
+            .map(|b| (b.start.as_str(), b.clicks))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("2024-07-01T00:00:00+02:00", 1),
+                ("2024-07-02T00:00:00+02:00", 1),
+                ("2024-07-03T00:00:00+02:00", 0),
+            ]
+        );
+        // The third click belongs to url 3, owned by user 8
+        assert_eq!(series.total, 2);
+    }
+
+    #[test]
+    fn half_hour_offsets_split_utc_hours() {
+        let db = db_with_clicks(&["2024-01-10 18:15:00", "2024-01-10 18:45:00"]);
+        let tz: Tz = "Asia/Kolkata".parse().unwrap();
+        let from = utc("2024-01-10 18:00:00");
+        let to = utc("2024-01-10 19:00:00");
+        let starts = bucket_starts(Interval::Hour, tz, from, to).unwrap();
+        let series = click_timeseries(
+            &db,
+            ClickScope::Url(1),
+            Interval::Hour,
+            tz,
+            from,
+            to,
+            &starts,
+        )
+        .unwrap();
+        // 18:15 UTC is 23:45 IST, 18:45 UTC is 00:15 IST the next day
+        assert_eq!(series.buckets.len(), 2);
+        assert_eq!(series.buckets[0].start, "2024-01-10T23:00:00+05:30");
+        assert_eq!(series.buckets[0].clicks, 1);
+    }
+
+    #[test]
+    fn dst_changes_skip_or_merge_hours() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        // Clocks go from 02:00 to 03:00 on 2024-03-31
+        let starts = bucket_starts(
+            Interval::Hour,
+            tz,
+            utc("2024-03-31 00:00:00"),
+            utc("2024-03-31 02:00:00"),
+        )
+        .unwrap();
+        assert_eq!(
+            starts,
+            [local("2024-03-31 01:00:00"), local("2024-03-31 03:00:00")]
+        );
+
+        // ...and a day bucket that spans it is only 23 hours long
+        let days = bucket_starts(
+            Interval::Day,
+            tz,
+            utc("2024-03-30 23:00:00"),
+            utc("2024-04-01 00:00:00"),
+        )
+        .unwrap();
+        assert_eq!(days.len(), 2);
+    }
+
+    #[test]
+    fn too_many_buckets_are_refused() {
+        let tz = Tz::UTC;
+        let from = utc("2020-01-01 00:00:00");
+        let to = utc("2024-01-01 00:00:00");
+        assert!(bucket_starts(Interval::Hour, tz, from, to).is_err());
+        assert_eq!(
+            bucket_starts(Interval::Month, tz, from, to).unwrap().len(),
+            48
+        );
+    }
+
+    #[test]
+    fn rolled_up_days_are_counted_at_noon_utc() {
+        let db = db_with_clicks(&["2024-07-03 09:00:00"]);
+        db.execute_batch(
+            "INSERT INTO click_rollups (url_id, day, clicks)
+             VALUES (1, '2024-07-01', 5), (1, '2024-07-02', 3), (3, '2024-07-01', 9);",
+        )
+        .unwrap();
+        let tz: Tz = "America/Los_Angeles".parse().unwrap();
+        let to = parse_bound("to", "2024-07-03", tz, true).unwrap();
+        let from = first_click(&db, ClickScope::Url(1)).unwrap().unwrap();
+        assert_eq!(from, utc("2024-07-01 12:00:00"));
+
+        let starts = bucket_starts(Interval::Day, tz, from, to).unwrap();
+        let series = click_timeseries(
+            &db,
+            ClickScope::Url(1),
+            Interval::Day,
+            tz,
+            from,
+            to,
+            &starts,
+        )
+        .unwrap();
+        let counts: Vec<u64> = series.buckets.iter().map(|b| b.clicks).collect();
+        assert_eq!(counts, [5, 3, 1]);
+        assert_eq!(series.buckets[0].start, "2024-07-01T00:00:00-07:00");
+    }
+
+    #[test]
+    fn unique_visitors_are_per_utc_day_within_each_bucket() {
+        let db = db_with_clicks(&[]);
+        db.execute_batch(
+            "INSERT INTO click_history (url_id, clicked_at, visitor_hash, is_bot) VALUES
+                 (1, '2024-07-02 08:00:00', 'aaaa', 0),
+                 (1, '2024-07-02 09:00:00', 'aaaa', 0),
+                 (1, '2024-07-02 10:00:00', 'bbbb', 0),
+                 (1, '2024-07-02 11:00:00', 'cccc', 1),
+                 (1, '2024-07-02 12:00:00', NULL, 0),
+                 (1, '2024-07-03 08:00:00', 'aaaa', 0);
+             INSERT INTO click_rollups (url_id, day, clicks, visitors)
+                 VALUES (1, '2024-07-01', 6, 4);",
+        )
+        .unwrap();
+        let tz = Tz::UTC;
+        let from = utc("2024-07-01 00:00:00");
+        let to = utc("2024-07-04 00:00:00");
+        let series = |interval| {
+            let starts = bucket_starts(interval, tz, from, to).unwrap();
+            click_timeseries(&db, ClickScope::Url(1), interval, tz, from, to, &starts).unwrap()
+        };
+
+        let days = series(Interval::Day);
+        let uniques: Vec<u64> = days.buckets.iter().map(|b| b.unique_visitors).collect();
+        assert_eq!(uniques, [4, 2, 1]);
+        assert_eq!(days.unique_visitors, 7);
+
+        // The visitor back on the 3rd is a new visitor that day
+        let week = series(Interval::Week);
+        assert_eq!(week.buckets.len(), 1);
+        assert_eq!(week.buckets[0].unique_visitors, 7);
+        assert_eq!(week.buckets[0].clicks, 11);
+    }
+
+    #[test]
+    fn first_click_is_scoped() {
+        let db = db_with_clicks(&["2024-05-02 00:00:00", "2024-05-01 00:00:00"]);
+        assert_eq!(
+            first_click(&db, ClickScope::Url(1)).unwrap(),
+            Some(utc("2024-05-02 00:00:00"))
+        );
+        assert_eq!(
+            first_click(&db, ClickScope::User(7)).unwrap(),
+            Some(utc("2024-05-01 00:00:00"))
+        );
+        assert_eq!(first_click(&db, ClickScope::User(8)).unwrap(), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Privacy-preserving visitor identifiers.
+//!
+//! A visitor is identified by a hash of their IP address and User-Agent with
+//! a random salt that changes every UTC day. Old salts are deleted as soon as
+//! a new one is created, so stored hashes can neither be reversed nor linked
+//! to the same visitor on another day. Unique visitors are therefore
+//! counted per UTC day: someone coming back the next day counts again.
+
+use rand::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+/// Today's salt, created (and earlier salts dropped) on first use
+fn daily_salt(db: &Connection) -> rusqlite::Result<String> {
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    let existing: Option<String> = db
+        .query_row(
+            "SELECT salt FROM visitor_salts WHERE day = ?1",
+            params![today],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(salt) = existing {
+        return Ok(salt);
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    // Another connection may have created today's salt in the meantime;
+    // keep whichever was stored first.
+    db.execute(
+        "INSERT OR IGNORE INTO visitor_salts (day, salt) VALUES (?1, ?2)",
+        params![today, hex::encode(bytes)],
+    )?;
+    db.execute("DELETE FROM visitor_salts WHERE day <> ?1", params![today])?;
+    db.query_row(
+        "SELECT salt FROM visitor_salts WHERE day = ?1",
+        params![today],
+        |row| row.get(0),
+    )
+}
+
+/// Hash identifying a visitor for the current UTC day
+pub fn visitor_hash(db: &Connection, ip: &str, user_agent: &str) -> rusqlite::Result<String> {
+    let salt = daily_salt(db)?;
+    let digest = Sha256::new()
+        .chain_update(salt)
+        .chain_update([0])
+        .chain_update(ip)
+        .chain_update([0])
+        .chain_update(user_agent)
+        .finalize();
+    Ok(hex::encode(&digest[..16]))
+}
+
+/// Unique human visitors of `url_id`, summed over UTC days, rolled-up days
+/// included. Clicks recorded without a visitor hash are not counted.
+pub fn unique_visitors(db: &Connection, url_id: i64) -> rusqlite::Result<u64> {
+    db.query_row(
+        "SELECT (SELECT COUNT(*) FROM (
+                     SELECT DISTINCT date(clicked_at), visitor_hash FROM click_history
+                     WHERE url_id = ?1 AND is_bot = 0 AND visitor_hash IS NOT NULL
+                 ))
+              + (SELECT COALESCE(SUM(visitors), 0) FROM click_rollups WHERE url_id = ?1)",
+        params![url_id],
+        |row| row.get(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::make_test_state;
+
+    #[test]
+    fn same_visitor_same_day_gets_same_hash() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        let a = visitor_hash(&db, "203.0.113.7", "UA").unwrap();
+        assert_eq!(a, visitor_hash(&db, "203.0.113.7", "UA").unwrap());
+        assert_ne!(a, visitor_hash(&db, "203.0.113.8", "UA").unwrap());
+        assert_ne!(a, visitor_hash(&db, "203.0.113.7", "Other UA").unwrap());
+        // Only a truncated digest is kept, never the address itself
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn salt_rotates_and_old_salts_are_dropped() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute(
+            "INSERT INTO visitor_salts (day, salt) VALUES ('2000-01-01', 'old')",
+            [],
+        )
+        .unwrap();
+        let today = visitor_hash(&db, "203.0.113.7", "UA").unwrap();
+
+        let days: Vec<String> = db
+            .prepare("SELECT day FROM visitor_salts")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(days.len(), 1);
+        assert_ne!(days[0], "2000-01-01");
+
+        // A different salt gives a different hash for the same visitor
+        db.execute("UPDATE visitor_salts SET salt = 'rotated'", [])
+            .unwrap();
+        assert_ne!(today, visitor_hash(&db, "203.0.113.7", "UA").unwrap());
+    }
+
+    #[test]
+    fn unique_visitors_are_counted_per_day() {
+        let state = make_test_state();
+        let db = state.db.lock().unwrap();
+        db.execute_batch(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass');
+             INSERT INTO urls (user_id, original_url, short_code)
+                 VALUES (1, 'https://example.com', 'aaa111');
+             INSERT INTO click_history (url_id, clicked_at, visitor_hash, is_bot) VALUES
+                 (1, '2024-01-02 08:00:00', 'aaaa', 0),
+                 (1, '2024-01-02 09:00:00', 'aaaa', 0),
+                 (1, '2024-01-02 10:00:00', 'bbbb', 0),
+                 (1, '2024-01-02 11:00:00', 'cccc', 1),
+                 (1, '2024-01-02 12:00:00', NULL, 0),
+                 (1, '2024-01-03 08:00:00', 'aaaa', 0);
+             INSERT INTO click_rollups (url_id, day, clicks, visitors)
+                 VALUES (1, '2024-01-01', 9, 4);",
+        )
+        .unwrap();
+
+        assert_eq!(unique_visitors(&db, 1).unwrap(), 7);
+        assert_eq!(unique_visitors(&db, 2).unwrap(), 0);
+    }
+}
@@ -0,0 +1,278 @@
+//! Command-line tasks that run instead of the server.
+
+use rusqlite::params;
+
+use crate::config::Config;
+use crate::db::AppState;
+use crate::import::{import_links, parse_import, parse_utm_presets, ImportFormat, OnConflict};
+
+const IMPORT_USAGE: &str = "Usage: rus import <file|-> --user <username> \
+                            [--format csv|json|zip] [--on-conflict rename|skip]";
+
+/// Options for `rus import`
+struct ImportArgs {
+    path: String,
+    username: String,
+    format: Option<ImportFormat>,
+    on_conflict: OnConflict,
+}
+
+fn parse_import_args(args: &[String]) -> Result<ImportArgs, String> {
+    let mut path = None;
+    let mut username = None;
+    let mut format = None;
+    let mut on_conflict = OnConflict::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match arg.as_str() {
+            "--user" => username = Some(value("--user")?),
+            "--format" => {
+                let v = value("--format")?;
+                format = Some(
+                    ImportFormat::from_param(&v)
+                        .ok_or("--format must be 'csv', 'json' or 'zip'")?,
+                );
+            }
+            "--on-conflict" => {
+                let v = value("--on-conflict")?;
+                on_conflict =
+                    OnConflict::from_param(&v).ok_or("--on-conflict must be 'rename' or 'skip'")?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    Ok(ImportArgs {
+        path: path.ok_or("Missing export file")?,
+        username: username.ok_or("Missing --user")?,
+        format,
+        on_conflict,
+    })
+}
+
+/// `rus import`: load a Bitly / YOURLS / Shlink export into a user's links.
+/// Returns the process exit code.
+pub fn run_import(config: Config, args: &[String]) -> i32 {
+    let args = match parse_import_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, IMPORT_USAGE);
+            return 2;
+        }
+    };
+
+    let data = if args.path == "-" {
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut data).map(|_| data)
+    } else {
+        std::fs::read(&args.path)
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", args.path, e);
+            return 1;
+        }
+    };
+
+    let format = args.format.unwrap_or_else(|| ImportFormat::detect(&data));
+    let parsed = parse_import(&data, format)
+        .and_then(|records| Ok((records, parse_utm_presets(&data, format)?)));
+    let (records, presets) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let max_url_length = config.max_url_length;
+    let state = match AppState::new(config) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to open database: {}", e);
+            return 1;
+        }
+    };
+    let mut db = state.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let user_id: i64 = match db.query_row(
+        "SELECT userID FROM users WHERE username = ?1",
+        params![args.username],
+        |row| row.get(0),
+    ) {
+        Ok(id) => id,
+        Err(_) => {
+            eprintln!("No user named '{}'", args.username);
+            return 1;
+        }
+    };
+
+    match import_links(
+        &mut db,
+        user_id,
+        records,
+        &presets,
+        args.on_conflict,
+        max_url_length,
+    ) {
+        Ok(report) => {
+            for conflict in &report.conflicts {
+                match &conflict.assigned_code {
+                    Some(code) => println!(
+                        "row {}: could not keep '{}' ({}), imported as '{}'",
+                        conflict.row, conflict.short_code, conflict.reason, code
+                    ),
+                    None => println!(
+                        "row {}: skipped '{}' ({})",
+                        conflict.row, conflict.short_code, conflict.reason
+                    ),
+                }
+            }
+            for error in &report.errors {
+                println!("row {}: {}", error.row, error.error);
+            }
+            println!(
+                "Imported {} links for '{}' ({} skipped, {} conflicts, {} errors)",
+                report.imported,
+                args.username,
+                report.skipped,
+                report.conflicts.len(),
+                report.errors.len()
+            );
+            if report.utm_presets > 0 {
+                println!("Added {} UTM presets", report.utm_presets);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Import failed: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn parse_err(list: &[&str]) -> String {
+        match parse_import_args(&args(list)) {
+            Ok(_) => panic!("{list:?} should not parse"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn import_args_parse_in_any_order() {
+        let parsed = parse_import_args(&args(&[
+            "--on-conflict",
+            "skip",
+            "links.json",
+            "--user",
+            "alice",
+            "--format",
+            "JSON",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.path, "links.json");
+        assert_eq!(parsed.username, "alice");
+        assert_eq!(parsed.format, Some(ImportFormat::Json));
+        assert_eq!(parsed.on_conflict, OnConflict::Skip);
+
+        let stdin = parse_import_args(&args(&["-", "--user", "bob"])).unwrap();
+        assert_eq!(stdin.path, "-");
+        assert_eq!(stdin.format, None);
+        assert_eq!(stdin.on_conflict, OnConflict::Rename);
+    }
+
+    #[test]
+    fn bad_import_args_are_rejected() {
+        assert_eq!(parse_err(&["links.csv"]), "Missing --user");
+        assert_eq!(parse_err(&["--user", "alice"]), "Missing export file");
+        assert_eq!(parse_err(&["links.csv", "--user"]), "--user needs a value");
+        assert_eq!(
+            parse_err(&["links.csv", "--user", "alice", "--format", "xml"]),
+            "--format must be 'csv', 'json' or 'zip'"
+        );
+        assert_eq!(
+            parse_err(&["links.csv", "--user", "alice", "--on-conflict", "merge"]),
+            "--on-conflict must be 'rename' or 'skip'"
+        );
+        assert_eq!(
+            parse_err(&["links.csv", "--user", "alice", "--dry-run"]),
+            "Unknown option --dry-run"
+        );
+        assert_eq!(
+            parse_err(&["a.csv", "b.csv", "--user", "alice"]),
+            "Unexpected argument b.csv"
+        );
+    }
+
+    #[test]
+    fn run_import_loads_links_into_the_database() {
+        let base = std::env::temp_dir().join(format!(
+            "rus-cli-import-{}-{}",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        std::fs::create_dir_all(&base).unwrap();
+        let db_path = base.join("rus.db");
+        let export_path = base.join("bitly.csv");
+        std::fs::write(
+            &export_path,
+            "Title,Bitlink,Long URL,Created,Clicks\n\
+             Launch,https://bit.ly/cli001,https://example.com/launch,2023-04-01T10:00:00Z,12\n",
+        )
+        .unwrap();
+
+        let mut config = crate::testing::test_config();
+        config.db_path = db_path.to_string_lossy().into_owned();
+        {
+            let state = AppState::new(config.clone()).unwrap();
+            let db = state.db.lock().unwrap();
+            db.execute(
+                "INSERT INTO users (username, password) VALUES ('alice', 'placeholder')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let export = export_path.to_string_lossy().into_owned();
+        assert_eq!(
+            run_import(config.clone(), &args(&[&export, "--user", "nobody"])),
+            1
+        );
+        assert_eq!(run_import(config.clone(), &args(&[&export])), 2);
+        assert_eq!(
+            run_import(config.clone(), &args(&[&export, "--user", "alice"])),
+            0
+        );
+
+        let state = AppState::new(config).unwrap();
+        let db = state.db.lock().unwrap();
+        let (url, clicks): (String, i64) = db
+            .query_row(
+                "SELECT original_url, clicks FROM urls WHERE short_code = 'cli001'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(url, "https://example.com/launch");
+        assert_eq!(clicks, 12);
+        drop(db);
+        std::fs::remove_dir_all(&base).ok();
+    }
+}
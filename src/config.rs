@@ -1,5 +1,7 @@
 use std::env;
 
+use crate::analytics::TrustedProxies;
+
 /// OIDC Relying Party + Resource Server configuration (saas mode).
 #[cfg(feature = "saas")]
 #[derive(Clone, Debug)]
@@ -54,6 +56,13 @@ pub struct Config {
     pub db_path: String,
     pub host: String,
     pub port: u16,
+    /// Optional MaxMind-format `.mmdb` database used to geolocate clicks
+    pub geoip_db_path: Option<String>,
+    /// Redirect status for links that do not set their own (301, 302, 307 or 308)
+    pub default_redirect_status: u16,
+    /// Reverse proxies whose `X-Forwarded-For` / `Forwarded` headers are
+    /// believed when recording the client IP of a click
+    pub trusted_proxies: TrustedProxies,
     #[cfg(feature = "standalone")]
     pub allow_registration: bool,
     /// HMAC secret for the maintenance webhook (saas mode).
@@ -117,6 +126,30 @@ impl Config {
             .and_then(|v| v.parse().ok())
             .unwrap_or(4001);
 
+        let geoip_db_path = env::var("GEOIP_DB_PATH").ok().filter(|v| !v.is_empty());
+
+        let default_redirect_status = env::var("DEFAULT_REDIRECT_STATUS")
+            .ok()
+            .and_then(|v| match v.parse() {
+                Ok(status) if crate::url::validate_redirect_status(status).is_ok() => Some(status),
+                _ => {
+                    tracing::warn!(value = %v, "Invalid DEFAULT_REDIRECT_STATUS, using 302");
+                    None
+                }
+            })
+            .unwrap_or(302);
+
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .ok()
+            .and_then(|v| match TrustedProxies::parse(&v) {
+                Ok(proxies) => Some(proxies),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Invalid TRUSTED_PROXIES, trusting no proxies");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
         #[cfg(feature = "standalone")]
         let allow_registration = env::var("ALLOW_REGISTRATION")
             .ok()
@@ -151,6 +184,9 @@ impl Config {
             db_path,
             host,
             port,
+            geoip_db_path,
+            default_redirect_status,
+            trusted_proxies,
             #[cfg(feature = "standalone")]
             allow_registration,
             #[cfg(feature = "saas")]
@@ -192,6 +228,9 @@ impl Config {
             account_lockout_attempts = self.account_lockout_attempts,
             account_lockout_duration_minutes = self.account_lockout_duration_minutes,
             click_retention_days = self.click_retention_days,
+            geoip_db_path = ?self.geoip_db_path,
+            default_redirect_status = self.default_redirect_status,
+            trusted_proxies = %self.trusted_proxies,
             allow_registration = self.allow_registration,
             "RUS configuration loaded"
         );
@@ -205,6 +244,9 @@ impl Config {
             db_path = %self.db_path,
             max_url_length = self.max_url_length,
             click_retention_days = self.click_retention_days,
+            geoip_db_path = ?self.geoip_db_path,
+            default_redirect_status = self.default_redirect_status,
+            trusted_proxies = %self.trusted_proxies,
             oidc_enabled = self.oidc.enabled(),
             oidc_issuer = %self.oidc.issuer,
             "RUS configuration loaded"
@@ -308,6 +350,7 @@ mod tests {
         assert_eq!(cfg.db_path, ":memory:");
         assert_eq!(cfg.host, "127.0.0.1");
         assert_eq!(cfg.port, 4001);
+        assert!(cfg.geoip_db_path.is_none());
     }
 
     #[cfg(feature = "standalone")]
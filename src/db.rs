@@ -1,4 +1,4 @@
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rusqlite::{params, Connection};
 #[cfg(feature = "saas")]
 use std::sync::atomic::AtomicBool;
@@ -6,6 +6,8 @@ use std::sync::Mutex;
 #[cfg(feature = "saas")]
 use std::sync::RwLock;
 
+use crate::analytics::geo::GeoIp;
+use crate::analytics::live::{self, ClickEvent};
 use crate::config::Config;
 
 /// Application state containing database connection and configuration
@@ -13,6 +15,10 @@ pub struct AppState {
     pub db: Mutex<Connection>,
     pub config: Config,
     pub start_time: std::time::Instant,
+    /// Click geolocation database, when `GEOIP_DB_PATH` points at one
+    pub geoip: Option<GeoIp>,
+    /// Clicks as they are recorded, for live dashboards
+    pub click_events: tokio::sync::broadcast::Sender<ClickEvent>,
     #[cfg(feature = "saas")]
     pub maintenance_mode: AtomicBool,
     #[cfg(feature = "saas")]
@@ -51,7 +57,25 @@ impl AppState {
                 short_code TEXT NOT NULL UNIQUE,
                 name TEXT,
                 clicks INTEGER DEFAULT 0,
+                bot_clicks INTEGER NOT NULL DEFAULT 0,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME,
+                max_clicks INTEGER,
+                password_hash TEXT,
+                active_from DATETIME,
+                active_until DATETIME,
+                public_stats INTEGER NOT NULL DEFAULT 0,
+                ios_url TEXT,
+                android_url TEXT,
+                desktop_url TEXT,
+                sticky_variants INTEGER NOT NULL DEFAULT 0,
+                redirect_status INTEGER,
+                passthrough TEXT,
+                utm_source TEXT,
+                utm_medium TEXT,
+                utm_campaign TEXT,
+                utm_term TEXT,
+                utm_content TEXT,
                 FOREIGN KEY (user_id) REFERENCES users(userID) ON DELETE CASCADE
             );
 
@@ -59,9 +83,104 @@ impl AppState {
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 url_id INTEGER NOT NULL,
                 clicked_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                referrer_host TEXT,
+                browser TEXT,
+                os TEXT,
+                device TEXT,
+                visitor_hash TEXT,
+                country TEXT,
+                region TEXT,
+                city TEXT,
+                is_bot INTEGER NOT NULL DEFAULT 0,
+                geo_rule TEXT,
                 FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
             );
 
+            CREATE TABLE IF NOT EXISTS visitor_salts (
+                day TEXT PRIMARY KEY,
+                salt TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS click_rollups (
+                url_id INTEGER NOT NULL,
+                day TEXT NOT NULL,
+                clicks INTEGER NOT NULL DEFAULT 0,
+                bot_clicks INTEGER NOT NULL DEFAULT 0,
+                visitors INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (url_id, day),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS click_rollup_dimensions (
+                url_id INTEGER NOT NULL,
+                day TEXT NOT NULL,
+                dimension TEXT NOT NULL,
+                value TEXT NOT NULL,
+                clicks INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (url_id, dimension, value, day),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS tags (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users(userID) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS utm_presets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                utm_source TEXT,
+                utm_medium TEXT,
+                utm_campaign TEXT,
+                utm_term TEXT,
+                utm_content TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users(userID) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS url_tags (
+                url_id INTEGER NOT NULL,
+                tag_id INTEGER NOT NULL,
+                PRIMARY KEY (url_id, tag_id),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS geo_rules (
+                url_id INTEGER NOT NULL,
+                country TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                PRIMARY KEY (url_id, country),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS url_variants (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url_id INTEGER NOT NULL,
+                destination TEXT NOT NULL,
+                weight INTEGER NOT NULL DEFAULT 1,
+                clicks INTEGER NOT NULL DEFAULT 0,
+                UNIQUE (url_id, destination),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS url_revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url_id INTEGER NOT NULL,
+                previous_url TEXT NOT NULL,
+                new_url TEXT NOT NULL,
+                changed_by INTEGER,
+                changed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE,
+                FOREIGN KEY (changed_by) REFERENCES users(userID) ON DELETE SET NULL
+            );
+
             CREATE TABLE IF NOT EXISTS refresh_tokens (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 user_id INTEGER NOT NULL,
@@ -95,6 +214,8 @@ impl AppState {
             CREATE INDEX IF NOT EXISTS idx_user_id ON urls(user_id);
             CREATE INDEX IF NOT EXISTS idx_click_history_url_id ON click_history(url_id);
             CREATE INDEX IF NOT EXISTS idx_click_history_clicked_at ON click_history(clicked_at);
+            CREATE INDEX IF NOT EXISTS idx_url_revisions_url_id ON url_revisions(url_id);
+            CREATE INDEX IF NOT EXISTS idx_url_tags_tag_id ON url_tags(tag_id);
             CREATE INDEX IF NOT EXISTS idx_refresh_tokens_token ON refresh_tokens(token);
             CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user_id ON refresh_tokens(user_id);
             CREATE INDEX IF NOT EXISTS idx_login_attempts_username ON login_attempts(username);
@@ -130,7 +251,25 @@ impl AppState {
                 short_code TEXT NOT NULL UNIQUE,
                 name TEXT,
                 clicks INTEGER DEFAULT 0,
+                bot_clicks INTEGER NOT NULL DEFAULT 0,
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME,
+                max_clicks INTEGER,
+                password_hash TEXT,
+                active_from DATETIME,
+                active_until DATETIME,
+                public_stats INTEGER NOT NULL DEFAULT 0,
+                ios_url TEXT,
+                android_url TEXT,
+                desktop_url TEXT,
+                sticky_variants INTEGER NOT NULL DEFAULT 0,
+                redirect_status INTEGER,
+                passthrough TEXT,
+                utm_source TEXT,
+                utm_medium TEXT,
+                utm_campaign TEXT,
+                utm_term TEXT,
+                utm_content TEXT,
                 FOREIGN KEY (user_id) REFERENCES users(userID) ON DELETE CASCADE
             );
 
@@ -138,9 +277,104 @@ impl AppState {
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 url_id INTEGER NOT NULL,
                 clicked_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                referrer_host TEXT,
+                browser TEXT,
+                os TEXT,
+                device TEXT,
+                visitor_hash TEXT,
+                country TEXT,
+                region TEXT,
+                city TEXT,
+                is_bot INTEGER NOT NULL DEFAULT 0,
+                geo_rule TEXT,
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS visitor_salts (
+                day TEXT PRIMARY KEY,
+                salt TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS click_rollups (
+                url_id INTEGER NOT NULL,
+                day TEXT NOT NULL,
+                clicks INTEGER NOT NULL DEFAULT 0,
+                bot_clicks INTEGER NOT NULL DEFAULT 0,
+                visitors INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (url_id, day),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS click_rollup_dimensions (
+                url_id INTEGER NOT NULL,
+                day TEXT NOT NULL,
+                dimension TEXT NOT NULL,
+                value TEXT NOT NULL,
+                clicks INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (url_id, dimension, value, day),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS tags (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users(userID) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS utm_presets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                utm_source TEXT,
+                utm_medium TEXT,
+                utm_campaign TEXT,
+                utm_term TEXT,
+                utm_content TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users(userID) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS url_tags (
+                url_id INTEGER NOT NULL,
+                tag_id INTEGER NOT NULL,
+                PRIMARY KEY (url_id, tag_id),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS geo_rules (
+                url_id INTEGER NOT NULL,
+                country TEXT NOT NULL,
+                destination TEXT NOT NULL,
+                PRIMARY KEY (url_id, country),
                 FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
             );
 
+            CREATE TABLE IF NOT EXISTS url_variants (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url_id INTEGER NOT NULL,
+                destination TEXT NOT NULL,
+                weight INTEGER NOT NULL DEFAULT 1,
+                clicks INTEGER NOT NULL DEFAULT 0,
+                UNIQUE (url_id, destination),
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS url_revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url_id INTEGER NOT NULL,
+                previous_url TEXT NOT NULL,
+                new_url TEXT NOT NULL,
+                changed_by INTEGER,
+                changed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (url_id) REFERENCES urls(id) ON DELETE CASCADE,
+                FOREIGN KEY (changed_by) REFERENCES users(userID) ON DELETE SET NULL
+            );
+
             CREATE TABLE IF NOT EXISTS abuse_reports (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 short_code TEXT NOT NULL,
@@ -177,6 +411,8 @@ impl AppState {
             CREATE INDEX IF NOT EXISTS idx_user_id ON urls(user_id);
             CREATE INDEX IF NOT EXISTS idx_click_history_url_id ON click_history(url_id);
             CREATE INDEX IF NOT EXISTS idx_click_history_clicked_at ON click_history(clicked_at);
+            CREATE INDEX IF NOT EXISTS idx_url_revisions_url_id ON url_revisions(url_id);
+            CREATE INDEX IF NOT EXISTS idx_url_tags_tag_id ON url_tags(tag_id);
             CREATE INDEX IF NOT EXISTS idx_abuse_reports_short_code ON abuse_reports(short_code);
             CREATE INDEX IF NOT EXISTS idx_abuse_reports_status ON abuse_reports(status);
             CREATE INDEX IF NOT EXISTS idx_abuse_reports_created_at ON abuse_reports(created_at);
@@ -186,6 +422,85 @@ impl AppState {
             ",
         )?;
 
+        // Best-effort migration to add link-setting and click-analytics
+        // columns to pre-existing tables (silently ignore "duplicate column
+        // name" errors).
+        for stmt in [
+            "ALTER TABLE urls ADD COLUMN expires_at DATETIME",
+            "ALTER TABLE urls ADD COLUMN max_clicks INTEGER",
+            "ALTER TABLE urls ADD COLUMN password_hash TEXT",
+            "ALTER TABLE urls ADD COLUMN active_from DATETIME",
+            "ALTER TABLE urls ADD COLUMN active_until DATETIME",
+            "ALTER TABLE click_history ADD COLUMN referrer_host TEXT",
+            "ALTER TABLE click_history ADD COLUMN browser TEXT",
+            "ALTER TABLE click_history ADD COLUMN os TEXT",
+            "ALTER TABLE click_history ADD COLUMN device TEXT",
+            "ALTER TABLE click_history ADD COLUMN visitor_hash TEXT",
+            "ALTER TABLE click_history ADD COLUMN country TEXT",
+            "ALTER TABLE click_history ADD COLUMN region TEXT",
+            "ALTER TABLE click_history ADD COLUMN city TEXT",
+            "ALTER TABLE click_history ADD COLUMN is_bot INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE click_history ADD COLUMN geo_rule TEXT",
+            "ALTER TABLE urls ADD COLUMN bot_clicks INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE urls ADD COLUMN public_stats INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE urls ADD COLUMN ios_url TEXT",
+            "ALTER TABLE urls ADD COLUMN android_url TEXT",
+            "ALTER TABLE urls ADD COLUMN desktop_url TEXT",
+            "ALTER TABLE urls ADD COLUMN sticky_variants INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE urls ADD COLUMN redirect_status INTEGER",
+            "ALTER TABLE urls ADD COLUMN passthrough TEXT",
+            "ALTER TABLE urls ADD COLUMN utm_source TEXT",
+            "ALTER TABLE urls ADD COLUMN utm_medium TEXT",
+            "ALTER TABLE urls ADD COLUMN utm_campaign TEXT",
+            "ALTER TABLE urls ADD COLUMN utm_term TEXT",
+            "ALTER TABLE urls ADD COLUMN utm_content TEXT",
+            "ALTER TABLE click_rollups ADD COLUMN bot_clicks INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE click_rollups ADD COLUMN visitors INTEGER NOT NULL DEFAULT 0",
+        ] {
+            if let Err(e) = conn.execute(stmt, []) {
+                let msg = e.to_string();
+                if !msg.contains("duplicate column name") {
+                    tracing::debug!(stmt = %stmt, error = %msg, "column migration skipped");
+                }
+            }
+        }
+
+        // Full-text index over link destinations and names, kept in sync by
+        // triggers. A database created before the index existed is backfilled
+        // once when the table is first created.
+        let has_fts: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'urls_fts'",
+            [],
+            |row| row.get(0),
+        )?;
+        conn.execute_batch(
+            "
+            CREATE VIRTUAL TABLE IF NOT EXISTS urls_fts USING fts5(
+                original_url, name, content='urls', content_rowid='id'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS urls_fts_insert AFTER INSERT ON urls BEGIN
+                INSERT INTO urls_fts(rowid, original_url, name)
+                VALUES (new.id, new.original_url, new.name);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS urls_fts_delete AFTER DELETE ON urls BEGIN
+                INSERT INTO urls_fts(urls_fts, rowid, original_url, name)
+                VALUES ('delete', old.id, old.original_url, old.name);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS urls_fts_update AFTER UPDATE OF original_url, name ON urls BEGIN
+                INSERT INTO urls_fts(urls_fts, rowid, original_url, name)
+                VALUES ('delete', old.id, old.original_url, old.name);
+                INSERT INTO urls_fts(rowid, original_url, name)
+                VALUES (new.id, new.original_url, new.name);
+            END;
+            ",
+        )?;
+        if !has_fts {
+            conn.execute("INSERT INTO urls_fts(urls_fts) VALUES ('rebuild')", [])?;
+        }
+
         // SaaS mode: best-effort migration to add SSO columns to a pre-existing
         // users table (silently ignore "duplicate column name" errors).
         #[cfg(feature = "saas")]
@@ -211,10 +526,26 @@ impl AppState {
             )?;
         }
 
+        let geoip = config
+            .geoip_db_path
+            .as_deref()
+            .and_then(|path| match GeoIp::open(path) {
+                Ok(geoip) => {
+                    tracing::info!(path = %path, "GeoIP database loaded");
+                    Some(geoip)
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path, error = %e, "Failed to load GeoIP database, clicks will not be geolocated");
+                    None
+                }
+            });
+
         Ok(AppState {
             db: Mutex::new(conn),
             config,
             start_time: std::time::Instant::now(),
+            geoip,
+            click_events: live::channel(),
             #[cfg(feature = "saas")]
             maintenance_mode: AtomicBool::new(false),
             #[cfg(feature = "saas")]
@@ -223,15 +554,30 @@ impl AppState {
     }
 }
 
-/// Cleanup old click history records
+/// Format a timestamp the way every timestamp column stores it, so it
+/// compares correctly against them in SQL
+pub(crate) fn to_db_timestamp(ts: DateTime<Utc>) -> String {
+    ts.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Cleanup old click history records. They are first added to the daily
+/// rollups, in the same transaction, so long-range stats keep counting them.
 pub fn cleanup_old_clicks(db: &Connection, retention_days: i64) {
+    // Rollups count each day's visitors once, so only purge whole UTC days
     let cutoff = Utc::now() - Duration::days(retention_days);
-    let cutoff_str = cutoff.format("%Y-%m-%d %H:%M:%S").to_string();
+    let cutoff_day = cutoff.format("%Y-%m-%d").to_string();
 
-    let _ = db.execute(
-        "DELETE FROM click_history WHERE clicked_at < ?1",
-        params![cutoff_str],
-    );
+    let result = db.unchecked_transaction().and_then(|tx| {
+        crate::analytics::rollup::roll_up_clicks(&tx, &cutoff_day)?;
+        tx.execute(
+            "DELETE FROM click_history WHERE clicked_at < ?1",
+            params![cutoff_day],
+        )?;
+        tx.commit()
+    });
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "Failed to roll up and purge old clicks");
+    }
 }
 
 #[cfg(test)]
@@ -258,6 +604,12 @@ mod tests {
         assert!(tables.contains(&"users".to_string()));
         assert!(tables.contains(&"urls".to_string()));
         assert!(tables.contains(&"click_history".to_string()));
+        assert!(tables.contains(&"url_revisions".to_string()));
+        assert!(tables.contains(&"tags".to_string()));
+        assert!(tables.contains(&"url_tags".to_string()));
+        assert!(tables.contains(&"visitor_salts".to_string()));
+        assert!(tables.contains(&"click_rollups".to_string()));
+        assert!(tables.contains(&"click_rollup_dimensions".to_string()));
         assert!(tables.contains(&"abuse_reports".to_string()));
     }
 
@@ -302,6 +654,8 @@ mod tests {
         assert!(indexes.contains(&"idx_user_id".to_string()));
         assert!(indexes.contains(&"idx_click_history_url_id".to_string()));
         assert!(indexes.contains(&"idx_click_history_clicked_at".to_string()));
+        assert!(indexes.contains(&"idx_url_revisions_url_id".to_string()));
+        assert!(indexes.contains(&"idx_url_tags_tag_id".to_string()));
     }
 
     #[test]
@@ -316,6 +670,96 @@ mod tests {
         assert_eq!(fk, 1);
     }
 
+    #[test]
+    fn appstate_migrates_legacy_urls_table() {
+        let path = std::env::temp_dir().join(format!(
+            "rus-migrate-{}-{}.db",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE urls (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    user_id INTEGER NOT NULL,
+                    original_url TEXT NOT NULL,
+                    short_code TEXT NOT NULL UNIQUE,
+                    name TEXT,
+                    clicks INTEGER DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+                CREATE TABLE click_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    url_id INTEGER NOT NULL,
+                    clicked_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+                INSERT INTO urls (user_id, original_url, short_code, name)
+                VALUES (1, 'https://legacy.example.com/docs', 'leg001', 'Docs');",
+            )
+            .unwrap();
+        }
+
+        let mut cfg = crate::testing::test_config();
+        cfg.db_path = path.to_string_lossy().into_owned();
+        let state = AppState::new(cfg).unwrap();
+        let (columns, click_columns, indexed): (Vec<String>, Vec<String>, i64) = {
+            let db = state.db.lock().unwrap();
+            let table_columns = |table: &str| -> Vec<String> {
+                let mut stmt = db
+                    .prepare("SELECT name FROM pragma_table_info(?1)")
+                    .unwrap();
+                stmt.query_map([table], |row| row.get(0))
+                    .unwrap()
+                    .filter_map(|r| r.ok())
+                    .collect()
+            };
+            let columns = table_columns("urls");
+            let click_columns = table_columns("click_history");
+            let indexed = db
+                .query_row(
+                    "SELECT COUNT(*) FROM urls_fts WHERE urls_fts MATCH 'legacy'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            (columns, click_columns, indexed)
+        };
+        drop(state);
+        std::fs::remove_file(&path).ok();
+
+        assert!(columns.contains(&"expires_at".to_string()));
+        assert!(columns.contains(&"max_clicks".to_string()));
+        assert!(columns.contains(&"password_hash".to_string()));
+        assert!(columns.contains(&"active_from".to_string()));
+        assert!(columns.contains(&"active_until".to_string()));
+        assert!(columns.contains(&"bot_clicks".to_string()));
+        assert!(columns.contains(&"public_stats".to_string()));
+        assert!(columns.contains(&"ios_url".to_string()));
+        assert!(columns.contains(&"android_url".to_string()));
+        assert!(columns.contains(&"desktop_url".to_string()));
+        assert!(columns.contains(&"sticky_variants".to_string()));
+        assert!(columns.contains(&"redirect_status".to_string()));
+        assert!(columns.contains(&"passthrough".to_string()));
+        assert!(columns.contains(&"utm_campaign".to_string()));
+        for column in [
+            "referrer_host",
+            "browser",
+            "os",
+            "device",
+            "visitor_hash",
+            "country",
+            "region",
+            "city",
+            "is_bot",
+            "geo_rule",
+        ] {
+            assert!(click_columns.contains(&column.to_string()), "{column}");
+        }
+        // Rows that predate the search index are backfilled into it
+        assert_eq!(indexed, 1);
+    }
+
     #[test]
     fn appstate_new_is_idempotent() {
         // Calling new twice with :memory: should both succeed (CREATE IF NOT EXISTS)
@@ -325,6 +769,18 @@ mod tests {
         let _s2 = AppState::new(cfg2).unwrap();
     }
 
+    #[test]
+    fn appstate_loads_geoip_database_when_configured() {
+        let mut cfg = crate::testing::test_config();
+        cfg.geoip_db_path = Some(crate::testing::GEOIP_FIXTURE.to_string());
+        assert!(AppState::new(cfg).unwrap().geoip.is_some());
+
+        // A missing database is logged and ignored rather than fatal
+        let mut cfg = crate::testing::test_config();
+        cfg.geoip_db_path = Some("/nonexistent/geoip.mmdb".to_string());
+        assert!(AppState::new(cfg).unwrap().geoip.is_none());
+    }
+
     #[test]
     fn cleanup_old_clicks_removes_expired_entries() {
         let state = crate::testing::make_test_state();
@@ -374,6 +830,15 @@ mod tests {
             .query_row("SELECT COUNT(*) FROM click_history", [], |r| r.get(0))
             .unwrap();
         assert_eq!(count_after, 1);
+
+        // The purged click lives on in the daily rollup
+        let (day, rolled_up): (String, i64) = db
+            .query_row("SELECT day, clicks FROM click_rollups", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(day, old_time[..10]);
+        assert_eq!(rolled_up, 1);
     }
 
     #[test]
@@ -410,6 +875,69 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[test]
+    fn cleanup_old_clicks_only_purges_whole_days() {
+        let state = crate::testing::make_test_state();
+        let db = state.db.lock().unwrap();
+
+        db.execute(
+            "INSERT INTO users (username, password) VALUES ('testuser', 'pass')",
+            [],
+        )
+        .unwrap();
+        let user_id = db.last_insert_rowid();
+        db.execute(
+            "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, 'https://example.com', 'abc123')",
+            params![user_id],
+        )
+        .unwrap();
+        let url_id = db.last_insert_rowid();
+
+        // Visitor aaaa clicks the day before the cutoff day, and on it both
+        // before and after the cutoff time
+        let cutoff_day = (Utc::now() - Duration::days(30)).date_naive();
+        let day_before = cutoff_day - Duration::days(1);
+        for (clicked_at, visitor) in [
+            (format!("{day_before} 12:00:00"), "aaaa"),
+            (format!("{cutoff_day} 00:00:00"), "aaaa"),
+            (format!("{cutoff_day} 00:00:01"), "bbbb"),
+            (format!("{cutoff_day} 23:59:59"), "aaaa"),
+        ] {
+            db.execute(
+                "INSERT INTO click_history (url_id, clicked_at, visitor_hash) VALUES (?1, ?2, ?3)",
+                params![url_id, clicked_at, visitor],
+            )
+            .unwrap();
+        }
+
+        let rollups = |db: &Connection| -> Vec<(String, i64, i64)> {
+            let mut stmt = db
+                .prepare("SELECT day, clicks, visitors FROM click_rollups ORDER BY day")
+                .unwrap();
+            stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect()
+        };
+
+        // The cutoff day stays raw until the whole of it is past retention
+        cleanup_old_clicks(&db, 30);
+        let remaining: i64 = db
+            .query_row("SELECT COUNT(*) FROM click_history", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(remaining, 3);
+        assert_eq!(rollups(&db), [(day_before.to_string(), 1, 1)]);
+
+        cleanup_old_clicks(&db, 29);
+        assert_eq!(
+            rollups(&db),
+            [
+                (day_before.to_string(), 1, 1),
+                (cutoff_day.to_string(), 3, 2),
+            ]
+        );
+    }
+
     #[test]
     fn cleanup_old_clicks_noop_on_empty_table() {
         let state = crate::testing::make_test_state();
@@ -0,0 +1,1018 @@
+//! Exporting all of a user's links and click history.
+//!
+//! [`ExportSource`] reads one user's links and clicks a page at a time,
+//! holding the database lock only while a page is read, and writes them as
+//! CSV, JSON Lines or a zip bundle holding all of them. Nothing is kept in
+//! memory beyond the current page. The links CSV uses column names
+//! [`crate::import`] understands, so an export can be imported into another
+//! instance with every link setting intact, apart from:
+//!
+//! - passwords, which are never exported. A password-protected link is
+//!   refused by the importer rather than brought back without one.
+//! - `bot_clicks` and the per-click history, which are for reading only.
+//!   The import keeps the `clicks` total.
+
+use std::collections::HashMap;
+use std::io::{self, Seek, Write};
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+
+use crate::handlers::utm_presets::{utm_preset_from_row, UTM_PRESET_COLUMNS};
+use crate::models::{ClickHistoryEntry, ExportedVariant, GeoRule, UtmPreset};
+use crate::url::{PlatformUrls, UtmParams};
+
+/// Links (or, for `clicks.csv`, clicks) read per database query
+pub const EXPORT_PAGE_SIZE: usize = 500;
+
+/// Export format requested by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+    Zip,
+}
+
+impl ExportFormat {
+    /// Parse a `format` parameter (`csv`, `jsonl` or `zip`)
+    pub fn from_param(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+}
+
+/// One link with everything stored about it
+#[derive(Debug, Serialize)]
+pub struct ExportedLink {
+    pub short_code: String,
+    pub short_url: String,
+    pub original_url: String,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub clicks: u64,
+    /// Not imported
+    pub bot_clicks: u64,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub max_clicks: Option<u64>,
+    pub active_from: Option<String>,
+    pub active_until: Option<String>,
+    /// The password itself is not exported, so such links cannot be imported
+    pub password_protected: bool,
+    pub public_stats: bool,
+    #[serde(flatten)]
+    pub platform_urls: PlatformUrls,
+    /// Country-specific destinations, by country code
+    pub geo_rules: Vec<GeoRule>,
+    /// A/B test destinations with the clicks each has received
+    pub variants: Vec<ExportedVariant>,
+    pub sticky_variants: bool,
+    /// `None` when the link uses the server's default
+    pub redirect_status: Option<u16>,
+    /// `keep` or `replace` when extra path and parameters are passed on
+    pub passthrough: Option<String>,
+    /// Campaign parameters, already part of `original_url`
+    #[serde(flatten)]
+    pub utm: UtmParams,
+    /// Recorded clicks still within the retention period, oldest first. Not
+    /// imported.
+    pub click_history: Vec<ClickHistoryEntry>,
+}
+
+/// One recorded click and the link it belongs to
+#[derive(Debug)]
+pub struct ExportedClick {
+    pub short_code: String,
+    pub click: ClickHistoryEntry,
+}
+
+/// Position after the last row of a page: its timestamp and id
+type Cursor = (String, i64);
+
+/// Reads the links and clicks of one user for export, a page at a time
+pub struct ExportSource<'a> {
+    db: &'a Mutex<Connection>,
+    user_id: i64,
+    host_url: &'a str,
+    page_size: usize,
+}
+
+impl<'a> ExportSource<'a> {
+    pub fn new(db: &'a Mutex<Connection>, user_id: i64, host_url: &'a str) -> Self {
+        Self {
+            db,
+            user_id,
+            host_url,
+            page_size: EXPORT_PAGE_SIZE,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'a, Connection> {
+        self.db.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The account's saved UTM presets, by name
+    fn utm_presets(&self) -> rusqlite::Result<Vec<UtmPreset>> {
+        let db = self.lock();
+        let mut stmt = db.prepare(&format!(
+            "SELECT {UTM_PRESET_COLUMNS} FROM utm_presets WHERE user_id = ?1 ORDER BY name"
+        ))?;
+        let presets = stmt
+            .query_map(params![self.user_id], utm_preset_from_row)?
+            .collect();
+        presets
+    }
+
+    fn username(&self) -> rusqlite::Result<String> {
+        self.lock().query_row(
+            "SELECT username FROM users WHERE userID = ?1",
+            params![self.user_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Links after `after`, oldest first, with their click history when
+    /// `with_history` is set
+    fn link_page(
+        &self,
+        after: &Cursor,
+        with_history: bool,
+    ) -> rusqlite::Result<(Vec<ExportedLink>, Option<Cursor>)> {
+        const PAGE: &str = "user_id = ?1 AND (created_at, id) > (?2, ?3)
+                            ORDER BY created_at, id LIMIT ?4";
+        let db = self.lock();
+        let page_params = params![self.user_id, after.0, after.1, self.page_size as i64];
+
+        let mut history: HashMap<i64, Vec<ClickHistoryEntry>> = HashMap::new();
+        if with_history {
+            let mut stmt = db.prepare(&format!(
+                "SELECT url_id, clicked_at, referrer_host, browser, os, device,
+                        country, region, city, is_bot, geo_rule
+                 FROM click_history
+                 WHERE url_id IN (SELECT id FROM urls WHERE {PAGE})
+                 ORDER BY clicked_at, id"
+            ))?;
+            let rows = stmt.query_map(page_params, |row| {
+                Ok((row.get::<_, i64>(0)?, click_from_row(row, 1)?))
+            })?;
+            for row in rows {
+                let (url_id, click) = row?;
+                history.entry(url_id).or_default().push(click);
+            }
+        }
+
+        let mut geo_rules: HashMap<i64, Vec<GeoRule>> = HashMap::new();
+        let mut stmt = db.prepare(&format!(
+            "SELECT url_id, country, destination FROM geo_rules
+             WHERE url_id IN (SELECT id FROM urls WHERE {PAGE})
+             ORDER BY country"
+        ))?;
+        let rows = stmt.query_map(page_params, |row| {
+            let rule = GeoRule {
+                country: row.get(1)?,
+                url: row.get(2)?,
+            };
+            Ok((row.get::<_, i64>(0)?, rule))
+        })?;
+        for row in rows {
+            let (url_id, rule) = row?;
+            geo_rules.entry(url_id).or_default().push(rule);
+        }
+
+        let mut variants: HashMap<i64, Vec<ExportedVariant>> = HashMap::new();
+        let mut stmt = db.prepare(&format!(
+            "SELECT url_id, destination, weight, clicks FROM url_variants
+             WHERE url_id IN (SELECT id FROM urls WHERE {PAGE})
+             ORDER BY id"
+        ))?;
+        let rows = stmt.query_map(page_params, |row| {
+            let variant = ExportedVariant {
+                url: row.get(1)?,
+                weight: row.get(2)?,
+                clicks: row.get(3)?,
+            };
+            Ok((row.get::<_, i64>(0)?, variant))
+        })?;
+        for row in rows {
+            let (url_id, variant) = row?;
+            variants.entry(url_id).or_default().push(variant);
+        }
+
+        let mut stmt = db.prepare(&format!(
+            "SELECT id, short_code, original_url, name, clicks, created_at, expires_at, max_clicks,
+                    active_from, active_until, password_hash IS NOT NULL,
+                    (SELECT group_concat(t.name, char(10)) FROM
+                        (SELECT t.name FROM url_tags ut JOIN tags t ON t.id = ut.tag_id
+                         WHERE ut.url_id = urls.id ORDER BY t.name) t),
+                    bot_clicks, public_stats, ios_url, android_url, desktop_url,
+                    sticky_variants, redirect_status, passthrough,
+                    utm_source, utm_medium, utm_campaign, utm_term, utm_content
+             FROM urls WHERE {PAGE}"
+        ))?;
+        let mut last = None;
+        let links = stmt
+            .query_map(page_params, |row| {
+                let id: i64 = row.get(0)?;
+                let short_code: String = row.get(1)?;
+                let tags: Option<String> = row.get(11)?;
+                Ok((
+                    id,
+                    ExportedLink {
+                        short_url: format!("{}/{}", self.host_url, short_code),
+                        short_code,
+                        original_url: row.get(2)?,
+                        name: row.get(3)?,
+                        tags: tags
+                            .map(|t| t.split('\n').map(str::to_string).collect())
+                            .unwrap_or_default(),
+                        clicks: row.get(4)?,
+                        bot_clicks: row.get(12)?,
+                        created_at: row.get(5)?,
+                        expires_at: row.get(6)?,
+                        max_clicks: row.get(7)?,
+                        active_from: row.get(8)?,
+                        active_until: row.get(9)?,
+                        password_protected: row.get(10)?,
+                        public_stats: row.get(13)?,
+                        platform_urls: PlatformUrls {
+                            ios_url: row.get(14)?,
+                            android_url: row.get(15)?,
+                            desktop_url: row.get(16)?,
+                        },
+                        geo_rules: geo_rules.remove(&id).unwrap_or_default(),
+                        variants: variants.remove(&id).unwrap_or_default(),
+                        sticky_variants: row.get(17)?,
+                        redirect_status: row.get(18)?,
+                        passthrough: row.get(19)?,
+                        utm: UtmParams {
+                            utm_source: row.get(20)?,
+                            utm_medium: row.get(21)?,
+                            utm_campaign: row.get(22)?,
+                            utm_term: row.get(23)?,
+                            utm_content: row.get(24)?,
+                        },
+                        click_history: history.remove(&id).unwrap_or_default(),
+                    },
+                ))
+            })?
+            .map(|row| {
+                row.map(|(id, link)| {
+                    last = Some((link.created_at.clone(), id));
+                    link
+                })
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((links, last))
+    }
+
+    /// Recorded clicks after `after`, oldest first
+    fn click_page(&self, after: &Cursor) -> rusqlite::Result<(Vec<ExportedClick>, Option<Cursor>)> {
+        let db = self.lock();
+        let mut stmt = db.prepare(
+            "SELECT ch.id, u.short_code, ch.clicked_at, ch.referrer_host, ch.browser, ch.os,
+                    ch.device, ch.country, ch.region, ch.city, ch.is_bot, ch.geo_rule
+             FROM click_history ch
+             JOIN urls u ON u.id = ch.url_id
+             WHERE u.user_id = ?1 AND (ch.clicked_at, ch.id) > (?2, ?3)
+             ORDER BY ch.clicked_at, ch.id
+             LIMIT ?4",
+        )?;
+        let mut last = None;
+        let clicks = stmt
+            .query_map(
+                params![self.user_id, after.0, after.1, self.page_size as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        ExportedClick {
+                            short_code: row.get(1)?,
+                            click: click_from_row(row, 2)?,
+                        },
+                    ))
+                },
+            )?
+            .map(|row| {
+                row.map(|(id, click)| {
+                    last = Some((click.click.clicked_at.clone(), id));
+                    click
+                })
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((clicks, last))
+    }
+
+    /// Call `write` with each page of links; returns how many there were
+    fn each_link_page(
+        &self,
+        with_history: bool,
+        mut write: impl FnMut(&[ExportedLink]) -> io::Result<()>,
+    ) -> io::Result<usize> {
+        let mut after = Cursor::default();
+        let mut total = 0;
+        loop {
+            let (links, last) = self
+                .link_page(&after, with_history)
+                .map_err(io::Error::other)?;
+            write(&links)?;
+            total += links.len();
+            match last {
+                Some(last) if links.len() == self.page_size => after = last,
+                _ => return Ok(total),
+            }
+        }
+    }
+
+    /// Write one CSV row per link (tags separated by `;`); returns the
+    /// number of links
+    pub fn links_csv<W: Write>(&self, out: W) -> io::Result<usize> {
+        let mut writer = LinksCsv::new(out)?;
+        let total = self.each_link_page(false, |links| writer.write(links))?;
+        writer.finish()?;
+        Ok(total)
+    }
+
+    /// Write one CSV row per recorded click; returns the number of clicks
+    pub fn clicks_csv<W: Write>(&self, out: W) -> io::Result<usize> {
+        let mut writer = ClicksCsv::new(out)?;
+        let mut after = Cursor::default();
+        let mut total = 0;
+        loop {
+            let (clicks, last) = self.click_page(&after).map_err(io::Error::other)?;
+            writer.write(&clicks)?;
+            total += clicks.len();
+            match last {
+                Some(last) if clicks.len() == self.page_size => after = last,
+                _ => break,
+            }
+        }
+        writer.finish()?;
+        Ok(total)
+    }
+
+    /// Write one JSON object per line per link, click history included;
+    /// returns the number of links
+    pub fn links_jsonl<W: Write>(&self, mut out: W) -> io::Result<usize> {
+        let total = self.each_link_page(true, |links| write_links_jsonl(&mut out, links))?;
+        out.flush()?;
+        Ok(total)
+    }
+
+    /// Write a zip holding `links.csv`, `clicks.csv`, `links.jsonl` and an
+    /// `account.json` summary; returns the number of links
+    pub fn zip_bundle<W: Write + Seek>(&self, out: W, exported_at: &str) -> io::Result<usize> {
+        let mut zip = zip::ZipWriter::new(out);
+        let options = SimpleFileOptions::default();
+
+        zip.start_file("links.csv", options)?;
+        let links = self.links_csv(&mut zip)?;
+        zip.start_file("clicks.csv", options)?;
+        let clicks = self.clicks_csv(&mut zip)?;
+        zip.start_file("links.jsonl", options)?;
+        self.links_jsonl(&mut zip)?;
+        let presets = self.utm_presets().map_err(io::Error::other)?;
+        zip.start_file("utm_presets.json", options)?;
+        serde_json::to_writer_pretty(&mut zip, &presets)?;
+
+        let account = serde_json::json!({
+            "username": self.username().map_err(io::Error::other)?,
+            "exported_at": exported_at,
+            "links": links,
+            "recorded_clicks": clicks,
+            "utm_presets": presets.len(),
+        });
+        zip.start_file("account.json", options)?;
+        serde_json::to_writer_pretty(&mut zip, &account)?;
+
+        zip.finish()?.flush()?;
+        Ok(links)
+    }
+}
+
+/// Read the `click_history` columns selected from `first` on
+fn click_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<ClickHistoryEntry> {
+    Ok(ClickHistoryEntry {
+        clicked_at: row.get(first)?,
+        referrer_host: row.get(first + 1)?,
+        browser: row.get(first + 2)?,
+        os: row.get(first + 3)?,
+        device: row.get(first + 4)?,
+        country: row.get(first + 5)?,
+        region: row.get(first + 6)?,
+        city: row.get(first + 7)?,
+        bot: row.get(first + 8)?,
+        geo_rule: row.get(first + 9)?,
+    })
+}
+
+/// A list as a JSON array in one CSV cell; empty when there is nothing in it
+fn json_list<T: Serialize>(items: &[T]) -> io::Result<String> {
+    if items.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(serde_json::to_string(items)?)
+}
+
+fn flag(value: bool) -> &'static str {
+    if value {
+        "true"
+    } else {
+        "false"
+    }
+}
+
+/// Links as CSV rows, written a page at a time
+pub struct LinksCsv<W: Write>(csv::Writer<W>);
+
+impl<W: Write> LinksCsv<W> {
+    /// Start the CSV with its header row
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record([
+            "short_code",
+            "short_url",
+            "original_url",
+            "name",
+            "tags",
+            "clicks",
+            "bot_clicks",
+            "created_at",
+            "expires_at",
+            "max_clicks",
+            "active_from",
+            "active_until",
+            "password_protected",
+            "public_stats",
+            "ios_url",
+            "android_url",
+            "desktop_url",
+            "geo_rules",
+            "variants",
+            "sticky_variants",
+            "redirect_status",
+            "passthrough",
+            "utm_source",
+            "utm_medium",
+            "utm_campaign",
+            "utm_term",
+            "utm_content",
+        ])?;
+        Ok(Self(writer))
+    }
+
+    pub fn write(&mut self, links: &[ExportedLink]) -> io::Result<()> {
+        for link in links {
+            let geo_rules = json_list(&link.geo_rules)?;
+            let variants = json_list(&link.variants)?;
+            self.0.write_record([
+                link.short_code.as_str(),
+                &link.short_url,
+                &link.original_url,
+                link.name.as_deref().unwrap_or_default(),
+                &link.tags.join(";"),
+                &link.clicks.to_string(),
+                &link.bot_clicks.to_string(),
+                &link.created_at,
+                link.expires_at.as_deref().unwrap_or_default(),
+                &link.max_clicks.map(|m| m.to_string()).unwrap_or_default(),
+                link.active_from.as_deref().unwrap_or_default(),
+                link.active_until.as_deref().unwrap_or_default(),
+                flag(link.password_protected),
+                flag(link.public_stats),
+                link.platform_urls.ios_url.as_deref().unwrap_or_default(),
+                link.platform_urls
+                    .android_url
+                    .as_deref()
+                    .unwrap_or_default(),
+                link.platform_urls
+                    .desktop_url
+                    .as_deref()
+                    .unwrap_or_default(),
+                &geo_rules,
+                &variants,
+                flag(link.sticky_variants),
+                &link
+                    .redirect_status
+                    .map(|s| s.to_string())
+                    .unwrap_or_default(),
+                link.passthrough.as_deref().unwrap_or_default(),
+                link.utm.utm_source.as_deref().unwrap_or_default(),
+                link.utm.utm_medium.as_deref().unwrap_or_default(),
+                link.utm.utm_campaign.as_deref().unwrap_or_default(),
+                link.utm.utm_term.as_deref().unwrap_or_default(),
+                link.utm.utm_content.as_deref().unwrap_or_default(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Flush the remaining rows and hand back the writer
+    pub fn finish(self) -> io::Result<W> {
+        self.0.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+/// Recorded clicks as CSV rows, written a page at a time
+pub struct ClicksCsv<W: Write>(csv::Writer<W>);
+
+impl<W: Write> ClicksCsv<W> {
+    /// Start the CSV with its header row
+    pub fn new(out: W) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record([
+            "short_code",
+            "clicked_at",
+            "referrer_host",
+            "browser",
+            "os",
+            "device",
+            "country",
+            "region",
+            "city",
+            "bot",
+            "geo_rule",
+        ])?;
+        Ok(Self(writer))
+    }
+
+    pub fn write(&mut self, clicks: &[ExportedClick]) -> io::Result<()> {
+        for ExportedClick { short_code, click } in clicks {
+            self.0.write_record([
+                short_code.as_str(),
+                &click.clicked_at,
+                click.referrer_host.as_deref().unwrap_or_default(),
+                click.browser.as_deref().unwrap_or_default(),
+                click.os.as_deref().unwrap_or_default(),
+                click.device.as_deref().unwrap_or_default(),
+                click.country.as_deref().unwrap_or_default(),
+                click.region.as_deref().unwrap_or_default(),
+                click.city.as_deref().unwrap_or_default(),
+                flag(click.bot),
+                click.geo_rule.as_deref().unwrap_or_default(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Flush the remaining rows and hand back the writer
+    pub fn finish(self) -> io::Result<W> {
+        self.0.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+/// One JSON object per line per link
+pub fn write_links_jsonl<W: Write>(mut out: W, links: &[ExportedLink]) -> io::Result<()> {
+    for link in links {
+        serde_json::to_writer(&mut out, link)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{import_links, parse_import, parse_utm_presets, ImportFormat, OnConflict};
+
+    fn link(code: &str, tags: &[&str], history: &[&str]) -> ExportedLink {
+        ExportedLink {
+            short_code: code.to_string(),
+            short_url: format!("http://localhost:4001/{code}"),
+            original_url: format!("https://example.com/{code}"),
+            name: Some(format!("Link {code}")),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            clicks: history.len() as u64 + 10,
+            bot_clicks: 0,
+            created_at: "2024-02-01 09:00:00".to_string(),
+            expires_at: None,
+            max_clicks: None,
+            active_from: None,
+            active_until: None,
+            password_protected: false,
+            public_stats: false,
+            platform_urls: PlatformUrls::default(),
+            geo_rules: Vec::new(),
+            variants: Vec::new(),
+            sticky_variants: false,
+            redirect_status: None,
+            passthrough: None,
+            utm: UtmParams::default(),
+            click_history: history.iter().map(|h| click(h)).collect(),
+        }
+    }
+
+    fn click(clicked_at: &str) -> ClickHistoryEntry {
+        ClickHistoryEntry {
+            clicked_at: clicked_at.to_string(),
+            referrer_host: Some("news.ycombinator.com".to_string()),
+            browser: None,
+            os: None,
+            device: Some("desktop".to_string()),
+            country: Some("NL".to_string()),
+            region: None,
+            city: None,
+            bot: false,
+            geo_rule: None,
+        }
+    }
+
+    fn links_csv(links: &[ExportedLink]) -> Vec<u8> {
+        let mut writer = LinksCsv::new(Vec::new()).unwrap();
+        writer.write(links).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn links_csv_can_be_imported_again() {
+        let links = vec![link("abc123", &["clients/acme", "launch"], &[])];
+        let csv = links_csv(&links);
+        let text = String::from_utf8(csv.clone()).unwrap();
+        assert!(text.contains("clients/acme;launch"));
+
+        let records = parse_import(&csv, ImportFormat::Csv).unwrap();
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(record.short_code.as_deref(), Some("abc123"));
+        assert_eq!(record.original_url, "https://example.com/abc123");
+        assert_eq!(record.name.as_deref(), Some("Link abc123"));
+        assert_eq!(record.clicks, 10);
+        assert_eq!(record.created_at.as_deref(), Some("2024-02-01 09:00:00"));
+    }
+
+    #[test]
+    fn clicks_csv_has_a_row_per_click() {
+        let clicks: Vec<_> = [
+            ("aaa111", "2024-03-01 10:00:00"),
+            ("aaa111", "2024-03-02 10:00:00"),
+            ("bbb222", "2024-03-03 10:00:00"),
+        ]
+        .into_iter()
+        .map(|(code, at)| ExportedClick {
+            short_code: code.to_string(),
+            click: click(at),
+        })
+        .collect();
+        let mut writer = ClicksCsv::new(Vec::new()).unwrap();
+        writer.write(&clicks[..2]).unwrap();
+        writer.write(&clicks[2..]).unwrap();
+        let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(text.lines().count(), 4);
+        assert!(
+            text.contains("bbb222,2024-03-03 10:00:00,news.ycombinator.com,,,desktop,NL,,,false,")
+        );
+    }
+
+    #[test]
+    fn jsonl_has_one_object_per_link() {
+        let links = vec![
+            link("aaa111", &["x1"], &["2024-03-01 10:00:00"]),
+            link("bbb222", &[], &[]),
+        ];
+        let mut out = Vec::new();
+        write_links_jsonl(&mut out, &links).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["tags"][0], "x1");
+        assert_eq!(
+            lines[0]["click_history"][0]["clicked_at"],
+            "2024-03-01 10:00:00"
+        );
+        assert_eq!(lines[0]["click_history"][0]["device"], "desktop");
+    }
+
+    /// A state with one user, `alice`, whose id is 1
+    fn state_with_user() -> actix_web::web::Data<crate::db::AppState> {
+        let state = crate::testing::make_test_state();
+        state
+            .db
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO users (username, password) VALUES ('alice', 'x')",
+                [],
+            )
+            .unwrap();
+        state
+    }
+
+    /// Export alice's account as a zip bundle and import it for alice on a
+    /// fresh instance, returning that instance and the import report
+    fn round_trip(
+        state: &actix_web::web::Data<crate::db::AppState>,
+    ) -> (
+        actix_web::web::Data<crate::db::AppState>,
+        crate::models::ImportReport,
+    ) {
+        let mut zip = io::Cursor::new(Vec::new());
+        ExportSource::new(&state.db, 1, "http://localhost:4001")
+            .zip_bundle(&mut zip, "2024-01-01T00:00:00Z")
+            .unwrap();
+        let zip = zip.into_inner();
+        let format = ImportFormat::detect(&zip);
+        assert_eq!(format, ImportFormat::Zip);
+        let records = parse_import(&zip, format).unwrap();
+        let presets = parse_utm_presets(&zip, format).unwrap();
+
+        let copy = state_with_user();
+        let mut db = copy.db.lock().unwrap();
+        let report = import_links(&mut db, 1, records, &presets, OnConflict::Rename, 2048).unwrap();
+        drop(db);
+        (copy, report)
+    }
+
+    #[test]
+    fn link_settings_survive_an_export_and_import() {
+        let state = state_with_user();
+        state
+            .db
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO urls (user_id, original_url, short_code, public_stats,
+                                   ios_url, android_url, desktop_url)
+                 VALUES (1, 'https://example.com/plain', 'plain1', 0, NULL, NULL, NULL),
+                        (1, 'https://example.com/full?utm_source=news&utm_campaign=spring+sale&ref=home',
+                         'full01', 1,
+                         'https://apps.apple.com/app/id1', 'https://play.google.com/store/apps',
+                         NULL);
+                 INSERT INTO geo_rules (url_id, country, destination)
+                 VALUES (2, 'GB', 'https://example.co.uk'), (2, 'DE', 'https://example.de');
+                 UPDATE urls SET sticky_variants = 1, redirect_status = 307, passthrough = 'replace',
+                                 utm_source = 'news', utm_campaign = 'spring sale'
+                 WHERE id = 2;
+                 INSERT INTO utm_presets (user_id, name, utm_source, utm_medium)
+                 VALUES (1, 'Newsletter', 'newsletter', 'email');
+                 INSERT INTO url_variants (url_id, destination, weight, clicks)
+                 VALUES (2, 'https://example.com/a', 3, 40), (2, 'https://example.com/b', 1, 9);
+                 UPDATE urls SET expires_at = '2020-01-01 00:00:00', max_clicks = 5,
+                                 active_from = '2030-01-01 00:00:00',
+                                 active_until = '2031-01-01 00:00:00'
+                 WHERE id = 2;
+                 INSERT INTO tags (user_id, name) VALUES (1, 'launch'), (1, 'clients/acme');
+                 INSERT INTO url_tags (url_id, tag_id) SELECT 2, id FROM tags;
+                 INSERT INTO urls (user_id, original_url, short_code, password_hash)
+                 VALUES (1, 'https://example.com/secret', 'lock01', 'not-a-real-hash');",
+            )
+            .unwrap();
+
+        let (copy, report) = round_trip(&state);
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.utm_presets, 1);
+        // Without its password a protected link would come back public
+        assert_eq!(report.errors.len(), 1, "{:?}", report.errors);
+        assert_eq!(report.errors[0].row, 3);
+        assert!(report.errors[0].error.contains("Password-protected"));
+        let db = copy.db.lock().unwrap();
+        let exists = |code: &str| -> bool {
+            db.query_row(
+                "SELECT EXISTS(SELECT 1 FROM urls WHERE short_code = ?1)",
+                [code],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert!(!exists("lock01"));
+
+        type Limits = (Option<String>, Option<u64>, Option<String>, Option<String>);
+        let limits = |code: &str| -> Limits {
+            db.query_row(
+                "SELECT expires_at, max_clicks, active_from, active_until
+                 FROM urls WHERE short_code = ?1",
+                [code],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(limits("plain1"), (None, None, None, None));
+        assert_eq!(
+            limits("full01"),
+            (
+                Some("2020-01-01 00:00:00".to_string()),
+                Some(5),
+                Some("2030-01-01 00:00:00".to_string()),
+                Some("2031-01-01 00:00:00".to_string())
+            )
+        );
+
+        let tags = |code: &str| -> Vec<String> {
+            let mut stmt = db
+                .prepare(
+                    "SELECT t.name FROM url_tags ut JOIN tags t ON t.id = ut.tag_id
+                     JOIN urls u ON u.id = ut.url_id
+                     WHERE u.short_code = ?1 AND t.user_id = 1 ORDER BY t.name",
+                )
+                .unwrap();
+            stmt.query_map([code], |r| r.get(0))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect()
+        };
+        assert!(tags("plain1").is_empty());
+        assert_eq!(tags("full01"), ["clients/acme", "launch"]);
+        let public_stats = |code: &str| -> bool {
+            db.query_row(
+                "SELECT public_stats FROM urls WHERE short_code = ?1",
+                [code],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert!(!public_stats("plain1"));
+        assert!(public_stats("full01"));
+
+        let platform_urls = |code: &str| -> (Option<String>, Option<String>, Option<String>) {
+            db.query_row(
+                "SELECT ios_url, android_url, desktop_url FROM urls WHERE short_code = ?1",
+                [code],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(platform_urls("plain1"), (None, None, None));
+        assert_eq!(
+            platform_urls("full01"),
+            (
+                Some("https://apps.apple.com/app/id1".to_string()),
+                Some("https://play.google.com/store/apps".to_string()),
+                None
+            )
+        );
+
+        let mut stmt = db
+            .prepare(
+                "SELECT u.short_code, g.country, g.destination FROM geo_rules g
+                 JOIN urls u ON u.id = g.url_id ORDER BY g.country",
+            )
+            .unwrap();
+        let rules: Vec<(String, String, String)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("full01".into(), "DE".into(), "https://example.de".into()),
+                ("full01".into(), "GB".into(), "https://example.co.uk".into()),
+            ]
+        );
+
+        let sticky_variants = |code: &str| -> bool {
+            db.query_row(
+                "SELECT sticky_variants FROM urls WHERE short_code = ?1",
+                [code],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert!(!sticky_variants("plain1"));
+        assert!(sticky_variants("full01"));
+
+        let redirect_status = |code: &str| -> Option<u16> {
+            db.query_row(
+                "SELECT redirect_status FROM urls WHERE short_code = ?1",
+                [code],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(redirect_status("plain1"), None);
+        assert_eq!(redirect_status("full01"), Some(307));
+
+        let passthrough = |code: &str| -> Option<String> {
+            db.query_row(
+                "SELECT passthrough FROM urls WHERE short_code = ?1",
+                [code],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(passthrough("plain1"), None);
+        assert_eq!(passthrough("full01").as_deref(), Some("replace"));
+
+        let utm = |code: &str| -> (String, Option<String>, Option<String>, Option<String>) {
+            db.query_row(
+                "SELECT original_url, utm_source, utm_medium, utm_campaign
+                 FROM urls WHERE short_code = ?1",
+                [code],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            utm("plain1"),
+            ("https://example.com/plain".to_string(), None, None, None)
+        );
+        assert_eq!(
+            utm("full01"),
+            (
+                "https://example.com/full?utm_source=news&utm_campaign=spring+sale&ref=home"
+                    .to_string(),
+                Some("news".to_string()),
+                None,
+                Some("spring sale".to_string())
+            )
+        );
+
+        let preset = crate::handlers::utm_presets::utm_preset_by_name(&db, 1, "Newsletter")
+            .unwrap()
+            .unwrap();
+        assert_eq!(preset.utm_source.as_deref(), Some("newsletter"));
+        assert_eq!(preset.utm_medium.as_deref(), Some("email"));
+
+        let mut stmt = db
+            .prepare(
+                "SELECT u.short_code, v.destination, v.weight, v.clicks FROM url_variants v
+                 JOIN urls u ON u.id = v.url_id ORDER BY v.id",
+            )
+            .unwrap();
+        let variants: Vec<(String, String, u32, u64)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            variants,
+            [
+                ("full01".into(), "https://example.com/a".into(), 3, 40),
+                ("full01".into(), "https://example.com/b".into(), 1, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn source_reads_every_page_in_order() {
+        let state = crate::testing::make_test_state();
+        {
+            let db = state.db.lock().unwrap();
+            db.execute(
+                "INSERT INTO users (username, password) VALUES ('alice', 'x')",
+                [],
+            )
+            .unwrap();
+            // Same creation time for several links, so pages split on the id
+            for (n, created_at) in [
+                "2024-01-02",
+                "2024-01-01",
+                "2024-01-01",
+                "2024-01-03",
+                "2024-01-01",
+            ]
+            .iter()
+            .enumerate()
+            {
+                db.execute(
+                    "INSERT INTO urls (user_id, original_url, short_code, created_at)
+                     VALUES (1, ?1, ?2, ?3)",
+                    rusqlite::params![
+                        format!("https://example.com/{n}"),
+                        format!("code0{n}"),
+                        created_at
+                    ],
+                )
+                .unwrap();
+                db.execute(
+                    "INSERT INTO click_history (url_id, clicked_at) VALUES (?1, ?2)",
+                    rusqlite::params![
+                        db.last_insert_rowid(),
+                        format!("2024-02-0{} 10:00:00", 5 - n)
+                    ],
+                )
+                .unwrap();
+            }
+        }
+        let mut source = ExportSource::new(&state.db, 1, "http://localhost:4001");
+        source.page_size = 2;
+
+        let mut out = Vec::new();
+        assert_eq!(source.links_jsonl(&mut out).unwrap(), 5);
+        let codes: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| {
+                let link: serde_json::Value = serde_json::from_str(l).unwrap();
+                assert_eq!(link["click_history"].as_array().unwrap().len(), 1);
+                link["short_code"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(codes, ["code01", "code02", "code04", "code00", "code03"]);
+
+        let mut out = Vec::new();
+        assert_eq!(source.clicks_csv(&mut out).unwrap(), 5);
+        let codes: Vec<&str> = std::str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|l| l.split(',').next().unwrap())
+            .collect();
+        assert_eq!(codes, ["code04", "code03", "code02", "code01", "code00"]);
+    }
+}
@@ -0,0 +1,685 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, NaiveTime, Utc};
+use chrono_tz::Tz;
+use futures_util::{stream, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::error;
+
+use super::urls::{get_user_id, link_not_found};
+use crate::analytics::live::sse_message;
+use crate::analytics::timeseries::{
+    bucket_starts, click_timeseries, first_click, parse_bound, parse_timezone, ClickScope, Interval,
+};
+use crate::analytics::{top_values, unique_visitors};
+use crate::db::AppState;
+use crate::models::{PublicStats, TimeseriesQuery};
+
+/// Days shown in the chart of a public stats page, today included
+const PUBLIC_STATS_DAYS: i64 = 30;
+
+/// Referrers listed on a public stats page
+const PUBLIC_STATS_REFERRERS: u32 = 10;
+
+/// Quiet time after which a click stream sends a keep-alive comment, so
+/// proxies do not close it
+const STREAM_KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Protected endpoint to get one link's clicks bucketed by hour, day, week
+/// or month
+pub async fn get_click_timeseries(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    query: web::Query<TimeseriesQuery>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let url_id: rusqlite::Result<i64> = {
+        let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+        db.query_row(
+            "SELECT id FROM urls WHERE short_code = ?1 AND user_id = ?2",
+            params![code.as_str(), user_id],
+            |row| row.get(0),
+        )
+    };
+    match url_id {
+        Ok(url_id) => timeseries_response(&data, ClickScope::Url(url_id), &query),
+        Err(_) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Short URL not found or not owned by you"
+        }))),
+    }
+}
+
+/// Protected endpoint to get clicks on all of the user's links bucketed by
+/// hour, day, week or month
+pub async fn get_account_click_timeseries(
+    data: web::Data<AppState>,
+    query: web::Query<TimeseriesQuery>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    timeseries_response(&data, ClickScope::User(user_id), &query)
+}
+
+/// Public page at `/{code}+` showing a link's stats, if its owner made them
+/// public. Links without public stats get the same 404 as unknown codes.
+pub async fn public_stats_page(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+) -> Result<HttpResponse> {
+    let public: bool = {
+        let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+        db.query_row(
+            "SELECT public_stats FROM urls WHERE short_code = ?1",
+            params![code.as_str()],
+            |row| row.get(0),
+        )
+        .unwrap_or(false)
+    };
+    if !public {
+        return Ok(link_not_found());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(include_str!("../../static/stats.html")))
+}
+
+/// Public endpoint with the data behind the `/{code}+` page
+pub async fn get_public_stats(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+) -> Result<HttpResponse> {
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    match load_public_stats(&db, code.as_str()) {
+        Ok(Some(stats)) => Ok(HttpResponse::Ok().json(stats)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Short URL not found or its stats are not public"
+        }))),
+        Err(e) => {
+            error!(short_code = %code.as_str(), error = %e, "public stats: query failed");
+            Err(actix_web::error::ErrorInternalServerError("Database error"))
+        }
+    }
+}
+
+/// Stats of `code`, or `None` unless the link exists and its stats are public
+fn load_public_stats(db: &Connection, code: &str) -> rusqlite::Result<Option<PublicStats>> {
+    let link = db
+        .query_row(
+            "SELECT id, short_code, name, created_at, clicks FROM urls
+             WHERE short_code = ?1 AND public_stats = 1",
+            params![code],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    PublicStats {
+                        short_code: row.get(1)?,
+                        name: row.get(2)?,
+                        created_at: row.get(3)?,
+                        clicks: row.get(4)?,
+                        unique_visitors: 0,
+                        daily: Vec::new(),
+                        top_referrers: Vec::new(),
+                    },
+                ))
+            },
+        )
+        .optional()?;
+    let Some((url_id, mut stats)) = link else {
+        return Ok(None);
+    };
+
+    let tomorrow = Utc::now().date_naive() + Duration::days(1);
+    let to = tomorrow.and_time(NaiveTime::MIN).and_utc();
+    let from = to - Duration::days(PUBLIC_STATS_DAYS);
+    let starts = bucket_starts(Interval::Day, Tz::UTC, from, to)
+        .expect("public stats cover fewer than MAX_BUCKETS days");
+    stats.daily = click_timeseries(
+        db,
+        ClickScope::Url(url_id),
+        Interval::Day,
+        Tz::UTC,
+        from,
+        to,
+        &starts,
+    )?
+    .buckets;
+    stats.unique_visitors = unique_visitors(db, url_id)?;
+    stats.top_referrers = top_values(db, url_id, "referrer", PUBLIC_STATS_REFERRERS)?;
+    Ok(Some(stats))
+}
+
+/// Protected endpoint streaming the user's clicks as Server-Sent Events
+/// while they are recorded
+pub async fn click_stream(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let receiver = data.click_events.subscribe();
+    let events = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let message = match tokio::time::timeout(STREAM_KEEP_ALIVE, receiver.recv()).await {
+                Ok(Ok(event)) if event.user_id == user_id => sse_message(&event),
+                Ok(Ok(_)) => continue,
+                // Too slow to keep up; tell the client to reload its counters
+                Ok(Err(RecvError::Lagged(_))) => "event: lagged\ndata: {}\n\n".to_string(),
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(_) => ": keep-alive\n\n".to_string(),
+            };
+            return Some((
+                Ok::<_, actix_web::Error>(web::Bytes::from(message)),
+                receiver,
+            ));
+        }
+    });
+    // Reconnect after 5 seconds if the connection drops
+    let opening = stream::once(async { Ok(web::Bytes::from_static(b"retry: 5000\n\n")) });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Stop nginx from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(opening.chain(events)))
+}
+
+/// Interval, timezone and optional bounds of a time-series request
+struct TimeseriesParams {
+    interval: Interval,
+    tz: Tz,
+    from: Option<chrono::DateTime<Utc>>,
+    to: chrono::DateTime<Utc>,
+}
+
+fn parse_timeseries_query(
+    query: &TimeseriesQuery,
+) -> std::result::Result<TimeseriesParams, String> {
+    let interval = match query.interval.as_deref() {
+        Some(value) => Interval::from_param(value)
+            .ok_or("interval must be 'hour', 'day', 'week' or 'month'")?,
+        None => Interval::Day,
+    };
+    let tz = match query.tz.as_deref() {
+        Some(value) => parse_timezone(value)?,
+        None => Tz::UTC,
+    };
+    let from = query
+        .from
+        .as_deref()
+        .map(|v| parse_bound("from", v, tz, false))
+        .transpose()?;
+    let to = match query.to.as_deref() {
+        Some(value) => parse_bound("to", value, tz, true)?,
+        None => Utc::now(),
+    };
+    if from.is_some_and(|from| from > to) {
+        return Err("from must not be after to".to_string());
+    }
+
+    Ok(TimeseriesParams {
+        interval,
+        tz,
+        from,
+        to,
+    })
+}
+
+fn timeseries_response(
+    data: &web::Data<AppState>,
+    scope: ClickScope,
+    query: &TimeseriesQuery,
+) -> Result<HttpResponse> {
+    let params = match parse_timeseries_query(query) {
+        Ok(params) => params,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    let from = match params.from {
+        Some(from) => from,
+        None => first_click(&db, scope)
+            .map_err(|e| {
+                error!(?scope, error = %e, "click timeseries: failed to find first click");
+                actix_web::error::ErrorInternalServerError("Database error")
+            })?
+            .unwrap_or(params.to)
+            .min(params.to),
+    };
+
+    let starts = match bucket_starts(params.interval, params.tz, from, params.to) {
+        Ok(starts) => starts,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    match click_timeseries(
+        &db,
+        scope,
+        params.interval,
+        params.tz,
+        from,
+        params.to,
+        &starts,
+    ) {
+        Ok(series) => Ok(HttpResponse::Ok().json(series)),
+        Err(e) => {
+            error!(?scope, error = %e, "click timeseries: query failed");
+            Err(actix_web::error::ErrorInternalServerError("Database error"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::testing::*;
+
+    /// Record clicks on `code` at the given UTC `YYYY-MM-DD HH:MM:SS` times
+    fn insert_clicks(state: &web::Data<AppState>, code: &str, times: &[&str]) {
+        let db = state.db.lock().unwrap();
+        for clicked_at in times {
+            db.execute(
+                "INSERT INTO click_history (url_id, clicked_at)
+                 SELECT id, ?2 FROM urls WHERE short_code = ?1",
+                params![code, clicked_at],
+            )
+            .unwrap();
+        }
+    }
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use actix_web_httpauth::middleware::HttpAuthentication;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new().app_data($state.clone()).service(
+                        web::scope("/api")
+                            .wrap(jwt)
+                            .route(
+                                "/clicks/timeseries",
+                                web::get().to(get_account_click_timeseries),
+                            )
+                            .route(
+                                "/urls/{code}/clicks/timeseries",
+                                web::get().to(get_click_timeseries),
+                            ),
+                    ),
+                )
+                .await
+            }};
+        }
+
+        /// Next chunk of a streaming response, such as one Server-Sent Event
+        async fn next_message(body: &mut actix_web::body::BoxBody) -> String {
+            use actix_web::body::MessageBody;
+
+            let chunk = futures_util::future::poll_fn(|cx| body.as_pin_mut().poll_next(cx)).await;
+            String::from_utf8(chunk.unwrap().unwrap().to_vec()).unwrap()
+        }
+
+        #[actix_web::test]
+        async fn link_timeseries_buckets_by_day_in_timezone() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            insert_clicks(
+                &state,
+                "abc123",
+                &[
+                    "2024-03-01 09:00:00",
+                    "2024-03-01 22:30:00",
+                    "2024-03-03 12:00:00",
+                ],
+            );
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/abc123/clicks/timeseries?from=2024-03-01&to=2024-03-03&tz=America/New_York")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["interval"], "day");
+            assert_eq!(body["timezone"], "America/New_York");
+            assert_eq!(body["total"], 3);
+            let buckets = body["buckets"].as_array().unwrap();
+            assert_eq!(buckets.len(), 3);
+            assert_eq!(buckets[0]["start"], "2024-03-01T00:00:00-05:00");
+            assert_eq!(buckets[0]["clicks"], 2);
+            assert_eq!(buckets[1]["clicks"], 0);
+            assert_eq!(buckets[2]["clicks"], 1);
+        }
+
+        #[actix_web::test]
+        async fn account_timeseries_covers_all_own_links_from_first_click() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com/a", "alice1");
+            insert_test_url(&state, uid, "https://example.com/b", "alice2");
+            insert_test_url(&state, other, "https://example.com/c", "bob001");
+            insert_clicks(&state, "alice1", &["2024-01-15 10:00:00"]);
+            insert_clicks(&state, "alice2", &["2024-02-20 10:00:00"]);
+            insert_clicks(&state, "bob001", &["2023-06-01 10:00:00"]);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/clicks/timeseries?interval=month&to=2024-03-31")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let counts: Vec<(String, u64)> = body["buckets"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|b| {
+                    (
+                        b["start"].as_str().unwrap().to_string(),
+                        b["clicks"].as_u64().unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                counts,
+                [
+                    ("2024-01-01T00:00:00+00:00".to_string(), 1),
+                    ("2024-02-01T00:00:00+00:00".to_string(), 1),
+                    ("2024-03-01T00:00:00+00:00".to_string(), 0),
+                ]
+            );
+        }
+
+        #[actix_web::test]
+        async fn timeseries_rejects_bad_parameters() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for query in [
+                "interval=minute",
+                "tz=Europe/Atlantis",
+                "from=last-week",
+                "from=2024-03-03&to=2024-03-01",
+                "interval=hour&from=2020-01-01&to=2024-01-01",
+            ] {
+                let req = test::TestRequest::get()
+                    .uri(&format!("/api/urls/abc123/clicks/timeseries?{query}"))
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{query} should be rejected");
+            }
+        }
+
+        #[actix_web::test]
+        async fn public_stats_are_opt_in_and_need_no_login() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/secret", "abc123");
+            let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+            insert_clicks(&state, "abc123", &[&now, &now]);
+            state
+                .db
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE urls SET clicks = 2, name = 'Launch post' WHERE short_code = 'abc123'",
+                    [],
+                )
+                .unwrap();
+            let token = make_test_token("alice", uid, false);
+            let app = test::init_service(
+                App::new()
+                    .app_data(state.clone())
+                    .route("/api/public/stats/{code}", web::get().to(get_public_stats))
+                    .service(
+                        web::scope("/api")
+                            .wrap(HttpAuthentication::bearer(jwt_validator))
+                            .route(
+                                "/urls/{code}/public-stats",
+                                web::patch().to(crate::handlers::update_url_public_stats),
+                            ),
+                    )
+                    .route("/{code}+", web::get().to(public_stats_page)),
+            )
+            .await;
+
+            // Private by default: both look like an unknown link
+            let req = test::TestRequest::get().uri("/abc123+").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+            let req = test::TestRequest::get()
+                .uri("/api/public/stats/abc123")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/abc123/public-stats")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({ "enabled": true }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let req = test::TestRequest::get().uri("/abc123+").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            let html = test::read_body(resp).await;
+            assert!(std::str::from_utf8(&html)
+                .unwrap()
+                .contains("/api/public/stats/"));
+
+            let req = test::TestRequest::get()
+                .uri("/api/public/stats/abc123")
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["short_code"], "abc123");
+            assert_eq!(body["name"], "Launch post");
+            assert_eq!(body["clicks"], 2);
+            assert_eq!(body["daily"].as_array().unwrap().len(), 30);
+            assert_eq!(body["daily"][29]["clicks"], 2);
+            assert_eq!(body["top_referrers"][0]["value"], "direct");
+            assert!(
+                !body.to_string().contains("example.com/secret"),
+                "the destination is not public"
+            );
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/abc123/public-stats")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({ "enabled": false }))
+                .to_request();
+            test::call_service(&app, req).await;
+            let req = test::TestRequest::get()
+                .uri("/api/public/stats/abc123")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+        }
+
+        #[actix_web::test]
+        async fn public_stats_of_someone_elses_link_cannot_be_enabled() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, other, "https://example.com", "bob001");
+            let token = make_test_token("alice", uid, false);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(HttpAuthentication::bearer(jwt_validator))
+                        .route(
+                            "/urls/{code}/public-stats",
+                            web::patch().to(crate::handlers::update_url_public_stats),
+                        ),
+                ),
+            )
+            .await;
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/bob001/public-stats")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({ "enabled": true }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+        }
+
+        #[actix_web::test]
+        async fn click_stream_pushes_own_clicks_as_they_happen() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com/a", "alice1");
+            insert_test_url(&state, other, "https://example.com/b", "bob001");
+            let token = make_test_token("alice", uid, false);
+            let app = test::init_service(
+                App::new()
+                    .app_data(state.clone())
+                    .service(
+                        web::scope("/api")
+                            .wrap(HttpAuthentication::bearer(jwt_validator))
+                            .route("/clicks/stream", web::get().to(click_stream)),
+                    )
+                    .route("/{code}", web::get().to(crate::handlers::redirect_url)),
+            )
+            .await;
+
+            let req = test::TestRequest::get()
+                .uri("/api/clicks/stream")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            assert_eq!(
+                resp.headers().get("content-type").unwrap(),
+                "text/event-stream"
+            );
+            let mut body = actix_web::body::MessageBody::boxed(resp.into_body());
+            assert_eq!(next_message(&mut body).await, "retry: 5000\n\n");
+
+            for code in ["bob001", "alice1"] {
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{code}"))
+                    .to_request();
+                assert_eq!(test::call_service(&app, req).await.status(), 302);
+            }
+
+            // Bob's click is not sent to Alice
+            let message = next_message(&mut body).await;
+            let data = message
+                .strip_prefix("event: click\ndata: ")
+                .and_then(|m| m.strip_suffix("\n\n"))
+                .unwrap();
+            let event: Value = serde_json::from_str(data).unwrap();
+            assert_eq!(event["short_code"], "alice1");
+            assert_eq!(event["clicks"], 1);
+            assert_eq!(event["bot"], false);
+        }
+
+        #[actix_web::test]
+        async fn timeseries_of_someone_elses_link_is_not_found() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, other, "https://example.com", "bob001");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/bob001/clicks/timeseries")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+
+        #[actix_web::test]
+        async fn timeseries_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            insert_saas_url(&state, uid, "https://example.com/a", "salice");
+            insert_clicks(
+                &state,
+                "salice",
+                &["2024-05-01 10:00:00", "2024-05-08 10:00:00"],
+            );
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route(
+                            "/urls/{code}/clicks/timeseries",
+                            web::get().to(get_click_timeseries),
+                        ),
+                ),
+            )
+            .await;
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/salice/clicks/timeseries?interval=week&from=2024-04-29&to=2024-05-12")
+                .insert_header(("Cookie", format!("{RUS_SESSION_COOKIE}={token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["total"], 2);
+            assert_eq!(body["buckets"].as_array().unwrap().len(), 2);
+            assert_eq!(body["buckets"][0]["start"], "2024-04-29T00:00:00+00:00");
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::io::{self, BufWriter, Seek, Write};
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result};
+use futures_util::stream;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use super::urls::get_user_id;
+use crate::db::AppState;
+use crate::export::{ExportFormat, ExportSource};
+use crate::models::ExportQuery;
+
+/// Bytes collected before they are handed to the response as one chunk
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks that may wait for a slow client before the export pauses
+const EXPORT_CHUNKS_QUEUED: usize = 4;
+
+/// Writing end of a streamed response body. Fails once the client has gone
+/// away, which stops the export.
+struct ResponseWriter {
+    buf: Vec<u8>,
+    chunks: mpsc::Sender<io::Result<web::Bytes>>,
+}
+
+impl Write for ResponseWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= EXPORT_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = web::Bytes::from(std::mem::take(&mut self.buf));
+        self.chunks
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+/// Temporary file the zip bundle is built in, removed when dropped
+struct SpoolFile {
+    path: std::path::PathBuf,
+    file: std::fs::File,
+}
+
+impl SpoolFile {
+    fn create() -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "rus-export-{}-{}.zip",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self { path, file })
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Write the export to `out`; returns the number of links (or clicks, for
+/// the clicks CSV) written. A zip needs to seek while it is built, so it is
+/// spooled to a temporary file first and then copied out.
+fn write_export(
+    source: &ExportSource,
+    format: ExportFormat,
+    clicks_only: bool,
+    exported_at: &str,
+    mut out: ResponseWriter,
+) -> io::Result<usize> {
+    let written = match format {
+        ExportFormat::Csv if clicks_only => source.clicks_csv(&mut out)?,
+        ExportFormat::Csv => source.links_csv(&mut out)?,
+        ExportFormat::JsonLines => source.links_jsonl(&mut out)?,
+        ExportFormat::Zip => {
+            let mut spool = SpoolFile::create()?;
+            let mut zip = BufWriter::new(&mut spool.file);
+            let written = source.zip_bundle(&mut zip, exported_at)?;
+            zip.flush()?;
+            drop(zip);
+            spool.file.rewind()?;
+            io::copy(&mut spool.file, &mut out)?;
+            written
+        }
+    };
+    out.flush()?;
+    Ok(written)
+}
+
+/// Protected endpoint to download all of the user's links, tags and click
+/// history, for backups and data-portability requests. The export is
+/// streamed: rows are read a page at a time and the database is free for
+/// other requests between pages.
+pub async fn export_urls(
+    data: web::Data<AppState>,
+    query: web::Query<ExportQuery>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let format = match query.format.as_deref().map(ExportFormat::from_param) {
+        Some(Some(format)) => format,
+        None => ExportFormat::Zip,
+        Some(None) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "format must be 'csv', 'jsonl' or 'zip'"
+            })));
+        }
+    };
+    let clicks_only = match (format, query.data.as_deref()) {
+        (_, None) | (ExportFormat::Csv, Some("links")) => false,
+        (ExportFormat::Csv, Some("clicks")) => true,
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "data must be 'links' or 'clicks', and only applies to CSV"
+            })));
+        }
+    };
+    let (content_type, extension) = match format {
+        ExportFormat::Csv if clicks_only => ("text/csv; charset=utf-8", "clicks.csv"),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "links.csv"),
+        ExportFormat::JsonLines => ("application/x-ndjson", "links.jsonl"),
+        ExportFormat::Zip => ("application/zip", "zip"),
+    };
+
+    let exported_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let filename = format!("rus-export-{}.{}", &exported_at[..10], extension);
+
+    let (chunks, receiver) = mpsc::channel(EXPORT_CHUNKS_QUEUED);
+    tokio::task::spawn_blocking(move || {
+        let source = ExportSource::new(&data.db, user_id, &data.config.host_url);
+        let out = ResponseWriter {
+            buf: Vec::with_capacity(EXPORT_CHUNK_SIZE),
+            chunks: chunks.clone(),
+        };
+        match write_export(&source, format, clicks_only, &exported_at, out) {
+            Ok(written) => info!(user_id, written, ?format, "Links exported"),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                info!(user_id, ?format, "Export abandoned by the client")
+            }
+            Err(e) => {
+                error!(user_id, error = %e, "export_urls: failed to build export");
+                // Abort the response so a partial export is not mistaken for a complete one
+                chunks.blocking_send(Err(e)).ok();
+            }
+        }
+    });
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+
+    use super::*;
+    use crate::testing::*;
+
+    /// Give `code` a tag and `clicks` recorded clicks
+    fn decorate_url(state: &web::Data<AppState>, code: &str, tag: &str, clicks: usize) {
+        let db = state.db.lock().unwrap();
+        let (url_id, user_id): (i64, i64) = db
+            .query_row(
+                "SELECT id, user_id FROM urls WHERE short_code = ?1",
+                [code],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        db.execute(
+            "INSERT OR IGNORE INTO tags (user_id, name) VALUES (?1, ?2)",
+            rusqlite::params![user_id, tag],
+        )
+        .unwrap();
+        db.execute(
+            "INSERT INTO url_tags (url_id, tag_id)
+             SELECT ?1, id FROM tags WHERE user_id = ?2 AND name = ?3",
+            rusqlite::params![url_id, user_id, tag],
+        )
+        .unwrap();
+        for _ in 0..clicks {
+            db.execute("INSERT INTO click_history (url_id) VALUES (?1)", [url_id])
+                .unwrap();
+        }
+    }
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use actix_web_httpauth::middleware::HttpAuthentication;
+        use std::io::Read;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new().app_data($state.clone()).service(
+                        web::scope("/api")
+                            .wrap(jwt)
+                            .route("/export", web::get().to(export_urls)),
+                    ),
+                )
+                .await
+            }};
+        }
+
+        #[actix_web::test]
+        async fn export_jsonl_includes_tags_and_history_of_own_links_only() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com/a", "alice1");
+            insert_test_url(&state, other, "https://example.com/b", "bob001");
+            decorate_url(&state, "alice1", "clients/acme", 2);
+            decorate_url(&state, "bob001", "private", 1);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/export?format=jsonl")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            assert_eq!(
+                resp.headers().get("content-type").unwrap(),
+                "application/x-ndjson"
+            );
+            let disposition = resp.headers().get("content-disposition").unwrap();
+            assert!(disposition.to_str().unwrap().contains(".links.jsonl"));
+            let body = test::read_body(resp).await;
+            let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect();
+            assert_eq!(lines.len(), 1);
+            assert_eq!(lines[0]["short_code"], "alice1");
+            assert_eq!(lines[0]["tags"][0], "clients/acme");
+            assert_eq!(lines[0]["click_history"].as_array().unwrap().len(), 2);
+        }
+
+        #[actix_web::test]
+        async fn export_csv_links_and_clicks() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/a", "alice1");
+            decorate_url(&state, "alice1", "launch", 3);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/export?format=csv")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body = test::call_and_read_body(&app, req).await;
+            let text = std::str::from_utf8(&body).unwrap();
+            assert!(text.starts_with("short_code,short_url,original_url"));
+            assert!(text.contains("alice1,http://localhost:4001/alice1,https://example.com/a"));
+
+            let req = test::TestRequest::get()
+                .uri("/api/export?format=csv&data=clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body = test::call_and_read_body(&app, req).await;
+            assert_eq!(std::str::from_utf8(&body).unwrap().lines().count(), 4);
+        }
+
+        #[actix_web::test]
+        async fn export_zip_bundle_by_default() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/a", "alice1");
+            decorate_url(&state, "alice1", "launch", 1);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/export")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(
+                resp.headers().get("content-type").unwrap(),
+                "application/zip"
+            );
+            let body = test::read_body(resp).await;
+
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(body.to_vec())).unwrap();
+            let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+            names.sort();
+            assert_eq!(
+                names,
+                [
+                    "account.json",
+                    "clicks.csv",
+                    "links.csv",
+                    "links.jsonl",
+                    "utm_presets.json"
+                ]
+            );
+            let mut account = String::new();
+            archive
+                .by_name("account.json")
+                .unwrap()
+                .read_to_string(&mut account)
+                .unwrap();
+            let account: serde_json::Value = serde_json::from_str(&account).unwrap();
+            assert_eq!(account["username"], "alice");
+            assert_eq!(account["links"], 1);
+            assert_eq!(account["recorded_clicks"], 1);
+        }
+
+        #[actix_web::test]
+        async fn export_rejects_unknown_format() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for uri in [
+                "/api/export?format=xlsx",
+                "/api/export?format=jsonl&data=clicks",
+            ] {
+                let req = test::TestRequest::get()
+                    .uri(uri)
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{uri} should be rejected");
+            }
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+
+        #[actix_web::test]
+        async fn export_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            insert_saas_url(&state, uid, "https://example.com/a", "salice");
+            decorate_url(&state, "salice", "launch", 2);
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route("/export", web::get().to(export_urls)),
+                ),
+            )
+            .await;
+
+            let req = test::TestRequest::get()
+                .uri("/api/export")
+                .insert_header(("Cookie", format!("{RUS_SESSION_COOKIE}={token}")))
+                .to_request();
+            let body = test::call_and_read_body(&app, req).await;
+            let archive = zip::ZipArchive::new(std::io::Cursor::new(body.to_vec())).unwrap();
+            assert_eq!(archive.len(), 5);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use rusqlite::params;
+use tracing::{error, info};
+
+use super::tags::{owned_url_id, url_not_found};
+use super::urls::get_user_id;
+use crate::db::AppState;
+use crate::models::{GeoRule, UpdateGeoRulesRequest};
+use crate::url::validate_geo_rules;
+
+/// Geo rules on a URL, ordered by country
+fn geo_rules_for_url(db: &rusqlite::Connection, url_id: i64) -> rusqlite::Result<Vec<GeoRule>> {
+    let mut stmt = db
+        .prepare("SELECT country, destination FROM geo_rules WHERE url_id = ?1 ORDER BY country")?;
+    let rules = stmt
+        .query_map(params![url_id], |row| {
+            Ok(GeoRule {
+                country: row.get(0)?,
+                url: row.get(1)?,
+            })
+        })?
+        .collect();
+    rules
+}
+
+/// A URL's rules plus the destination used when none of them match
+fn geo_rules_response(db: &rusqlite::Connection, url_id: i64) -> Result<HttpResponse> {
+    let rules = geo_rules_for_url(db, url_id)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    let fallback_url: String = db
+        .query_row(
+            "SELECT original_url FROM urls WHERE id = ?1",
+            params![url_id],
+            |row| row.get(0),
+        )
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "rules": rules,
+        "fallback_url": fallback_url
+    })))
+}
+
+/// Protected endpoint to list a URL's geo rules
+pub async fn get_geo_rules(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    match owned_url_id(&db, code.as_str(), user_id) {
+        Some(url_id) => geo_rules_response(&db, url_id),
+        None => Ok(url_not_found()),
+    }
+}
+
+/// Protected endpoint to replace all of a URL's geo rules. Visitors from a
+/// listed country are sent to its destination; everyone else, and everyone
+/// when no GeoIP database is configured, to the URL's own destination.
+pub async fn replace_geo_rules(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateGeoRulesRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let rules = match validate_geo_rules(&req_payload.rules, data.config.max_url_length) {
+        Ok(rules) => rules,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let mut db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let url_id = match owned_url_id(&db, code.as_str(), user_id) {
+        Some(id) => id,
+        None => return Ok(url_not_found()),
+    };
+
+    let result = db.transaction().and_then(|tx| {
+        tx.execute("DELETE FROM geo_rules WHERE url_id = ?1", params![url_id])?;
+        for rule in &rules {
+            tx.execute(
+                "INSERT INTO geo_rules (url_id, country, destination) VALUES (?1, ?2, ?3)",
+                params![url_id, &rule.country, &rule.url],
+            )?;
+        }
+        tx.commit()
+    });
+    if let Err(e) = result {
+        error!(user_id, short_code = %code.as_str(), error = %e, "Failed to replace geo rules");
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update geo rules"
+        })));
+    }
+
+    info!(user_id, short_code = %code.as_str(), rules = rules.len(), "Geo rules replaced");
+    geo_rules_response(&db, url_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use serde_json::Value;
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use crate::handlers::urls::{get_click_history, get_stats, redirect_url};
+        use crate::testing::{
+            insert_test_url, insert_test_user, make_test_token, peer, test_config, GEOIP_FIXTURE,
+        };
+        use actix_web_httpauth::middleware::HttpAuthentication;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new()
+                        .app_data($state.clone())
+                        .service(
+                            web::scope("/api")
+                                .wrap(jwt)
+                                .route("/urls/{code}/geo-rules", web::get().to(get_geo_rules))
+                                .route("/urls/{code}/geo-rules", web::put().to(replace_geo_rules))
+                                .route("/urls/{code}/clicks", web::get().to(get_click_history))
+                                .route("/stats/{code}", web::get().to(get_stats)),
+                        )
+                        .route("/{code}", web::get().to(redirect_url)),
+                )
+                .await
+            }};
+        }
+
+        fn geo_state() -> web::Data<AppState> {
+            let mut config = test_config();
+            config.geoip_db_path = Some(GEOIP_FIXTURE.to_string());
+            web::Data::new(AppState::new(config).unwrap())
+        }
+
+        #[actix_web::test]
+        async fn visitors_are_sent_to_their_countrys_destination() {
+            let state = geo_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "geo001");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::put()
+                .uri("/api/urls/geo001/geo-rules")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"rules": [
+                    {"country": "se", "url": "https://example.se"},
+                    {"country": "GB", "url": "https://example.co.uk"}
+                ]}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["rules"][0]["country"], "GB");
+            assert_eq!(body["rules"][1]["country"], "SE");
+            assert_eq!(body["fallback_url"], "https://example.com");
+
+            // London, Linköping, Milton (no rule) and a private address
+            for (ip, expected) in [
+                ("81.2.69.142", "https://example.co.uk"),
+                ("89.160.20.112", "https://example.se"),
+                ("216.160.83.56", "https://example.com"),
+                ("10.0.0.1", "https://example.com"),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri("/geo001")
+                    .peer_addr(peer(ip))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 302, "{ip}");
+                assert_eq!(resp.headers().get("Location").unwrap(), expected, "{ip}");
+            }
+
+            // The matched rule is recorded on each click
+            let req = test::TestRequest::get()
+                .uri("/api/urls/geo001/clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let rules: Vec<&Value> = body["history"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|click| &click["geo_rule"])
+                .collect();
+            assert_eq!(
+                rules,
+                [&Value::Null, &Value::Null, &"SE".into(), &"GB".into()]
+            );
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/geo001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let geo_rules = &body["breakdown"]["geo_rules"];
+            assert_eq!(geo_rules[0]["value"], "fallback");
+            assert_eq!(geo_rules[0]["clicks"], 2);
+            assert_eq!(geo_rules.as_array().unwrap().len(), 3);
+        }
+
+        #[actix_web::test]
+        async fn empty_rule_list_restores_the_single_destination() {
+            let state = geo_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "geo002");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for rules in [
+                serde_json::json!([{"country": "GB", "url": "https://example.co.uk"}]),
+                serde_json::json!([]),
+            ] {
+                let req = test::TestRequest::put()
+                    .uri("/api/urls/geo002/geo-rules")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({ "rules": rules }))
+                    .to_request();
+                assert_eq!(test::call_service(&app, req).await.status(), 200);
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/geo002/geo-rules")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["rules"], serde_json::json!([]));
+
+            let req = test::TestRequest::get()
+                .uri("/geo002")
+                .peer_addr(peer("81.2.69.142"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+        }
+
+        #[actix_web::test]
+        async fn invalid_rules_and_other_users_links_are_refused() {
+            let state = geo_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, other, "https://bob.example.com", "geo003");
+            insert_test_url(&state, uid, "https://example.com", "geo004");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for rules in [
+                serde_json::json!([{"country": "GBR", "url": "https://example.co.uk"}]),
+                serde_json::json!([{"country": "GB", "url": "javascript:alert(1)"}]),
+                serde_json::json!([
+                    {"country": "GB", "url": "https://example.co.uk"},
+                    {"country": "gb", "url": "https://example.com/uk"}
+                ]),
+            ] {
+                let req = test::TestRequest::put()
+                    .uri("/api/urls/geo004/geo-rules")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({ "rules": rules }))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{rules}");
+            }
+
+            let req = test::TestRequest::put()
+                .uri("/api/urls/geo003/geo-rules")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"rules": []}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+            let req = test::TestRequest::get()
+                .uri("/api/urls/geo003/geo-rules")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+        use crate::testing::{
+            insert_saas_url, insert_saas_user, make_saas_session, make_test_state,
+        };
+
+        #[actix_web::test]
+        async fn replace_and_list_geo_rules_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            insert_saas_url(&state, uid, "https://example.com", "sgeo01");
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route("/urls/{code}/geo-rules", web::get().to(get_geo_rules))
+                        .route("/urls/{code}/geo-rules", web::put().to(replace_geo_rules)),
+                ),
+            )
+            .await;
+            let cookie = format!("{RUS_SESSION_COOKIE}={token}");
+
+            let req = test::TestRequest::put()
+                .uri("/api/urls/sgeo01/geo-rules")
+                .insert_header(("Cookie", cookie.clone()))
+                .set_json(
+                    serde_json::json!({"rules": [{"country": "de", "url": "https://example.de"}]}),
+                )
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/sgeo01/geo-rules")
+                .insert_header(("Cookie", cookie))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["rules"][0]["country"], "DE");
+            assert_eq!(body["rules"][0]["url"], "https://example.de");
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result};
+use tracing::{error, info};
+
+use super::urls::get_user_id;
+use crate::db::AppState;
+use crate::import::{import_links, parse_import, parse_utm_presets, ImportFormat, OnConflict};
+use crate::models::ImportQuery;
+
+/// Protected endpoint to import links exported from Bitly, YOURLS, Shlink or
+/// a similar CSV / JSON file, or a zip bundle exported by rus. Original short
+/// codes are kept unless they are taken; every conflict and rejected row is
+/// listed in the report.
+pub async fn import_urls(
+    data: web::Data<AppState>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let format = match query.format.as_deref() {
+        Some(value) => match ImportFormat::from_param(value) {
+            Some(format) => format,
+            None => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "format must be 'csv', 'json' or 'zip'"
+                })));
+            }
+        },
+        None => format_from_content_type(&http_req).unwrap_or_else(|| ImportFormat::detect(&body)),
+    };
+
+    let on_conflict = match query.on_conflict.as_deref() {
+        Some(value) => match OnConflict::from_param(value) {
+            Some(on_conflict) => on_conflict,
+            None => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "on_conflict must be 'rename' or 'skip'"
+                })));
+            }
+        },
+        None => OnConflict::default(),
+    };
+
+    let parsed = parse_import(&body, format)
+        .and_then(|records| Ok((records, parse_utm_presets(&body, format)?)));
+    let (records, presets) = match parsed {
+        Ok(parsed) => parsed,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let mut db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    match import_links(
+        &mut db,
+        user_id,
+        records,
+        &presets,
+        on_conflict,
+        data.config.max_url_length,
+    ) {
+        Ok(report) => {
+            info!(
+                user_id,
+                imported = report.imported,
+                utm_presets = report.utm_presets,
+                skipped = report.skipped,
+                conflicts = report.conflicts.len(),
+                errors = report.errors.len(),
+                "Links imported"
+            );
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(e) => {
+            error!(user_id, error = %e, "import_urls: import failed");
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to import links"
+            })))
+        }
+    }
+}
+
+/// Format implied by the request's Content-Type, if it names one
+fn format_from_content_type(http_req: &HttpRequest) -> Option<ImportFormat> {
+    let content_type = http_req
+        .headers()
+        .get(header::CONTENT_TYPE)?
+        .to_str()
+        .ok()?
+        .to_ascii_lowercase();
+    if content_type.contains("json") {
+        Some(ImportFormat::Json)
+    } else if content_type.contains("zip") {
+        Some(ImportFormat::Zip)
+    } else if content_type.contains("csv") {
+        Some(ImportFormat::Csv)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::testing::*;
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use actix_web_httpauth::middleware::HttpAuthentication;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new().app_data($state.clone()).service(
+                        web::scope("/api").wrap(jwt).service(
+                            web::resource("/import")
+                                .app_data(web::PayloadConfig::new(crate::import::IMPORT_MAX_BYTES))
+                                .route(web::post().to(import_urls)),
+                        ),
+                    ),
+                )
+                .await
+            }};
+        }
+
+        #[actix_web::test]
+        async fn import_csv_keeps_codes_and_reports_conflicts() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, other, "https://bob.com", "taken");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let csv = "shortCode,longUrl,title,dateCreated,visits\n\
+                       docs,https://example.com/docs,Docs,2021-07-01T12:00:00+00:00,42\n\
+                       taken,https://example.com/other,,,3\n\
+                       nope,not a url,,,0\n";
+            let req = test::TestRequest::post()
+                .uri("/api/import")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .insert_header(("Content-Type", "text/csv"))
+                .set_payload(csv)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["imported"], 2);
+            assert_eq!(body["conflicts"][0]["row"], 2);
+            assert_eq!(body["conflicts"][0]["short_code"], "taken");
+            assert!(body["conflicts"][0]["assigned_code"].is_string());
+            assert_eq!(body["errors"][0]["row"], 3);
+
+            let db = state.db.lock().unwrap();
+            let (user_id, name, clicks, created_at): (i64, String, i64, String) = db
+                .query_row(
+                    "SELECT user_id, name, clicks, created_at FROM urls WHERE short_code = 'docs'",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+                )
+                .unwrap();
+            assert_eq!(user_id, uid);
+            assert_eq!(name, "Docs");
+            assert_eq!(clicks, 42);
+            assert_eq!(created_at, "2021-07-01 12:00:00");
+        }
+
+        #[actix_web::test]
+        async fn import_json_with_skip_leaves_conflicts_out() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "taken");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/import?on_conflict=skip")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!([
+                    {"link": "https://bit.ly/taken", "long_url": "https://example.com/a"},
+                    {"link": "https://bit.ly/fresh", "long_url": "https://example.com/b"}
+                ]))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["imported"], 1);
+            assert_eq!(body["skipped"], 1);
+            assert!(body["conflicts"][0].get("assigned_code").is_none());
+        }
+
+        #[actix_web::test]
+        async fn import_rejects_bad_parameters_and_files() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for (uri, payload) in [
+                ("/api/import?format=xml", "url\nhttps://example.com\n"),
+                (
+                    "/api/import?on_conflict=merge",
+                    "url\nhttps://example.com\n",
+                ),
+                ("/api/import", "code,clicks\nabc,1\n"),
+                ("/api/import?format=json", "{not json"),
+            ] {
+                let req = test::TestRequest::post()
+                    .uri(uri)
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_payload(payload)
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{uri} should be rejected");
+            }
+        }
+
+        #[actix_web::test]
+        async fn import_without_auth_returns_401() {
+            let state = make_test_state();
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/import")
+                .set_payload("url\nhttps://example.com\n")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 401);
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+
+        #[actix_web::test]
+        async fn import_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route("/import", web::post().to(import_urls)),
+                ),
+            )
+            .await;
+
+            let req = test::TestRequest::post()
+                .uri("/api/import")
+                .insert_header(("Cookie", format!("{RUS_SESSION_COOKIE}={token}")))
+                .set_payload("url,keyword,clicks\nhttps://example.com,kept1,5\n")
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["imported"], 1);
+            assert!(body["conflicts"].as_array().unwrap().is_empty());
+        }
+    }
+}
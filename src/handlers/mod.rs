@@ -1,8 +1,12 @@
 pub mod abuse;
 #[cfg(feature = "standalone")]
 pub mod admin;
+pub mod analytics;
 #[cfg(feature = "standalone")]
 pub mod auth;
+pub mod export;
+pub mod geo_rules;
+pub mod import;
 pub mod pages;
 #[cfg(feature = "saas")]
 pub mod saas_auth;
@@ -12,7 +16,10 @@ pub mod webhook;
 pub use saas_auth::{maintenance_guard, saas_me};
 #[cfg(feature = "saas")]
 pub use webhook::handle_maintenance_webhook;
+pub mod tags;
 pub mod urls;
+pub mod utm_presets;
+pub mod variants;
 
 // Re-export handlers for easier importing
 pub use abuse::submit_abuse_report;
@@ -20,15 +27,28 @@ pub use abuse::submit_abuse_report;
 pub use abuse::{admin_list_reports, admin_resolve_report};
 #[cfg(feature = "standalone")]
 pub use admin::{admin_delete_user, admin_get_stats, admin_list_users, admin_promote_user};
+pub use analytics::{
+    click_stream, get_account_click_timeseries, get_click_timeseries, get_public_stats,
+    public_stats_page,
+};
 #[cfg(feature = "standalone")]
 pub use auth::{get_current_user, login, refresh_token, register};
+pub use export::export_urls;
+pub use geo_rules::{get_geo_rules, replace_geo_rules};
+pub use import::import_urls;
 #[cfg(feature = "standalone")]
 pub use pages::{admin_page, check_setup_required, login_page, setup_page, signup_page};
 pub use pages::{
     dashboard_page, get_config, get_version, health_check, index, report_page, serve_auth_js,
     serve_css, serve_theme_js,
 };
+pub use tags::{add_url_tags, list_tags, remove_url_tag};
 pub use urls::{
-    delete_url, get_click_history, get_qr_code, get_stats, get_user_urls, redirect_url,
-    shorten_url, update_url_name,
+    bulk_shorten_urls, delete_url, forwarded_method, get_click_history, get_qr_code, get_stats,
+    get_url_revisions, get_user_urls, password_protected_link, redirect_url, shorten_url,
+    unlock_url, update_url_destination, update_url_expiry, update_url_name, update_url_passthrough,
+    update_url_password, update_url_platform_urls, update_url_public_stats,
+    update_url_redirect_status, update_url_schedule,
 };
+pub use utm_presets::{delete_utm_preset, list_utm_presets, save_utm_preset};
+pub use variants::{get_url_variants, replace_url_variants};
@@ -0,0 +1,461 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use rusqlite::params;
+use tracing::{error, info};
+
+use super::urls::get_user_id;
+use crate::db::AppState;
+use crate::models::{AddTagsRequest, TagSummary};
+use crate::url::{normalize_tag, MAX_TAGS_PER_URL};
+
+/// Look up the id of a URL owned by `user_id`
+pub(crate) fn owned_url_id(db: &rusqlite::Connection, code: &str, user_id: i64) -> Option<i64> {
+    db.query_row(
+        "SELECT id FROM urls WHERE short_code = ?1 AND user_id = ?2",
+        params![code, user_id],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// Tag names on a URL, sorted alphabetically
+fn tags_for_url(db: &rusqlite::Connection, url_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = db.prepare(
+        "SELECT t.name FROM url_tags ut JOIN tags t ON t.id = ut.tag_id
+         WHERE ut.url_id = ?1 ORDER BY t.name",
+    )?;
+    let tags = stmt
+        .query_map(params![url_id], |row| row.get(0))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(tags)
+}
+
+pub(crate) fn url_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "Short URL not found or not owned by you"
+    }))
+}
+
+/// Protected endpoint to add tags to a URL, creating any the user doesn't have yet
+pub async fn add_url_tags(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<AddTagsRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let mut names = Vec::new();
+    for tag in &req_payload.tags {
+        match normalize_tag(tag) {
+            Ok(name) if !names.contains(&name) => names.push(name),
+            Ok(_) => {}
+            Err(error_message) => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": error_message
+                })));
+            }
+        }
+    }
+    if names.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "At least one tag is required"
+        })));
+    }
+
+    let mut db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let url_id = match owned_url_id(&db, code.as_str(), user_id) {
+        Some(id) => id,
+        None => return Ok(url_not_found()),
+    };
+
+    let existing = tags_for_url(&db, url_id)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    let added = names.iter().filter(|n| !existing.contains(n)).count();
+    if existing.len() + added > MAX_TAGS_PER_URL {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("A link can have at most {} tags", MAX_TAGS_PER_URL)
+        })));
+    }
+
+    let result = db.transaction().and_then(|tx| {
+        for name in &names {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (user_id, name) VALUES (?1, ?2)",
+                params![user_id, name],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO url_tags (url_id, tag_id)
+                 SELECT ?1, id FROM tags WHERE user_id = ?2 AND name = ?3",
+                params![url_id, user_id, name],
+            )?;
+        }
+        tx.commit()
+    });
+    if let Err(e) = result {
+        error!(user_id, short_code = %code.as_str(), error = %e, "Failed to add tags");
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to add tags"
+        })));
+    }
+
+    info!(user_id, short_code = %code.as_str(), tags = ?names, "Tags added");
+    let tags = tags_for_url(&db, url_id)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tags": tags })))
+}
+
+/// Protected endpoint to remove a tag from a URL. A tag left on no links is
+/// deleted so it no longer shows up in the user's tag list.
+pub async fn remove_url_tag(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (code, tag) = path.into_inner();
+
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let name = match normalize_tag(&tag) {
+        Ok(name) => name,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let url_id = match owned_url_id(&db, &code, user_id) {
+        Some(id) => id,
+        None => return Ok(url_not_found()),
+    };
+
+    let removed = db
+        .execute(
+            "DELETE FROM url_tags
+             WHERE url_id = ?1
+               AND tag_id = (SELECT id FROM tags WHERE user_id = ?2 AND name = ?3)",
+            params![url_id, user_id, &name],
+        )
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    if removed == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Tag not found on this URL"
+        })));
+    }
+
+    let _ = db.execute(
+        "DELETE FROM tags
+         WHERE user_id = ?1 AND name = ?2
+           AND NOT EXISTS (SELECT 1 FROM url_tags WHERE tag_id = tags.id)",
+        params![user_id, &name],
+    );
+
+    info!(user_id, short_code = %code, tag = %name, "Tag removed");
+    let tags = tags_for_url(&db, url_id)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tags": tags })))
+}
+
+/// Protected endpoint to list the current user's tags with link counts
+pub async fn list_tags(data: web::Data<AppState>, http_req: HttpRequest) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut stmt = db
+        .prepare(
+            "SELECT t.name, COUNT(ut.url_id)
+             FROM tags t JOIN url_tags ut ON ut.tag_id = t.id
+             WHERE t.user_id = ?1
+             GROUP BY t.id
+             ORDER BY t.name",
+        )
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let tags: Vec<TagSummary> = stmt
+        .query_map(params![user_id], |row| {
+            Ok(TagSummary {
+                name: row.get(0)?,
+                url_count: row.get(1)?,
+            })
+        })
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use serde_json::Value;
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use crate::handlers::urls::{delete_url, get_stats, get_user_urls};
+        use crate::testing::{insert_test_url, insert_test_user, make_test_state, make_test_token};
+        use actix_web_httpauth::middleware::HttpAuthentication;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new().app_data($state.clone()).service(
+                        web::scope("/api")
+                            .wrap(jwt)
+                            .route("/tags", web::get().to(list_tags))
+                            .route("/urls", web::get().to(get_user_urls))
+                            .route("/urls/{code}", web::delete().to(delete_url))
+                            .route("/urls/{code}/tags", web::post().to(add_url_tags))
+                            .route(
+                                "/urls/{code}/tags/{tag:.*}",
+                                web::delete().to(remove_url_tag),
+                            )
+                            .route("/stats/{code}", web::get().to(get_stats)),
+                    ),
+                )
+                .await
+            }};
+        }
+
+        #[actix_web::test]
+        async fn add_tags_normalises_and_dedupes() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "tag001");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/urls/tag001/tags")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"tags": ["Launch", "launch ", "clients/acme"]}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["tags"], serde_json::json!(["clients/acme", "launch"]));
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/tag001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["tags"], serde_json::json!(["clients/acme", "launch"]));
+        }
+
+        #[actix_web::test]
+        async fn add_invalid_tag_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "tag002");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/urls/tag002/tags")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"tags": ["a,b"]}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+
+        #[actix_web::test]
+        async fn add_tags_to_other_users_url_returns_404() {
+            let state = make_test_state();
+            let alice = insert_test_user(&state, "alice", false);
+            let bob = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, alice, "https://example.com", "tag003");
+            let token = make_test_token("bob", bob, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/urls/tag003/tags")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"tags": ["mine"]}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+        }
+
+        #[actix_web::test]
+        async fn filter_urls_by_tag_includes_sub_folders() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/a", "tag004");
+            insert_test_url(&state, uid, "https://example.com/b", "tag005");
+            insert_test_url(&state, uid, "https://example.com/c", "tag006");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for (code, tag) in [
+                ("tag004", "clients"),
+                ("tag005", "clients/acme"),
+                ("tag006", "clientsx"),
+            ] {
+                let req = test::TestRequest::post()
+                    .uri(&format!("/api/urls/{code}/tags"))
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"tags": [tag]}))
+                    .to_request();
+                test::call_service(&app, req).await;
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls?tag=clients")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let mut codes: Vec<&str> = body["urls"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|u| u["short_code"].as_str().unwrap())
+                .collect();
+            codes.sort();
+            assert_eq!(codes, vec!["tag004", "tag005"]);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls?tag=clients%2Facme")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["total"], 1);
+            assert_eq!(body["urls"][0]["short_code"], "tag005");
+        }
+
+        #[actix_web::test]
+        async fn remove_tag_drops_unused_tag_from_list() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "tag007");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/urls/tag007/tags")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"tags": ["clients/acme", "q3"]}))
+                .to_request();
+            test::call_service(&app, req).await;
+
+            let req = test::TestRequest::delete()
+                .uri("/api/urls/tag007/tags/clients/acme")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["tags"], serde_json::json!(["q3"]));
+
+            let req = test::TestRequest::get()
+                .uri("/api/tags")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body, serde_json::json!([{"name": "q3", "url_count": 1}]));
+
+            let req = test::TestRequest::delete()
+                .uri("/api/urls/tag007/tags/clients/acme")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+        }
+
+        #[actix_web::test]
+        async fn add_tags_enforces_per_link_limit() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "tag008");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let tags: Vec<String> = (0..=MAX_TAGS_PER_URL).map(|i| format!("t{i}")).collect();
+            let req = test::TestRequest::post()
+                .uri("/api/urls/tag008/tags")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({ "tags": tags }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::handlers::urls::get_user_urls;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+        use crate::testing::{
+            insert_saas_url, insert_saas_user, make_saas_session, make_test_state,
+        };
+
+        #[actix_web::test]
+        async fn tag_and_filter_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            insert_saas_url(&state, uid, "https://example.com/a", "stag01");
+            insert_saas_url(&state, uid, "https://example.com/b", "stag02");
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route("/urls", web::get().to(get_user_urls))
+                        .route("/urls/{code}/tags", web::post().to(add_url_tags)),
+                ),
+            )
+            .await;
+            let cookie = format!("{RUS_SESSION_COOKIE}={token}");
+
+            let req = test::TestRequest::post()
+                .uri("/api/urls/stag01/tags")
+                .insert_header(("Cookie", cookie.clone()))
+                .set_json(serde_json::json!({"tags": ["launch"]}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls?tag=launch")
+                .insert_header(("Cookie", cookie))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["total"], 1);
+            assert_eq!(body["urls"][0]["short_code"], "stag01");
+        }
+    }
+}
@@ -1,28 +1,46 @@
+use actix_web::guard::GuardContext;
+use actix_web::http::Method;
 use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
 use rand::Rng;
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
+use std::borrow::Cow;
 use tracing::{debug, error, info};
 
+use super::utm_presets::utm_preset_by_name;
+use super::variants::variants_for_url;
+use crate::analytics::live::ClickEvent;
+use crate::analytics::{click_breakdown, unique_visitors, ClickDetails};
 #[cfg(feature = "standalone")]
 use crate::auth::get_claims;
-use crate::db::AppState;
+use crate::db::{to_db_timestamp, AppState};
 use crate::models::{
-    ClickHistoryEntry, ClickStats, ShortenRequest, ShortenResponse, UpdateUrlNameRequest, UrlEntry,
+    BulkShortenRequest, BulkShortenResult, ClickHistoryEntry, ClickStats, GeoRule, ShortenRequest,
+    ShortenResponse, UnlockUrlRequest, UpdateUrlDestinationRequest, UpdateUrlExpiryRequest,
+    UpdateUrlNameRequest, UpdateUrlPassthroughRequest, UpdateUrlPasswordRequest,
+    UpdateUrlPlatformUrlsRequest, UpdateUrlPublicStatsRequest, UpdateUrlRedirectStatusRequest,
+    UpdateUrlScheduleRequest, UrlEntry, UrlListQuery, UrlListResponse, UrlRevision, UrlStats,
+    UrlVariant,
 };
 #[cfg(feature = "saas")]
 use crate::oidc::session::{lookup_session, AuthenticatedUser};
-use crate::url::{generate_qr_code_png, generate_qr_code_svg, generate_short_code, validate_url};
+use crate::url::{
+    generate_qr_code_png, generate_qr_code_svg, generate_short_code, hash_link_password,
+    normalize_tag, pick_weighted, redirect_cache_control, validate_alias, validate_link_password,
+    validate_redirect_status, validate_url, verify_link_password, Passthrough, PlatformUrls,
+    UtmParams, VARIANT_COOKIE,
+};
 #[cfg(feature = "saas")]
 use actix_web::HttpMessage;
 
 /// Helper to get user_id from request based on mode
 #[cfg(feature = "standalone")]
-fn get_user_id(http_req: &HttpRequest) -> Option<i64> {
+pub(crate) fn get_user_id(http_req: &HttpRequest) -> Option<i64> {
     get_claims(http_req).map(|c| c.user_id)
 }
 
 #[cfg(feature = "saas")]
-fn get_user_id(http_req: &HttpRequest) -> Option<i64> {
+pub(crate) fn get_user_id(http_req: &HttpRequest) -> Option<i64> {
     // Cached identity inserted by an upstream extractor.
     if let Some(u) = http_req.extensions().get::<AuthenticatedUser>().cloned() {
         return Some(u.user_id);
@@ -38,6 +56,322 @@ fn get_user_id(http_req: &HttpRequest) -> Option<i64> {
     Some(user_id)
 }
 
+/// Why a shorten request was refused, as an HTTP status and message
+struct ShortenError {
+    status: actix_web::http::StatusCode,
+    message: String,
+}
+
+impl ShortenError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: actix_web::http::StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn alias_conflict(alias: &str) -> Self {
+        Self {
+            status: actix_web::http::StatusCode::CONFLICT,
+            message: format!("The alias '{}' is already taken", alias),
+        }
+    }
+
+    fn internal(message: &str) -> Self {
+        Self {
+            status: actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.to_string(),
+        }
+    }
+
+    fn into_response(self) -> HttpResponse {
+        HttpResponse::build(self.status).json(serde_json::json!({
+            "error": self.message
+        }))
+    }
+}
+
+/// A validated shorten request, ready to insert
+struct NewLink {
+    url: String,
+    alias: Option<String>,
+    name: Option<String>,
+    expires_at: Option<String>,
+    max_clicks: Option<u64>,
+    password_hash: Option<String>,
+    active_from: Option<String>,
+    active_until: Option<String>,
+    platform_urls: PlatformUrls,
+    redirect_status: Option<u16>,
+    passthrough: Option<Passthrough>,
+    utm: UtmParams,
+    /// Name of the saved preset to fill in unset `utm` fields from
+    utm_preset: Option<String>,
+}
+
+impl NewLink {
+    /// A plain link has no per-link settings and may reuse an existing one
+    fn is_plain(&self) -> bool {
+        self.alias.is_none()
+            && self.name.is_none()
+            && self.expires_at.is_none()
+            && self.max_clicks.is_none()
+            && self.password_hash.is_none()
+            && self.active_from.is_none()
+            && self.active_until.is_none()
+            && self.platform_urls.is_empty()
+            && self.redirect_status.is_none()
+            && self.passthrough.is_none()
+            && self.utm.is_empty()
+            && self.utm_preset.is_none()
+    }
+}
+
+/// Validate a shorten request without touching the database. Passwords are
+/// hashed here so callers can do it before taking the DB lock.
+fn validate_shorten_request(
+    req: &ShortenRequest,
+    max_url_length: usize,
+) -> std::result::Result<NewLink, ShortenError> {
+    if req.url.is_empty() {
+        return Err(ShortenError::bad_request("URL cannot be empty"));
+    }
+
+    // Comprehensive URL validation
+    validate_url(&req.url, max_url_length).map_err(ShortenError::bad_request)?;
+
+    // Treat an empty alias or name the same as none
+    let alias = req
+        .alias
+        .as_deref()
+        .map(str::trim)
+        .filter(|a| !a.is_empty());
+    if let Some(alias) = alias {
+        validate_alias(alias).map_err(ShortenError::bad_request)?;
+    }
+    let name = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty());
+
+    let expires_at = req
+        .expires_at
+        .as_deref()
+        .map(parse_expiry)
+        .transpose()
+        .map_err(ShortenError::bad_request)?;
+
+    let (active_from, active_until) =
+        parse_schedule(req.active_from.as_deref(), req.active_until.as_deref())
+            .map_err(ShortenError::bad_request)?;
+
+    match req.max_clicks {
+        Some(0) => return Err(ShortenError::bad_request("max_clicks must be at least 1")),
+        // Stored as a signed 64-bit SQLite integer
+        Some(max) if i64::try_from(max).is_err() => {
+            return Err(ShortenError::bad_request(format!(
+                "max_clicks must be at most {}",
+                i64::MAX
+            )))
+        }
+        _ => {}
+    }
+
+    let platform_urls = PlatformUrls::parse(
+        req.ios_url.as_deref(),
+        req.android_url.as_deref(),
+        req.desktop_url.as_deref(),
+        max_url_length,
+    )
+    .map_err(ShortenError::bad_request)?;
+
+    if let Some(status) = req.redirect_status {
+        validate_redirect_status(status).map_err(ShortenError::bad_request)?;
+    }
+
+    let passthrough = req
+        .passthrough
+        .as_deref()
+        .map(Passthrough::parse)
+        .transpose()
+        .map_err(ShortenError::bad_request)?;
+
+    let utm = req.utm.normalized().map_err(ShortenError::bad_request)?;
+    let utm_preset = req
+        .utm_preset
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+
+    let password_hash = req.password.as_deref().map(hash_new_password).transpose()?;
+
+    Ok(NewLink {
+        url: req.url.clone(),
+        alias: alias.map(str::to_string),
+        name: name.map(str::to_string),
+        expires_at,
+        max_clicks: req.max_clicks,
+        password_hash,
+        active_from,
+        active_until,
+        platform_urls,
+        redirect_status: req.redirect_status,
+        passthrough,
+        utm,
+        utm_preset: utm_preset.map(str::to_string),
+    })
+}
+
+/// Store a validated link for `user_id`. A plain link reuses the user's
+/// existing plain link for the same URL; any per-link setting always
+/// creates a new one.
+fn insert_link(
+    db: &rusqlite::Connection,
+    user_id: i64,
+    mut link: NewLink,
+    host_url: &str,
+    max_url_length: usize,
+) -> std::result::Result<ShortenResponse, ShortenError> {
+    // Campaign parameters become part of the URL; a preset fills in any
+    // field the request left out
+    if let Some(name) = link.utm_preset.take() {
+        let preset = match utm_preset_by_name(db, user_id, &name) {
+            Ok(Some(preset)) => preset,
+            Ok(None) => {
+                return Err(ShortenError::bad_request(format!(
+                    "UTM preset '{name}' not found"
+                )))
+            }
+            Err(e) => {
+                error!(user_id, error = %e, "Failed to load UTM preset");
+                return Err(ShortenError::internal("Failed to create short URL"));
+            }
+        };
+        link.utm = link.utm.or(preset);
+    }
+    link.url = link
+        .utm
+        .apply(&link.url, max_url_length)
+        .map_err(ShortenError::bad_request)?;
+
+    if link.is_plain() {
+        let existing: rusqlite::Result<String> = db.query_row(
+            "SELECT short_code FROM urls
+             WHERE user_id = ?1 AND original_url = ?2 AND name IS NULL
+               AND expires_at IS NULL AND max_clicks IS NULL
+               AND password_hash IS NULL
+               AND active_from IS NULL AND active_until IS NULL
+               AND ios_url IS NULL AND android_url IS NULL AND desktop_url IS NULL
+               AND redirect_status IS NULL AND passthrough IS NULL
+               AND utm_source IS NULL AND utm_medium IS NULL AND utm_campaign IS NULL
+               AND utm_term IS NULL AND utm_content IS NULL",
+            params![user_id, &link.url],
+            |row| row.get(0),
+        );
+        if let Ok(short_code) = existing {
+            return Ok(ShortenResponse {
+                short_url: format!("{}/{}", host_url, short_code),
+                short_code,
+                original_url: link.url,
+                name: None,
+                expires_at: None,
+                max_clicks: None,
+                password_protected: false,
+                active_from: None,
+                active_until: None,
+                ios_url: None,
+                android_url: None,
+                desktop_url: None,
+                redirect_status: None,
+                passthrough: None,
+                utm: UtmParams::default(),
+            });
+        }
+    }
+
+    let code_taken = |code: &str| -> bool {
+        db.query_row(
+            "SELECT COUNT(*) FROM urls WHERE short_code = ?1",
+            params![code],
+            |row| row.get(0),
+        )
+        .map(|count: i64| count > 0)
+        .unwrap_or(false)
+    };
+
+    let short_code = match &link.alias {
+        Some(alias) if code_taken(alias) => return Err(ShortenError::alias_conflict(alias)),
+        Some(alias) => alias.clone(),
+        None => {
+            // Generate a unique short code
+            let mut short_code = generate_short_code();
+            while code_taken(&short_code) {
+                short_code = generate_short_code();
+            }
+            short_code
+        }
+    };
+
+    // Insert URL into database
+    match db.execute(
+        "INSERT INTO urls (user_id, original_url, short_code, name, expires_at, max_clicks,
+                           password_hash, active_from, active_until, ios_url, android_url,
+                           desktop_url, redirect_status, passthrough, utm_source, utm_medium,
+                           utm_campaign, utm_term, utm_content)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                 ?18, ?19)",
+        params![
+            user_id,
+            &link.url,
+            &short_code,
+            link.name.as_deref(),
+            link.expires_at.as_deref(),
+            link.max_clicks,
+            link.password_hash.as_deref(),
+            link.active_from.as_deref(),
+            link.active_until.as_deref(),
+            link.platform_urls.ios_url.as_deref(),
+            link.platform_urls.android_url.as_deref(),
+            link.platform_urls.desktop_url.as_deref(),
+            link.redirect_status,
+            link.passthrough.map(Passthrough::as_str),
+            link.utm.utm_source.as_deref(),
+            link.utm.utm_medium.as_deref(),
+            link.utm.utm_campaign.as_deref(),
+            link.utm.utm_term.as_deref(),
+            link.utm.utm_content.as_deref()
+        ],
+    ) {
+        Ok(_) => {
+            info!(user_id, short_code = %short_code, "URL shortened");
+            Ok(ShortenResponse {
+                short_url: format!("{}/{}", host_url, short_code),
+                short_code,
+                original_url: link.url,
+                name: link.name,
+                expires_at: link.expires_at,
+                max_clicks: link.max_clicks,
+                password_protected: link.password_hash.is_some(),
+                active_from: link.active_from,
+                active_until: link.active_until,
+                ios_url: link.platform_urls.ios_url,
+                android_url: link.platform_urls.android_url,
+                desktop_url: link.platform_urls.desktop_url,
+                redirect_status: link.redirect_status,
+                passthrough: link.passthrough.map(|mode| mode.as_str().to_string()),
+                utm: link.utm,
+            })
+        }
+        // Lost a race with another request claiming the same alias
+        Err(rusqlite::Error::SqliteFailure(err, _))
+            if link.alias.is_some() && err.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            Err(ShortenError::alias_conflict(&short_code))
+        }
+        Err(e) => {
+            error!(user_id, error = %e, "Failed to insert shortened URL");
+            Err(ShortenError::internal("Failed to create short URL"))
+        }
+    }
+}
+
 /// Protected API endpoint to shorten a URL
 pub async fn shorten_url(
     data: web::Data<AppState>,
@@ -54,126 +388,742 @@ pub async fn shorten_url(
         }
     };
 
-    // Validate URL
-    if req_payload.url.is_empty() {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "URL cannot be empty"
-        })));
+    let link = match validate_shorten_request(&req_payload, data.config.max_url_length) {
+        Ok(link) => link,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    match insert_link(
+        &db,
+        user_id,
+        link,
+        &data.config.host_url,
+        data.config.max_url_length,
+    ) {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(e) => Ok(e.into_response()),
     }
+}
 
-    // Comprehensive URL validation
-    if let Err(error_message) = validate_url(&req_payload.url, data.config.max_url_length) {
+/// Maximum number of URLs accepted by one bulk shorten request
+pub const BULK_SHORTEN_MAX_ITEMS: usize = 500;
+
+/// Protected API endpoint to shorten many URLs in one request. Every item is
+/// validated on its own and all inserts share one transaction; the response
+/// lists a result per item, in request order.
+pub async fn bulk_shorten_urls(
+    data: web::Data<AppState>,
+    req_payload: web::Json<BulkShortenRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let items = req_payload.into_inner().items;
+    if items.is_empty() || items.len() > BULK_SHORTEN_MAX_ITEMS {
         return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": error_message
+            "error": format!("items must contain between 1 and {} URLs", BULK_SHORTEN_MAX_ITEMS)
         })));
     }
 
-    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    // Validate (and hash passwords) before taking the DB lock
+    let validated: Vec<_> = items
+        .iter()
+        .map(|item| validate_shorten_request(item, data.config.max_url_length))
+        .collect();
 
-    // Check if URL is already shortened by this user
-    let mut stmt = db
-        .prepare("SELECT short_code FROM urls WHERE user_id = ?1 AND original_url = ?2")
-        .map_err(|e| {
-            error!(error = %e, "shorten_url: DB prepare failed");
-            actix_web::error::ErrorInternalServerError("Database error")
-        })?;
+    let mut db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    let tx = db.transaction().map_err(|e| {
+        error!(user_id, error = %e, "bulk_shorten_urls: failed to begin transaction");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    let mut created = 0;
+    let results: Vec<BulkShortenResult> = validated
+        .into_iter()
+        .enumerate()
+        .map(|(index, link)| {
+            let inserted = link.and_then(|link| {
+                insert_link(
+                    &tx,
+                    user_id,
+                    link,
+                    &data.config.host_url,
+                    data.config.max_url_length,
+                )
+            });
+            match inserted {
+                Ok(link) => {
+                    created += 1;
+                    BulkShortenResult {
+                        index,
+                        status: actix_web::http::StatusCode::OK.as_u16(),
+                        link: Some(link),
+                        error: None,
+                    }
+                }
+                Err(e) => BulkShortenResult {
+                    index,
+                    status: e.status.as_u16(),
+                    link: None,
+                    error: Some(e.message),
+                },
+            }
+        })
+        .collect();
+
+    tx.commit().map_err(|e| {
+        error!(user_id, error = %e, "bulk_shorten_urls: commit failed");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    info!(user_id, total = results.len(), created, "Bulk shorten");
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "succeeded": created,
+        "failed": results.len() - created,
+        "results": results
+    })))
+}
+
+/// Parse an RFC 3339 timestamp supplied for `field` into UTC
+fn parse_timestamp(field: &str, value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|ts| ts.with_timezone(&Utc))
+        .map_err(|_| format!("{} must be an RFC 3339 timestamp", field))
+}
+
+/// Parse an RFC 3339 expiry timestamp into the UTC `YYYY-MM-DD HH:MM:SS`
+/// format used for every other timestamp column. Must be in the future.
+fn parse_expiry(value: &str) -> std::result::Result<String, String> {
+    let ts = parse_timestamp("expires_at", value)?;
 
-    if let Ok(short_code) = stmt.query_row(params![user_id, &req_payload.url], |row| {
-        row.get::<_, String>(0)
-    }) {
-        return Ok(HttpResponse::Ok().json(ShortenResponse {
-            short_code: short_code.clone(),
-            short_url: format!("{}/{}", data.config.host_url, short_code),
-            original_url: req_payload.url.clone(),
-        }));
+    if ts <= Utc::now() {
+        return Err("expires_at must be in the future".to_string());
     }
 
-    // Generate a unique short code
-    let mut short_code = generate_short_code();
-    loop {
-        let exists: bool = db
-            .query_row(
-                "SELECT COUNT(*) FROM urls WHERE short_code = ?1",
-                params![&short_code],
-                |row| row.get(0),
-            )
-            .map(|count: i64| count > 0)
-            .unwrap_or(false);
+    Ok(to_db_timestamp(ts))
+}
 
-        if !exists {
-            break;
+/// Activation window as stored: `(active_from, active_until)`
+type Schedule = (Option<String>, Option<String>);
+
+/// Parse an activation window. Either side may be open; the window must
+/// close in the future and open before it closes.
+fn parse_schedule(
+    active_from: Option<&str>,
+    active_until: Option<&str>,
+) -> std::result::Result<Schedule, String> {
+    let from = active_from
+        .map(|v| parse_timestamp("active_from", v))
+        .transpose()?;
+    let until = active_until
+        .map(|v| parse_timestamp("active_until", v))
+        .transpose()?;
+
+    if let Some(until) = until {
+        if until <= Utc::now() {
+            return Err("active_until must be in the future".to_string());
+        }
+        if from.is_some_and(|from| from >= until) {
+            return Err("active_from must be before active_until".to_string());
         }
-        short_code = generate_short_code();
     }
 
-    // Insert URL into database
-    match db.execute(
-        "INSERT INTO urls (user_id, original_url, short_code) VALUES (?1, ?2, ?3)",
-        params![user_id, &req_payload.url, &short_code],
-    ) {
-        Ok(_) => {
-            info!(user_id, short_code = %short_code, "URL shortened");
-            Ok(HttpResponse::Ok().json(ShortenResponse {
-                short_code: short_code.clone(),
-                short_url: format!("{}/{}", data.config.host_url, short_code),
-                original_url: req_payload.url.clone(),
-            }))
+    Ok((from.map(to_db_timestamp), until.map(to_db_timestamp)))
+}
+
+/// Validate and hash a new link password
+fn hash_new_password(password: &str) -> std::result::Result<String, ShortenError> {
+    validate_link_password(password).map_err(ShortenError::bad_request)?;
+    hash_link_password(password).map_err(|e| {
+        error!(error = %e, "Failed to hash link password");
+        ShortenError::internal("Failed to set link password")
+    })
+}
+
+/// Render the shared "link unavailable" page used for links that exist but
+/// must not redirect right now
+fn link_unavailable(
+    status: actix_web::http::StatusCode,
+    title: &str,
+    message: &str,
+    reason: &str,
+) -> HttpResponse {
+    let html = include_str!("../../static/unavailable.html")
+        .replace("{{STATUS}}", status.as_str())
+        .replace("{{TITLE}}", title)
+        .replace("{{MESSAGE}}", message)
+        .replace("{{REASON}}", reason);
+    // Never cache: the owner can change expiry, schedule or limits at any time
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(html)
+}
+
+/// Columns selected for every `UrlEntry`, in the order `url_entry_from_row` reads them
+const URL_ENTRY_COLUMNS: &str = "original_url, short_code, name, clicks, expires_at, max_clicks, \
+     password_hash IS NOT NULL, active_from, active_until, \
+     (SELECT group_concat(t.name, ',') FROM url_tags ut JOIN tags t ON t.id = ut.tag_id \
+      WHERE ut.url_id = urls.id), created_at, bot_clicks, public_stats, \
+     ios_url, android_url, desktop_url, redirect_status, passthrough, \
+     utm_source, utm_medium, utm_campaign, utm_term, utm_content";
+
+/// Number of columns in `URL_ENTRY_COLUMNS`; extra columns selected after
+/// them start at this index
+const URL_ENTRY_COLUMN_COUNT: usize = 23;
+
+fn url_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<UrlEntry> {
+    let clicks: u64 = row.get(3)?;
+    let max_clicks: Option<u64> = row.get(5)?;
+    Ok(UrlEntry {
+        original_url: row.get(0)?,
+        short_code: row.get(1)?,
+        name: row.get(2)?,
+        clicks,
+        bot_clicks: row.get(11)?,
+        expires_at: row.get(4)?,
+        max_clicks,
+        remaining_clicks: max_clicks.map(|max| max.saturating_sub(clicks)),
+        password_protected: row.get(6)?,
+        active_from: row.get(7)?,
+        active_until: row.get(8)?,
+        public_stats: row.get(12)?,
+        ios_url: row.get(13)?,
+        android_url: row.get(14)?,
+        desktop_url: row.get(15)?,
+        redirect_status: row.get(16)?,
+        passthrough: row.get(17)?,
+        utm: UtmParams {
+            utm_source: row.get(18)?,
+            utm_medium: row.get(19)?,
+            utm_campaign: row.get(20)?,
+            utm_term: row.get(21)?,
+            utm_content: row.get(22)?,
+        },
+        // Tag names cannot contain commas, so the aggregate splits cleanly
+        tags: row
+            .get::<_, Option<String>>(9)?
+            .map(|joined| {
+                let mut tags: Vec<String> = joined.split(',').map(str::to_string).collect();
+                tags.sort();
+                tags
+            })
+            .unwrap_or_default(),
+        created_at: row.get(10)?,
+    })
+}
+
+/// Redirect target of a short code as seen by the public endpoints
+struct RedirectTarget {
+    url_id: i64,
+    user_id: i64,
+    short_code: String,
+    original_url: String,
+    expired: bool,
+    /// Start of the activation window, if it has not opened yet
+    pending_until: Option<String>,
+    /// Whether the activation window has already closed
+    window_closed: bool,
+    password_hash: Option<String>,
+    platform_urls: PlatformUrls,
+    /// Geo rule for the visitor's country, if the link has one
+    geo_rule: Option<GeoRule>,
+    /// A/B test destinations (empty when the link is not split)
+    variants: Vec<UrlVariant>,
+    /// Whether returning visitors get the variant they got before
+    sticky_variants: bool,
+    /// Status set on the link; `None` uses the instance default
+    redirect_status: Option<u16>,
+    /// Whether the link has any geo rules, not just one for this visitor
+    has_geo_rules: bool,
+    /// Whether the link has an expiry, activation window or click cap
+    has_limits: bool,
+    /// How extra path and query are passed on (`None`: they are not)
+    passthrough: Option<Passthrough>,
+    /// Whether the link has reached its click cap
+    used_up: bool,
+}
+
+/// Where one visitor is sent, and why
+struct Destination<'a> {
+    url: Cow<'a, str>,
+    /// Country of the geo rule that chose `url`
+    geo_rule: Option<&'a str>,
+    /// Id of the A/B test variant that chose `url`
+    variant_id: Option<i64>,
+}
+
+impl RedirectTarget {
+    /// Where to send a visitor. The override for their platform comes
+    /// first, then the geo rule for their country, then an A/B test variant
+    /// (the `remembered` one on sticky links, if it still exists), then the
+    /// link's URL. Bots always get the link's URL so previews show the
+    /// website rather than an app store or a random variant.
+    fn destination(&self, details: &ClickDetails, remembered: Option<i64>) -> Destination<'_> {
+        let mut destination = Destination {
+            url: Cow::Borrowed(&self.original_url),
+            geo_rule: None,
+            variant_id: None,
+        };
+        if details.is_bot {
+            return destination;
         }
-        Err(e) => {
-            error!(user_id, error = %e, "Failed to insert shortened URL");
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to create short URL"
-            })))
+        if let Some(url) = self.platform_urls.for_client(&details.client) {
+            destination.url = Cow::Borrowed(url);
+        } else if let Some(rule) = &self.geo_rule {
+            destination.url = Cow::Borrowed(&rule.url);
+            destination.geo_rule = Some(&rule.country);
+        } else if !self.variants.is_empty() {
+            let variant = remembered
+                .filter(|_| self.sticky_variants)
+                .and_then(|id| self.variants.iter().find(|v| v.id == id))
+                .unwrap_or_else(|| {
+                    let weights: Vec<u32> = self.variants.iter().map(|v| v.weight).collect();
+                    let roll = rand::thread_rng().gen_range(0..weights.iter().sum::<u32>());
+                    &self.variants[pick_weighted(&weights, roll)]
+                });
+            destination.url = Cow::Borrowed(&variant.url);
+            destination.variant_id = Some(variant.id);
+        }
+        destination
+    }
+
+    /// Whether every visitor is sent to the same place for as long as the
+    /// link exists, so a permanent redirect to it may be cached
+    fn is_cacheable(&self) -> bool {
+        self.platform_urls.is_empty()
+            && !self.has_geo_rules
+            && self.variants.is_empty()
+            && !self.has_limits
+            && self.password_hash.is_none()
+    }
+
+    /// Redirect to `destination` with `status`, remembering its variant on
+    /// sticky links
+    fn redirect(
+        &self,
+        status: actix_web::http::StatusCode,
+        destination: &Destination,
+    ) -> HttpResponse {
+        let mut response = HttpResponse::build(status);
+        response.append_header(("Location", destination.url.as_ref()));
+        response.insert_header((
+            "Cache-Control",
+            redirect_cache_control(status, self.is_cacheable()),
+        ));
+        if let (true, Some(id)) = (self.sticky_variants, destination.variant_id) {
+            response.cookie(
+                actix_web::cookie::Cookie::build(VARIANT_COOKIE, id.to_string())
+                    .path(format!("/{}", self.short_code))
+                    .max_age(actix_web::cookie::time::Duration::days(30))
+                    .http_only(true)
+                    .same_site(actix_web::cookie::SameSite::Lax)
+                    .finish(),
+            );
+        }
+        response.finish()
+    }
+
+    /// Whether the request may be served at all: a path after the code is
+    /// only accepted by links with passthrough on
+    fn accepts_tail(&self, tail: Option<&str>) -> bool {
+        tail.is_none() || self.passthrough.is_some()
+    }
+
+    /// Append the request's extra path and query to `destination` on
+    /// passthrough links. On failure the error page to show instead.
+    fn pass_through(
+        &self,
+        destination: &mut Destination,
+        tail: Option<&str>,
+        query: &str,
+        max_url_length: usize,
+    ) -> std::result::Result<(), HttpResponse> {
+        let Some(mode) = self.passthrough else {
+            return Ok(());
+        };
+        match mode.apply(&destination.url, tail.unwrap_or(""), query, max_url_length) {
+            Ok(url) => {
+                destination.url = Cow::Owned(url);
+                Ok(())
+            }
+            Err(e) => {
+                debug!(short_code = %self.short_code, error = %e, "Passthrough refused");
+                Err(link_unavailable(
+                    actix_web::http::StatusCode::BAD_REQUEST,
+                    "Invalid Link",
+                    "The extra path or parameters added to this short link do not make a valid destination.",
+                    "invalid passthrough",
+                ))
+            }
         }
     }
+
+    /// Page to show instead of redirecting when the link is outside its
+    /// expiry or activation window
+    fn unavailable_response(&self) -> Option<HttpResponse> {
+        if self.expired {
+            return Some(link_expired());
+        }
+        if let Some(active_from) = &self.pending_until {
+            return Some(link_unavailable(
+                actix_web::http::StatusCode::FORBIDDEN,
+                "Link Not Yet Available",
+                &format!("This short link has been scheduled by its owner and opens on {active_from} UTC."),
+                "link not yet active",
+            ));
+        }
+        if self.window_closed {
+            return Some(link_unavailable(
+                actix_web::http::StatusCode::GONE,
+                "Link No Longer Active",
+                "This short link was only scheduled to be available for a limited time and that window has ended.",
+                "activation window ended",
+            ));
+        }
+        None
+    }
+}
+
+/// Look up `code` along with its geo rule for `country` (the visitor's,
+/// when a GeoIP database is loaded)
+fn lookup_redirect_target(
+    db: &rusqlite::Connection,
+    code: &str,
+    country: Option<&str>,
+) -> rusqlite::Result<RedirectTarget> {
+    db.query_row(
+        "SELECT id, original_url,
+                expires_at IS NOT NULL AND expires_at <= datetime('now'),
+                CASE WHEN active_from > datetime('now') THEN active_from END,
+                active_until IS NOT NULL AND active_until <= datetime('now'),
+                password_hash, user_id, short_code, ios_url, android_url, desktop_url,
+                g.country, g.destination, sticky_variants, redirect_status,
+                EXISTS (SELECT 1 FROM geo_rules WHERE url_id = urls.id),
+                expires_at IS NOT NULL OR active_from IS NOT NULL
+                    OR active_until IS NOT NULL OR max_clicks IS NOT NULL,
+                passthrough, max_clicks IS NOT NULL AND clicks >= max_clicks
+         FROM urls
+         LEFT JOIN geo_rules g ON g.url_id = urls.id AND g.country = ?2
+         WHERE short_code = ?1",
+        params![code, country],
+        |row| {
+            Ok(RedirectTarget {
+                url_id: row.get(0)?,
+                original_url: row.get(1)?,
+                expired: row.get(2)?,
+                pending_until: row.get(3)?,
+                window_closed: row.get(4)?,
+                password_hash: row.get(5)?,
+                user_id: row.get(6)?,
+                short_code: row.get(7)?,
+                platform_urls: PlatformUrls {
+                    ios_url: row.get(8)?,
+                    android_url: row.get(9)?,
+                    desktop_url: row.get(10)?,
+                },
+                geo_rule: match (row.get(11)?, row.get(12)?) {
+                    (Some(country), Some(url)) => Some(GeoRule { country, url }),
+                    _ => None,
+                },
+                variants: Vec::new(),
+                sticky_variants: row.get(13)?,
+                redirect_status: row.get(14)?,
+                has_geo_rules: row.get(15)?,
+                has_limits: row.get(16)?,
+                passthrough: row
+                    .get::<_, Option<String>>(17)?
+                    .and_then(|mode| Passthrough::parse(&mode).ok()),
+                used_up: row.get(18)?,
+            })
+        },
+    )
+    .and_then(|mut target| {
+        target.variants = variants_for_url(db, target.url_id)?;
+        Ok(target)
+    })
+}
+
+/// Variant id from the sticky-variant cookie, if the visitor has one
+fn remembered_variant(http_req: &HttpRequest) -> Option<i64> {
+    http_req.cookie(VARIANT_COOKIE)?.value().parse().ok()
+}
+
+/// Count a click (on the link and on the variant it was sent to, if any),
+/// record it in the history with the geo rule that chose its destination,
+/// and publish it to live listeners. Returns `false` without counting
+/// anything when the link has reached its click cap.
+fn record_click(
+    db: &rusqlite::Connection,
+    data: &AppState,
+    target: &RedirectTarget,
+    details: &ClickDetails,
+    destination: &Destination,
+) -> bool {
+    let url_id = target.url_id;
+    // The check and increment are a single statement so concurrent requests
+    // can never push `clicks` past `max_clicks`. Bots are counted separately
+    // and never use up a capped link, but are not let through once it is.
+    let counter = if details.is_bot {
+        "bot_clicks"
+    } else {
+        "clicks"
+    };
+    let counted = db
+        .query_row(
+            &format!(
+                "UPDATE urls SET {counter} = {counter} + 1
+                 WHERE id = ?1 AND (max_clicks IS NULL OR clicks < max_clicks)
+                 RETURNING clicks, bot_clicks"
+            ),
+            params![url_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional();
+    if matches!(counted, Ok(None)) {
+        return false;
+    }
+
+    if let Some(variant_id) = destination.variant_id {
+        let _ = db.execute(
+            "UPDATE url_variants SET clicks = clicks + 1 WHERE id = ?1",
+            params![variant_id],
+        );
+    }
+
+    // Record click in history
+    let _ = db.execute(
+        "INSERT INTO click_history
+             (url_id, referrer_host, browser, os, device, visitor_hash, country, region, city,
+              is_bot, geo_rule)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            url_id,
+            details.referrer_host,
+            details.client.browser,
+            details.client.os,
+            details.client.device,
+            details.visitor_hash(db),
+            details.location.country,
+            details.location.region,
+            details.location.city,
+            details.is_bot,
+            destination.geo_rule
+        ],
+    );
+
+    // Sending only fails when no dashboard is listening
+    if let Ok(Some(counts)) = counted {
+        let event = ClickEvent::new(target.user_id, &target.short_code, details, counts);
+        let _ = data.click_events.send(event);
+    }
+
+    // Cleanup old clicks periodically (1% chance)
+    if rand::thread_rng().gen_range(0..100) == 0 {
+        crate::db::cleanup_old_clicks(db, data.config.click_retention_days);
+    }
+
+    true
+}
+
+pub(crate) fn link_not_found() -> HttpResponse {
+    let html = include_str!("../../static/404.html");
+    HttpResponse::NotFound()
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
+
+fn link_expired() -> HttpResponse {
+    link_unavailable(
+        actix_web::http::StatusCode::GONE,
+        "Link Expired",
+        "This short link had an expiry date set by its owner and is no longer active.",
+        "link expired",
+    )
+}
+
+fn link_used_up() -> HttpResponse {
+    link_unavailable(
+        actix_web::http::StatusCode::GONE,
+        "Link Used Up",
+        "This short link could only be opened a limited number of times and has reached its limit.",
+        "click limit reached",
+    )
+}
+
+/// Render the password prompt for a protected link
+fn password_prompt(status: actix_web::http::StatusCode, error: Option<&str>) -> HttpResponse {
+    let error_html = error
+        .map(|message| format!(r#"<div class="error show">{}</div>"#, message))
+        .unwrap_or_default();
+    let html = include_str!("../../static/password.html").replace("{{ERROR}}", &error_html);
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(html)
+}
+
+/// Path of a short link: `/{code}`, or `/{code}/{tail}` with anything after
+/// the code in `tail`
+#[derive(serde::Deserialize)]
+pub struct RedirectPath {
+    code: String,
+    tail: Option<String>,
 }
 
 /// Public endpoint to redirect to the original URL
 pub async fn redirect_url(
     data: web::Data<AppState>,
-    code: web::Path<String>,
+    path: web::Path<RedirectPath>,
+    http_req: HttpRequest,
 ) -> Result<HttpResponse> {
+    let code = &path.code;
+    let details = ClickDetails::from_request(&http_req, &data.config, data.geoip.as_ref());
     let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
 
-    // Get URL ID and original URL
-    let result: rusqlite::Result<(i64, String)> = db.query_row(
-        "SELECT id, original_url FROM urls WHERE short_code = ?1",
-        params![code.as_str()],
-        |row| Ok((row.get(0)?, row.get(1)?)),
-    );
+    let country = details.location.country.as_deref();
+    let target = match lookup_redirect_target(&db, code.as_str(), country) {
+        Ok(target) => target,
+        Err(_) => {
+            debug!(short_code = %code.as_str(), "Redirect failed: code not found");
+            return Ok(link_not_found());
+        }
+    };
+    if !target.accepts_tail(path.tail.as_deref()) {
+        debug!(short_code = %code.as_str(), "Redirect failed: extra path without passthrough");
+        return Ok(link_not_found());
+    }
 
-    match result {
-        Ok((url_id, original_url)) => {
-            // Increment click count (legacy counter)
-            let _ = db.execute(
-                "UPDATE urls SET clicks = clicks + 1 WHERE id = ?1",
-                params![url_id],
-            );
+    if let Some(response) = target.unavailable_response() {
+        debug!(short_code = %code.as_str(), "Redirect refused: link not active");
+        return Ok(response);
+    }
 
-            // Record click in history
-            let _ = db.execute(
-                "INSERT INTO click_history (url_id) VALUES (?1)",
-                params![url_id],
-            );
+    // Protected links only count a click once the password has been accepted
+    if target.password_hash.is_some() {
+        debug!(short_code = %code.as_str(), "Redirect paused: password required");
+        return Ok(password_prompt(actix_web::http::StatusCode::OK, None));
+    }
 
-            // Cleanup old clicks periodically (1% chance)
-            if rand::thread_rng().gen_range(0..100) == 0 {
-                crate::db::cleanup_old_clicks(&db, data.config.click_retention_days);
-            }
+    let mut destination = target.destination(&details, remembered_variant(&http_req));
+    if let Err(response) = target.pass_through(
+        &mut destination,
+        path.tail.as_deref(),
+        http_req.query_string(),
+        data.config.max_url_length,
+    ) {
+        return Ok(response);
+    }
+    // HEAD and OPTIONS (link scanners, `curl -I`) see the redirect without
+    // counting a click or using up a capped link
+    let counted = !matches!(*http_req.method(), Method::HEAD | Method::OPTIONS);
+    if (counted && !record_click(&db, &data, &target, &details, &destination))
+        || (!counted && target.used_up)
+    {
+        debug!(short_code = %code.as_str(), "Redirect refused: click limit reached");
+        return Ok(link_used_up());
+    }
+
+    debug!(short_code = %code.as_str(), geo_rule = destination.geo_rule, variant = destination.variant_id, "Redirect");
+    let status = target
+        .redirect_status
+        .unwrap_or(data.config.default_redirect_status);
+    let status =
+        actix_web::http::StatusCode::from_u16(status).unwrap_or(actix_web::http::StatusCode::FOUND);
+    Ok(target.redirect(status, &destination))
+}
+
+/// Route guard sending POSTs for password-protected links to [`unlock_url`].
+/// Other POST, PUT, PATCH and DELETE requests go to [`redirect_url`] so that
+/// 307 and 308 links can forward them with their body.
+pub fn password_protected_link(ctx: &GuardContext) -> bool {
+    let Some(data) = ctx.app_data::<web::Data<AppState>>() else {
+        return false;
+    };
+    let path = ctx.head().uri.path();
+    let code = path.trim_start_matches('/').split('/').next().unwrap_or("");
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    db.query_row(
+        "SELECT password_hash IS NOT NULL FROM urls WHERE short_code = ?1",
+        params![code],
+        |row| row.get(0),
+    )
+    .unwrap_or(false)
+}
+
+/// Route guard for the methods other than GET that [`redirect_url`] counts
+/// as clicks: the ones 307 and 308 redirects exist to preserve
+pub fn forwarded_method(ctx: &GuardContext) -> bool {
+    matches!(
+        ctx.head().method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
 
-            debug!(short_code = %code.as_str(), "Redirect");
-            Ok(HttpResponse::Found()
-                .append_header(("Location", original_url))
-                .finish())
+/// Public endpoint that checks the password of a protected link and, if it
+/// matches, redirects to the original URL
+pub async fn unlock_url(
+    data: web::Data<AppState>,
+    path: web::Path<RedirectPath>,
+    form: web::Form<UnlockUrlRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let code = &path.code;
+    let details = ClickDetails::from_request(&http_req, &data.config, data.geoip.as_ref());
+    let target = {
+        let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+        let country = details.location.country.as_deref();
+        match lookup_redirect_target(&db, code.as_str(), country) {
+            Ok(target) => target,
+            Err(_) => return Ok(link_not_found()),
         }
-        Err(_) => {
-            debug!(short_code = %code.as_str(), "Redirect failed: code not found");
-            let html = include_str!("../../static/404.html");
-            Ok(HttpResponse::NotFound()
-                .content_type("text/html; charset=utf-8")
-                .body(html))
+    };
+    if !target.accepts_tail(path.tail.as_deref()) {
+        return Ok(link_not_found());
+    }
+
+    if let Some(response) = target.unavailable_response() {
+        return Ok(response);
+    }
+
+    // Verify without holding the DB lock; Argon2 is deliberately slow
+    if let Some(hash) = target.password_hash.as_deref() {
+        if !verify_link_password(&form.password, hash) {
+            info!(short_code = %code.as_str(), "Incorrect link password");
+            return Ok(password_prompt(
+                actix_web::http::StatusCode::UNAUTHORIZED,
+                Some("Incorrect password. Please try again."),
+            ));
         }
     }
+
+    let mut destination = target.destination(&details, remembered_variant(&http_req));
+    if let Err(response) = target.pass_through(
+        &mut destination,
+        path.tail.as_deref(),
+        http_req.query_string(),
+        data.config.max_url_length,
+    ) {
+        return Ok(response);
+    }
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    if !record_click(&db, &data, &target, &details, &destination) {
+        return Ok(link_used_up());
+    }
+
+    debug!(short_code = %code.as_str(), "Redirect after password check");
+    // 303 so the browser follows up with a GET rather than re-posting the
+    // form, whatever status the link normally redirects with
+    Ok(target.redirect(actix_web::http::StatusCode::SEE_OTHER, &destination))
 }
 
 /// Protected API endpoint to get URL statistics
@@ -195,30 +1145,93 @@ pub async fn get_stats(
     let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
 
     // Get URL entry for this user
-    let result: rusqlite::Result<UrlEntry> = db.query_row(
-        "SELECT original_url, short_code, name, clicks FROM urls WHERE short_code = ?1 AND user_id = ?2",
+    let result: rusqlite::Result<(i64, UrlEntry)> = db.query_row(
+        &format!("SELECT {URL_ENTRY_COLUMNS}, id FROM urls WHERE short_code = ?1 AND user_id = ?2"),
         params![code.as_str(), user_id],
-        |row| {
-            Ok(UrlEntry {
-                original_url: row.get(0)?,
-                short_code: row.get(1)?,
-                name: row.get(2)?,
-                clicks: row.get(3)?,
-            })
-        },
+        |row| Ok((row.get(URL_ENTRY_COLUMN_COUNT)?, url_entry_from_row(row)?)),
     );
 
-    match result {
-        Ok(entry) => Ok(HttpResponse::Ok().json(entry)),
-        Err(_) => Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Short URL not found or not owned by you"
-        }))),
+    let (url_id, entry) = match result {
+        Ok(found) => found,
+        Err(_) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Short URL not found or not owned by you"
+            })));
+        }
+    };
+
+    let breakdown = click_breakdown(&db, url_id).map_err(|e| {
+        error!(url_id, error = %e, "get_stats: failed to load click breakdown");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+    let unique_visitors = unique_visitors(&db, url_id).map_err(|e| {
+        error!(url_id, error = %e, "get_stats: failed to count unique visitors");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+    let variants = variants_for_url(&db, url_id).map_err(|e| {
+        error!(url_id, error = %e, "get_stats: failed to load variants");
+        actix_web::error::ErrorInternalServerError("Database error")
+    })?;
+
+    Ok(HttpResponse::Ok().json(UrlStats {
+        entry,
+        unique_visitors,
+        breakdown,
+        variants,
+    }))
+}
+
+/// Default and maximum page size for `GET /api/urls`
+const URL_PAGE_DEFAULT: u32 = 50;
+const URL_PAGE_MAX: u32 = 200;
+
+/// Turn free text into an FTS5 query: every word must match, as a prefix,
+/// and FTS5 syntax characters in the input are treated literally
+fn fts_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Parse a date-range filter given as RFC 3339 or a bare `YYYY-MM-DD` (UTC midnight)
+fn parse_date_filter(field: &str, value: &str) -> std::result::Result<String, String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d") {
+        return Ok(to_db_timestamp(
+            date.and_time(chrono::NaiveTime::MIN).and_utc(),
+        ));
     }
+    parse_timestamp(field, value)
+        .map(to_db_timestamp)
+        .map_err(|_| format!("{} must be an RFC 3339 timestamp or YYYY-MM-DD date", field))
+}
+
+/// `ORDER BY` clause for the requested sort; `id` breaks ties so pages are stable
+fn url_list_order(sort: Option<&str>, order: Option<&str>) -> std::result::Result<String, String> {
+    let (column, default_desc) = match sort.unwrap_or("created") {
+        "created" => ("created_at", true),
+        "clicks" => ("clicks", true),
+        // Unnamed links sort after named ones in either direction
+        "name" => ("name IS NULL, name COLLATE NOCASE", false),
+        _ => return Err("sort must be one of: created, clicks, name".to_string()),
+    };
+    let desc = match order {
+        None => default_desc,
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return Err("order must be 'asc' or 'desc'".to_string()),
+    };
+    let direction = if desc { "DESC" } else { "ASC" };
+    Ok(format!("{column} {direction}, id {direction}"))
 }
 
-/// Protected endpoint to get all URLs for the current user
+/// Protected endpoint to list the current user's URLs one page at a time.
+/// Supports tag filtering (`?tag=clients` also matches `clients/acme`),
+/// full-text search, sorting and created-date ranges.
 pub async fn get_user_urls(
     data: web::Data<AppState>,
+    query: web::Query<UrlListQuery>,
     http_req: HttpRequest,
 ) -> Result<HttpResponse> {
     let user_id = match get_user_id(&http_req) {
@@ -230,28 +1243,109 @@ pub async fn get_user_urls(
         }
     };
 
-    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    let bad_request = |error_message: String| {
+        Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": error_message
+        })))
+    };
 
-    let mut stmt = db
-        .prepare(
-            "SELECT original_url, short_code, name, clicks FROM urls WHERE user_id = ?1 ORDER BY created_at DESC",
-        )
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    let tag = match query.tag.as_deref().map(normalize_tag).transpose() {
+        Ok(tag) => tag,
+        Err(error_message) => return bad_request(error_message),
+    };
+    let search = query.q.as_deref().and_then(fts_query);
+    let created_after = match query
+        .created_after
+        .as_deref()
+        .map(|v| parse_date_filter("created_after", v))
+        .transpose()
+    {
+        Ok(ts) => ts,
+        Err(error_message) => return bad_request(error_message),
+    };
+    let created_before = match query
+        .created_before
+        .as_deref()
+        .map(|v| parse_date_filter("created_before", v))
+        .transpose()
+    {
+        Ok(ts) => ts,
+        Err(error_message) => return bad_request(error_message),
+    };
+    let order_by = match url_list_order(query.sort.as_deref(), query.order.as_deref()) {
+        Ok(order_by) => order_by,
+        Err(error_message) => return bad_request(error_message),
+    };
+    let limit = query
+        .limit
+        .unwrap_or(URL_PAGE_DEFAULT)
+        .clamp(1, URL_PAGE_MAX);
+    // An OFFSET, so it has to fit SQLite's signed 64-bit integers
+    let offset: i64 = match query.page_token.as_deref().map(str::parse).transpose() {
+        Ok(offset) if offset.unwrap_or(0) >= 0 => offset.unwrap_or(0),
+        _ => return bad_request("Invalid page_token".to_string()),
+    };
+
+    const FILTERS: &str = "user_id = ?1
+         AND (?2 IS NULL OR id IN (
+              SELECT ut.url_id FROM url_tags ut JOIN tags t ON t.id = ut.tag_id
+              WHERE t.user_id = ?1
+                AND (t.name = ?2 OR substr(t.name, 1, length(?2) + 1) = ?2 || '/')))
+         AND (?3 IS NULL OR id IN (SELECT rowid FROM urls_fts WHERE urls_fts MATCH ?3))
+         AND (?4 IS NULL OR created_at >= ?4)
+         AND (?5 IS NULL OR created_at < ?5)
+         AND (?6 IS NULL OR utm_source = ?6)
+         AND (?7 IS NULL OR utm_medium = ?7)
+         AND (?8 IS NULL OR utm_campaign = ?8)
+         AND (?9 IS NULL OR utm_term = ?9)
+         AND (?10 IS NULL OR utm_content = ?10)";
+    let filter_params = params![
+        user_id,
+        tag,
+        search,
+        created_after,
+        created_before,
+        query.utm_source.as_deref().map(str::trim),
+        query.utm_medium.as_deref().map(str::trim),
+        query.utm_campaign.as_deref().map(str::trim),
+        query.utm_term.as_deref().map(str::trim),
+        query.utm_content.as_deref().map(str::trim)
+    ];
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let total: u64 = db
+        .query_row(
+            &format!("SELECT COUNT(*) FROM urls WHERE {FILTERS}"),
+            filter_params,
+            |row| row.get(0),
+        )
+        .map_err(|e| {
+            error!(user_id, error = %e, "get_user_urls: count failed");
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let mut stmt = db
+        .prepare(&format!(
+            "SELECT {URL_ENTRY_COLUMNS} FROM urls WHERE {FILTERS}
+             ORDER BY {order_by} LIMIT ?11 OFFSET ?12"
+        ))
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
 
+    let mut page_params = filter_params.to_vec();
+    page_params.extend(params![limit, offset]);
     let urls: Vec<UrlEntry> = stmt
-        .query_map(params![user_id], |row| {
-            Ok(UrlEntry {
-                original_url: row.get(0)?,
-                short_code: row.get(1)?,
-                name: row.get(2)?,
-                clicks: row.get(3)?,
-            })
-        })
+        .query_map(page_params.as_slice(), url_entry_from_row)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
         .filter_map(|r| r.ok())
         .collect();
 
-    Ok(HttpResponse::Ok().json(urls))
+    let next_offset = offset as u64 + urls.len() as u64;
+    Ok(HttpResponse::Ok().json(UrlListResponse {
+        urls,
+        total,
+        next_page_token: (next_offset < total).then(|| next_offset.to_string()),
+    }))
 }
 
 /// Protected endpoint to delete a URL
@@ -337,8 +1431,361 @@ pub async fn update_url_name(
     }
 }
 
-/// Protected endpoint to get click history
-pub async fn get_click_history(
+/// Protected endpoint to set, change or clear a URL's activation window
+pub async fn update_url_schedule(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlScheduleRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let (active_from, active_until) = match parse_schedule(
+        req_payload.active_from.as_deref(),
+        req_payload.active_until.as_deref(),
+    ) {
+        Ok(schedule) => schedule,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the schedule only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET active_from = ?1, active_until = ?2
+         WHERE short_code = ?3 AND user_id = ?4",
+        params![
+            active_from.as_deref(),
+            active_until.as_deref(),
+            code.as_str(),
+            user_id
+        ],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), active_from = ?active_from, active_until = ?active_until, "URL schedule updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL schedule updated successfully",
+                    "active_from": active_from,
+                    "active_until": active_until
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL schedule"
+        }))),
+    }
+}
+
+/// Protected endpoint to replace a URL's iOS, Android and desktop
+/// destinations. Each override is validated like the link's own URL.
+pub async fn update_url_platform_urls(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlPlatformUrlsRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let platform_urls = match PlatformUrls::parse(
+        req_payload.ios_url.as_deref(),
+        req_payload.android_url.as_deref(),
+        req_payload.desktop_url.as_deref(),
+        data.config.max_url_length,
+    ) {
+        Ok(platform_urls) => platform_urls,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the overrides only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET ios_url = ?1, android_url = ?2, desktop_url = ?3
+         WHERE short_code = ?4 AND user_id = ?5",
+        params![
+            platform_urls.ios_url.as_deref(),
+            platform_urls.android_url.as_deref(),
+            platform_urls.desktop_url.as_deref(),
+            code.as_str(),
+            user_id
+        ],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), "URL platform destinations updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL platform destinations updated successfully",
+                    "ios_url": platform_urls.ios_url,
+                    "android_url": platform_urls.android_url,
+                    "desktop_url": platform_urls.desktop_url
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL platform destinations"
+        }))),
+    }
+}
+
+/// Protected endpoint to set the status a URL redirects with; `null` goes
+/// back to the instance default
+pub async fn update_url_redirect_status(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlRedirectStatusRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    if let Some(status) = req_payload.redirect_status {
+        if let Err(error_message) = validate_redirect_status(status) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    }
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the status only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET redirect_status = ?1 WHERE short_code = ?2 AND user_id = ?3",
+        params![req_payload.redirect_status, code.as_str(), user_id],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), redirect_status = ?req_payload.redirect_status, "URL redirect status updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL redirect status updated successfully",
+                    "redirect_status": req_payload.redirect_status,
+                    "effective_redirect_status": req_payload
+                        .redirect_status
+                        .unwrap_or(data.config.default_redirect_status)
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL redirect status"
+        }))),
+    }
+}
+
+/// Protected endpoint to set how a URL passes extra path and query on to its
+/// destination; `null` turns passthrough off
+pub async fn update_url_passthrough(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlPassthroughRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let passthrough = match req_payload
+        .passthrough
+        .as_deref()
+        .map(Passthrough::parse)
+        .transpose()
+    {
+        Ok(passthrough) => passthrough.map(Passthrough::as_str),
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the mode only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET passthrough = ?1 WHERE short_code = ?2 AND user_id = ?3",
+        params![passthrough, code.as_str(), user_id],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), passthrough = ?passthrough, "URL passthrough updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL passthrough updated successfully",
+                    "passthrough": passthrough
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL passthrough"
+        }))),
+    }
+}
+
+/// Protected endpoint to change a URL's destination, recording the previous
+/// one in `url_revisions`. The link's UTM parameters are added to the new
+/// destination just as they were to the first one.
+pub async fn update_url_destination(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlDestinationRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let new_url = req_payload.url.trim();
+    if new_url.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "URL cannot be empty"
+        })));
+    }
+    if let Err(error_message) = validate_url(new_url, data.config.max_url_length) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": error_message
+        })));
+    }
+
+    let mut db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+    let tx = match db.transaction() {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!(user_id, short_code = %code.as_str(), error = %e, "Failed to update URL destination");
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update URL destination"
+            })));
+        }
+    };
+
+    // Verify ownership and fetch the current destination and UTM parameters
+    let current: rusqlite::Result<(i64, String, UtmParams)> = tx.query_row(
+        "SELECT id, original_url, utm_source, utm_medium, utm_campaign, utm_term, utm_content
+         FROM urls WHERE short_code = ?1 AND user_id = ?2",
+        params![code.as_str(), user_id],
+        |row| {
+            let utm = UtmParams {
+                utm_source: row.get(2)?,
+                utm_medium: row.get(3)?,
+                utm_campaign: row.get(4)?,
+                utm_term: row.get(5)?,
+                utm_content: row.get(6)?,
+            };
+            Ok((row.get(0)?, row.get(1)?, utm))
+        },
+    );
+    let (url_id, previous_url, utm) = match current {
+        Ok(row) => row,
+        Err(_) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Short URL not found or not owned by you"
+            })));
+        }
+    };
+
+    // Keep the stored campaign fields true of the URL visitors are sent to
+    let new_url = match utm.apply(new_url, data.config.max_url_length) {
+        Ok(url) => url,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    if previous_url == new_url {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "URL destination unchanged",
+            "original_url": previous_url
+        })));
+    }
+
+    // Update and record the revision together so history never drifts
+    let result = tx
+        .execute(
+            "UPDATE urls SET original_url = ?1 WHERE id = ?2",
+            params![new_url, url_id],
+        )
+        .and_then(|_| {
+            tx.execute(
+                "INSERT INTO url_revisions (url_id, previous_url, new_url, changed_by)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![url_id, &previous_url, new_url, user_id],
+            )
+        })
+        .and_then(|_| tx.commit());
+
+    match result {
+        Ok(()) => {
+            info!(user_id, short_code = %code.as_str(), "URL destination updated");
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "message": "URL destination updated successfully",
+                "original_url": new_url
+            })))
+        }
+        Err(e) => {
+            error!(user_id, short_code = %code.as_str(), error = %e, "Failed to update URL destination");
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update URL destination"
+            })))
+        }
+    }
+}
+
+/// Protected endpoint to list a URL's previous destinations, newest first
+pub async fn get_url_revisions(
     data: web::Data<AppState>,
     code: web::Path<String>,
     http_req: HttpRequest,
@@ -355,13 +1802,11 @@ pub async fn get_click_history(
     let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
 
     // First verify ownership
-    let url_id: rusqlite::Result<i64> = db.query_row(
+    let url_id: i64 = match db.query_row(
         "SELECT id FROM urls WHERE short_code = ?1 AND user_id = ?2",
         params![code.as_str(), user_id],
         |row| row.get(0),
-    );
-
-    let url_id = match url_id {
+    ) {
         Ok(id) => id,
         Err(_) => {
             return Ok(HttpResponse::NotFound().json(serde_json::json!({
@@ -370,46 +1815,39 @@ pub async fn get_click_history(
         }
     };
 
-    // Get total clicks from counter
-    let total_clicks: u64 = db
-        .query_row(
-            "SELECT clicks FROM urls WHERE id = ?1",
-            params![url_id],
-            |row| row.get(0),
-        )
-        .unwrap_or(0);
-
-    // Get click history (limited to recent 1000)
     let mut stmt = db
         .prepare(
-            "SELECT clicked_at FROM click_history WHERE url_id = ?1 ORDER BY clicked_at DESC LIMIT 1000",
+            "SELECT r.previous_url, r.new_url, u.username, r.changed_at
+             FROM url_revisions r
+             LEFT JOIN users u ON u.userID = r.changed_by
+             WHERE r.url_id = ?1
+             ORDER BY r.changed_at DESC, r.id DESC",
         )
         .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
 
-    let history: Vec<ClickHistoryEntry> = stmt
+    let revisions: Vec<UrlRevision> = stmt
         .query_map(params![url_id], |row| {
-            Ok(ClickHistoryEntry {
-                clicked_at: row.get(0)?,
+            Ok(UrlRevision {
+                previous_url: row.get(0)?,
+                new_url: row.get(1)?,
+                changed_by: row.get(2)?,
+                changed_at: row.get(3)?,
             })
         })
         .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
         .filter_map(|r| r.ok())
         .collect();
 
-    Ok(HttpResponse::Ok().json(ClickStats {
-        total_clicks,
-        history,
-    }))
+    Ok(HttpResponse::Ok().json(revisions))
 }
 
-/// Protected endpoint to generate and download QR codes
-pub async fn get_qr_code(
+/// Protected endpoint to set, extend or clear a URL's expiry
+pub async fn update_url_expiry(
     data: web::Data<AppState>,
-    path: web::Path<(String, String)>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlExpiryRequest>,
     http_req: HttpRequest,
 ) -> Result<HttpResponse> {
-    let (code, format) = path.into_inner();
-
     let user_id = match get_user_id(&http_req) {
         Some(id) => id,
         None => {
@@ -419,9 +1857,241 @@ pub async fn get_qr_code(
         }
     };
 
-    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
-
-    // Verify ownership
+    let expires_at = match &req_payload.expires_at {
+        Some(Some(value)) => match parse_expiry(value) {
+            Ok(ts) => Some(ts),
+            Err(error_message) => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": error_message
+                })));
+            }
+        },
+        Some(None) => None,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "expires_at is required; use null to clear the expiry"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the expiry only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET expires_at = ?1 WHERE short_code = ?2 AND user_id = ?3",
+        params![expires_at.as_deref(), code.as_str(), user_id],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), expires_at = ?expires_at, "URL expiry updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL expiry updated successfully",
+                    "expires_at": expires_at
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL expiry"
+        }))),
+    }
+}
+
+/// Protected endpoint to set, change or remove a URL's password
+pub async fn update_url_password(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlPasswordRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let password_hash = match req_payload.password.as_deref() {
+        Some(password) => match hash_new_password(password) {
+            Ok(hash) => Some(hash),
+            Err(e) => return Ok(e.into_response()),
+        },
+        None => None,
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the password only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET password_hash = ?1 WHERE short_code = ?2 AND user_id = ?3",
+        params![password_hash.as_deref(), code.as_str(), user_id],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), protected = password_hash.is_some(), "URL password updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL password updated successfully",
+                    "password_protected": password_hash.is_some()
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL password"
+        }))),
+    }
+}
+
+/// Protected endpoint to make a URL's stats public at `/{code}+`, or private again
+pub async fn update_url_public_stats(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlPublicStatsRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Update the setting only if the URL belongs to the current user
+    match db.execute(
+        "UPDATE urls SET public_stats = ?1 WHERE short_code = ?2 AND user_id = ?3",
+        params![req_payload.enabled, code.as_str(), user_id],
+    ) {
+        Ok(rows_affected) => {
+            if rows_affected > 0 {
+                info!(user_id, short_code = %code.as_str(), public_stats = req_payload.enabled, "URL public stats updated");
+                Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "message": "URL public stats updated successfully",
+                    "public_stats": req_payload.enabled
+                })))
+            } else {
+                Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Short URL not found or not owned by you"
+                })))
+            }
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update URL public stats"
+        }))),
+    }
+}
+
+/// Protected endpoint to get click history
+pub async fn get_click_history(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // First verify ownership
+    let url_id: rusqlite::Result<i64> = db.query_row(
+        "SELECT id FROM urls WHERE short_code = ?1 AND user_id = ?2",
+        params![code.as_str(), user_id],
+        |row| row.get(0),
+    );
+
+    let url_id = match url_id {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Short URL not found or not owned by you"
+            })));
+        }
+    };
+
+    // Get total clicks from counters
+    let (total_clicks, bot_clicks): (u64, u64) = db
+        .query_row(
+            "SELECT clicks, bot_clicks FROM urls WHERE id = ?1",
+            params![url_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap_or((0, 0));
+    let unique_visitors = unique_visitors(&db, url_id).unwrap_or(0);
+
+    // Get click history (limited to recent 1000)
+    let mut stmt = db
+        .prepare(
+            "SELECT clicked_at, referrer_host, browser, os, device, country, region, city, is_bot,
+                    geo_rule
+             FROM click_history
+             WHERE url_id = ?1 ORDER BY clicked_at DESC, id DESC LIMIT 1000",
+        )
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let history: Vec<ClickHistoryEntry> = stmt
+        .query_map(params![url_id], |row| {
+            Ok(ClickHistoryEntry {
+                clicked_at: row.get(0)?,
+                referrer_host: row.get(1)?,
+                browser: row.get(2)?,
+                os: row.get(3)?,
+                device: row.get(4)?,
+                country: row.get(5)?,
+                region: row.get(6)?,
+                city: row.get(7)?,
+                bot: row.get(8)?,
+                geo_rule: row.get(9)?,
+            })
+        })
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ClickStats {
+        total_clicks,
+        bot_clicks,
+        unique_visitors,
+        history,
+    }))
+}
+
+/// Protected endpoint to generate and download QR codes
+pub async fn get_qr_code(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (code, format) = path.into_inner();
+
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Verify ownership
     let exists: bool = db
         .query_row(
             "SELECT COUNT(*) FROM urls WHERE short_code = ?1 AND user_id = ?2",
@@ -493,7 +2163,10 @@ mod tests {
     mod standalone {
         use super::*;
         use crate::auth::middleware::jwt_validator;
-        use crate::testing::{insert_test_url, insert_test_user, make_test_state, make_test_token};
+        use crate::testing::{
+            insert_test_url, insert_test_user, make_test_state, make_test_token, peer, test_config,
+            GEOIP_FIXTURE,
+        };
         use actix_web_httpauth::middleware::HttpAuthentication;
 
         macro_rules! setup_app {
@@ -506,14 +2179,56 @@ mod tests {
                             web::scope("/api")
                                 .wrap(jwt)
                                 .route("/shorten", web::post().to(shorten_url))
+                                .route("/shorten/bulk", web::post().to(bulk_shorten_urls))
                                 .route("/urls", web::get().to(get_user_urls))
                                 .route("/urls/{code}", web::delete().to(delete_url))
                                 .route("/urls/{code}/name", web::patch().to(update_url_name))
+                                .route("/urls/{code}/expiry", web::patch().to(update_url_expiry))
+                                .route(
+                                    "/urls/{code}/destination",
+                                    web::patch().to(update_url_destination),
+                                )
+                                .route("/urls/{code}/revisions", web::get().to(get_url_revisions))
+                                .route(
+                                    "/urls/{code}/schedule",
+                                    web::patch().to(update_url_schedule),
+                                )
+                                .route(
+                                    "/urls/{code}/platform-urls",
+                                    web::patch().to(update_url_platform_urls),
+                                )
+                                .route(
+                                    "/urls/{code}/redirect-status",
+                                    web::patch().to(update_url_redirect_status),
+                                )
+                                .route(
+                                    "/urls/{code}/passthrough",
+                                    web::patch().to(update_url_passthrough),
+                                )
+                                .route(
+                                    "/urls/{code}/password",
+                                    web::patch().to(update_url_password),
+                                )
                                 .route("/stats/{code}", web::get().to(get_stats))
                                 .route("/urls/{code}/clicks", web::get().to(get_click_history))
                                 .route("/urls/{code}/qr/{format}", web::get().to(get_qr_code)),
                         )
-                        .route("/{code}", web::get().to(redirect_url)),
+                        .service(
+                            web::resource(["/{code}", "/{code}/{tail:.*}"])
+                                .route(web::get().to(redirect_url))
+                                .route(web::head().to(redirect_url))
+                                .route(web::method(Method::OPTIONS).to(redirect_url))
+                                .route(
+                                    web::post()
+                                        .guard(actix_web::guard::fn_guard(password_protected_link))
+                                        .to(unlock_url),
+                                )
+                                .route(
+                                    web::route()
+                                        .guard(actix_web::guard::fn_guard(forwarded_method))
+                                        .to(redirect_url),
+                                ),
+                        ),
                 )
                 .await
             }};
@@ -522,211 +2237,2264 @@ mod tests {
         // --- shorten_url ---
 
         #[actix_web::test]
-        async fn shorten_url_success() {
+        async fn shorten_url_success() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let body: Value = test::read_body_json(resp).await;
+            assert!(body["short_code"].is_string());
+            assert_eq!(body["original_url"], "https://example.com");
+        }
+
+        #[actix_web::test]
+        async fn shorten_url_short_code_is_6_chars() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com"}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["short_code"].as_str().unwrap().len(), 6);
+        }
+
+        #[actix_web::test]
+        async fn shorten_same_url_twice_returns_same_code() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let shorten = |_app: &_| {
+                test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"url": "https://example.com"}))
+                    .to_request()
+            };
+            let b1: Value = test::call_and_read_body_json(&app, shorten(&app)).await;
+            let b2: Value = test::call_and_read_body_json(&app, shorten(&app)).await;
+            assert_eq!(b1["short_code"], b2["short_code"]);
+        }
+
+        #[actix_web::test]
+        async fn shorten_url_empty_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": ""}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+
+        #[actix_web::test]
+        async fn shorten_invalid_url_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "ftp://bad-scheme.com"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+
+        #[actix_web::test]
+        async fn shorten_url_without_auth_returns_401() {
+            let state = make_test_state();
+            let app = setup_app!(state);
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .set_json(serde_json::json!({"url": "https://example.com"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 401);
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_alias_uses_alias_as_code() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(
+                    serde_json::json!({"url": "https://example.com/q3", "alias": "q3-report"}),
+                )
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["short_code"], "q3-report");
+            assert_eq!(body["short_url"], "http://localhost:4001/q3-report");
+
+            let resp = test::call_service(
+                &app,
+                test::TestRequest::get().uri("/q3-report").to_request(),
+            )
+            .await;
+            assert_eq!(resp.status(), 302);
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/q3"
+            );
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_taken_alias_returns_409() {
+            let state = make_test_state();
+            let uid_a = insert_test_user(&state, "alice", false);
+            let uid_b = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid_b, "https://bob.com", "launch");
+            let token = make_test_token("alice", uid_a, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://alice.com", "alias": "launch"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 409);
+            let body: Value = test::read_body_json(resp).await;
+            assert!(body["error"].as_str().unwrap().contains("launch"));
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_reserved_alias_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for alias in ["api", "health", "dashboard.html", "x"] {
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"url": "https://example.com", "alias": alias}))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "alias {alias} should be rejected");
+            }
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_alias_creates_new_link_for_existing_url() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com", "alias": "branded"}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["short_code"], "branded");
+
+            let count: i64 = {
+                let db = state.db.lock().unwrap();
+                db.query_row("SELECT COUNT(*) FROM urls WHERE user_id = ?1", [uid], |r| {
+                    r.get(0)
+                })
+                .unwrap()
+            };
+            assert_eq!(count, 2);
+        }
+
+        // --- bulk_shorten_urls ---
+
+        #[actix_web::test]
+        async fn bulk_shorten_reports_per_item_results() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://taken.com", "taken");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten/bulk")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"items": [
+                    {"url": "https://one.com", "name": "First"},
+                    {"url": "not a url"},
+                    {"url": "https://two.com", "alias": "taken"},
+                    {"url": "https://three.com", "alias": "three"},
+                    {"url": "https://four.com", "alias": "three"}
+                ]}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["succeeded"], 2);
+            assert_eq!(body["failed"], 3);
+
+            let results = body["results"].as_array().unwrap();
+            let statuses: Vec<_> = results
+                .iter()
+                .map(|r| r["status"].as_u64().unwrap())
+                .collect();
+            assert_eq!(statuses, vec![200, 400, 409, 200, 409]);
+            assert_eq!(results[0]["name"], "First");
+            assert!(results[0]["short_url"].as_str().is_some());
+            assert!(results[1]["error"].as_str().is_some());
+            assert_eq!(results[3]["short_code"], "three");
+
+            let db = state.db.lock().unwrap();
+            let name: String = db
+                .query_row(
+                    "SELECT name FROM urls WHERE original_url = 'https://one.com'",
+                    [],
+                    |r| r.get(0),
+                )
+                .unwrap();
+            assert_eq!(name, "First");
+            let count: i64 = db
+                .query_row("SELECT COUNT(*) FROM urls WHERE user_id = ?1", [uid], |r| {
+                    r.get(0)
+                })
+                .unwrap();
+            assert_eq!(count, 3);
+        }
+
+        #[actix_web::test]
+        async fn bulk_shorten_rejects_empty_and_oversized_batches() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let too_many: Vec<_> = (0..=BULK_SHORTEN_MAX_ITEMS)
+                .map(|i| serde_json::json!({"url": format!("https://example.com/{i}")}))
+                .collect();
+            for items in [vec![], too_many] {
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten/bulk")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"items": items}))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400);
+            }
+        }
+
+        #[actix_web::test]
+        async fn bulk_shorten_without_auth_returns_401() {
+            let state = make_test_state();
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten/bulk")
+                .set_json(serde_json::json!({"items": [{"url": "https://example.com"}]}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 401);
+        }
+
+        // --- redirect_url ---
+
+        #[actix_web::test]
+        async fn redirect_existing_code_returns_302() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get().uri("/abc123").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 302);
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+        }
+
+        #[actix_web::test]
+        async fn redirect_nonexistent_code_returns_404() {
+            let state = make_test_state();
+            let app = setup_app!(state);
+            let req = test::TestRequest::get().uri("/xxxxxx").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+        }
+
+        #[actix_web::test]
+        async fn redirect_increments_click_counter() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            let app = setup_app!(state);
+
+            // Visit twice
+            for _ in 0..2 {
+                test::call_service(&app, test::TestRequest::get().uri("/abc123").to_request())
+                    .await;
+            }
+
+            let clicks: i64 = {
+                let db = state.db.lock().unwrap();
+                db.query_row(
+                    "SELECT clicks FROM urls WHERE short_code = 'abc123'",
+                    [],
+                    |r| r.get(0),
+                )
+                .unwrap()
+            };
+            assert_eq!(clicks, 2);
+        }
+
+        #[actix_web::test]
+        async fn redirect_expired_link_returns_410_without_counting() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "old001");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET expires_at = datetime('now', '-1 hour') WHERE short_code = 'old001'",
+                    [],
+                )
+                .unwrap();
+            }
+            let app = setup_app!(state);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/old001").to_request())
+                    .await;
+            assert_eq!(resp.status(), 410);
+            assert!(resp.headers().get("Location").is_none());
+
+            let clicks: i64 = {
+                let db = state.db.lock().unwrap();
+                db.query_row(
+                    "SELECT clicks FROM urls WHERE short_code = 'old001'",
+                    [],
+                    |r| r.get(0),
+                )
+                .unwrap()
+            };
+            assert_eq!(clicks, 0);
+        }
+
+        // --- expiry ---
+
+        #[actix_web::test]
+        async fn shorten_with_expiry_stores_and_returns_it() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com/event",
+                    "expires_at": "2099-01-01T12:00:00+02:00"
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["expires_at"], "2099-01-01 10:00:00");
+
+            let code = body["short_code"].as_str().unwrap();
+            let resp = test::call_service(
+                &app,
+                test::TestRequest::get()
+                    .uri(&format!("/{code}"))
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(resp.status(), 302);
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_invalid_expiry_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for expires_at in ["2000-01-01T00:00:00Z", "next tuesday"] {
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({
+                        "url": "https://example.com",
+                        "expires_at": expires_at
+                    }))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(
+                    resp.status(),
+                    400,
+                    "expires_at {expires_at} should be rejected"
+                );
+            }
+        }
+
+        #[actix_web::test]
+        async fn shorten_plain_url_does_not_reuse_expiring_link() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let temp: Value = test::call_and_read_body_json(
+                &app,
+                test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({
+                        "url": "https://example.com",
+                        "expires_at": "2099-01-01T00:00:00Z"
+                    }))
+                    .to_request(),
+            )
+            .await;
+            let plain: Value = test::call_and_read_body_json(
+                &app,
+                test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"url": "https://example.com"}))
+                    .to_request(),
+            )
+            .await;
+            assert_ne!(temp["short_code"], plain["short_code"]);
+            assert!(plain.get("expires_at").is_none());
+        }
+
+        #[actix_web::test]
+        async fn update_expiry_extends_and_clears() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "exp001");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET expires_at = datetime('now', '-1 day') WHERE short_code = 'exp001'",
+                    [],
+                )
+                .unwrap();
+            }
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            // Extend into the future: the link redirects again
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/exp001/expiry")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"expires_at": "2099-06-30T00:00:00Z"}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["expires_at"], "2099-06-30 00:00:00");
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/exp001").to_request())
+                    .await;
+            assert_eq!(resp.status(), 302);
+
+            // An empty body names no change and leaves the expiry alone
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/exp001/expiry")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+            let req = test::TestRequest::get()
+                .uri("/api/stats/exp001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["expires_at"], "2099-06-30 00:00:00");
+
+            // Clear it
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/exp001/expiry")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"expires_at": null}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/exp001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert!(body["expires_at"].is_null());
+        }
+
+        #[actix_web::test]
+        async fn update_expiry_for_other_users_url_returns_404() {
+            let state = make_test_state();
+            let uid_a = insert_test_user(&state, "alice", false);
+            let uid_b = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid_b, "https://bob.com", "bbb222");
+            let token = make_test_token("alice", uid_a, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/bbb222/expiry")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"expires_at": null}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+        }
+
+        // --- activation window ---
+
+        #[actix_web::test]
+        async fn redirect_before_window_returns_not_yet_available() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/launch", "soon01");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET active_from = datetime('now', '+1 day') WHERE short_code = 'soon01'",
+                    [],
+                )
+                .unwrap();
+            }
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get().uri("/soon01").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 403);
+            assert!(resp.headers().get("Location").is_none());
+            let body = test::read_body(resp).await;
+            assert!(String::from_utf8_lossy(&body).contains("Not Yet Available"));
+
+            let clicks: i64 = {
+                let db = state.db.lock().unwrap();
+                db.query_row(
+                    "SELECT clicks FROM urls WHERE short_code = 'soon01'",
+                    [],
+                    |r| r.get(0),
+                )
+                .unwrap()
+            };
+            assert_eq!(clicks, 0);
+        }
+
+        #[actix_web::test]
+        async fn redirect_inside_and_after_window() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/live", "live01");
+            insert_test_url(&state, uid, "https://example.com/over", "over01");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET active_from = datetime('now', '-1 day'),
+                                     active_until = datetime('now', '+1 day')
+                     WHERE short_code = 'live01'",
+                    [],
+                )
+                .unwrap();
+                db.execute(
+                    "UPDATE urls SET active_until = datetime('now', '-1 minute')
+                     WHERE short_code = 'over01'",
+                    [],
+                )
+                .unwrap();
+            }
+            let app = setup_app!(state);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/live01").to_request())
+                    .await;
+            assert_eq!(resp.status(), 302);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/over01").to_request())
+                    .await;
+            assert_eq!(resp.status(), 410);
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_schedule_surfaces_it_in_list() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com/launch",
+                    "active_from": "2099-01-01T09:00:00+01:00",
+                    "active_until": "2099-02-01T00:00:00Z"
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["active_from"], "2099-01-01 08:00:00");
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["urls"][0]["active_from"], "2099-01-01 08:00:00");
+            assert_eq!(body["urls"][0]["active_until"], "2099-02-01 00:00:00");
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_inverted_window_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for window in [
+                serde_json::json!({"active_from": "2099-02-01T00:00:00Z", "active_until": "2099-01-01T00:00:00Z"}),
+                serde_json::json!({"active_until": "2000-01-01T00:00:00Z"}),
+                serde_json::json!({"active_from": "next week"}),
+            ] {
+                let mut payload = window;
+                payload["url"] = serde_json::json!("https://example.com");
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(payload)
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400);
+            }
+        }
+
+        #[actix_web::test]
+        async fn update_schedule_opens_pending_link() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "sched1");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/sched1/schedule")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"active_from": "2099-01-01T00:00:00Z", "active_until": null}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/sched1").to_request())
+                    .await;
+            assert_eq!(resp.status(), 403);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/sched1/schedule")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"active_from": null, "active_until": null}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/sched1").to_request())
+                    .await;
+            assert_eq!(resp.status(), 302);
+        }
+
+        // --- platform destinations ---
+
+        const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) \
+            AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1";
+        const ANDROID: &str = "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 \
+            (KHTML, like Gecko) Chrome/119.0.0.0 Mobile Safari/537.36";
+        const FIREFOX: &str =
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+
+        #[actix_web::test]
+        async fn platform_urls_redirect_each_device_to_its_destination() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com/app",
+                    "alias": "get-app",
+                    "ios_url": "https://apps.apple.com/app/id123",
+                    "android_url": "https://play.google.com/store/apps/details?id=com.example"
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["ios_url"], "https://apps.apple.com/app/id123");
+            assert!(body.get("desktop_url").is_none());
+
+            for (user_agent, expected) in [
+                (IPHONE, "https://apps.apple.com/app/id123"),
+                (
+                    ANDROID,
+                    "https://play.google.com/store/apps/details?id=com.example",
+                ),
+                // No desktop override: desktops get the link's own URL
+                (FIREFOX, "https://example.com/app"),
+                // Unfurlers preview the website, not the app store
+                (
+                    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) facebookexternalhit/1.1",
+                    "https://example.com/app",
+                ),
+                ("", "https://example.com/app"),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri("/get-app")
+                    .insert_header(("User-Agent", user_agent))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 302, "{user_agent}");
+                assert_eq!(
+                    resp.headers().get("Location").unwrap(),
+                    expected,
+                    "{user_agent}"
+                );
+            }
+        }
+
+        #[actix_web::test]
+        async fn platform_urls_are_validated_like_the_link_url() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for field in ["ios_url", "android_url", "desktop_url"] {
+                let mut payload = serde_json::json!({"url": "https://example.com"});
+                payload[field] = serde_json::json!("javascript:alert(1)");
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(payload)
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{field}");
+                let body: Value = test::read_body_json(resp).await;
+                assert!(body["error"].as_str().unwrap().starts_with(field), "{body}");
+            }
+        }
+
+        #[actix_web::test]
+        async fn update_platform_urls_sets_and_clears_overrides() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com", "plat01");
+            let token = make_test_token("alice", uid, false);
+            let other_token = make_test_token("bob", other, false);
+            let app = setup_app!(state);
+            let redirect_for = |user_agent: &'static str| {
+                test::TestRequest::get()
+                    .uri("/plat01")
+                    .insert_header(("User-Agent", user_agent))
+                    .to_request()
+            };
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/plat01/platform-urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"desktop_url": "https://example.com/desktop"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+            let resp = test::call_service(&app, redirect_for(FIREFOX)).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/desktop"
+            );
+            let resp = test::call_service(&app, redirect_for(IPHONE)).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(
+                body["urls"][0]["desktop_url"],
+                "https://example.com/desktop"
+            );
+            assert_eq!(body["urls"][0]["ios_url"], Value::Null);
+
+            // Invalid overrides and other people's links are refused
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/plat01/platform-urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"ios_url": "ftp://example.com/app"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/plat01/platform-urls")
+                .insert_header(("Authorization", format!("Bearer {other_token}")))
+                .set_json(serde_json::json!({"ios_url": "https://evil.example.com"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/plat01/platform-urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"desktop_url": null}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+            let resp = test::call_service(&app, redirect_for(FIREFOX)).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+        }
+
+        #[actix_web::test]
+        async fn plain_link_is_not_reused_for_one_with_platform_urls() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "plain1");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com",
+                    "ios_url": "https://apps.apple.com/app/id123"
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_ne!(body["short_code"], "plain1");
+        }
+
+        // --- redirect_status ---
+
+        #[actix_web::test]
+        async fn redirect_status_and_cache_headers_follow_the_link_setting() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "perm01");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            // Links without their own status use the instance default
+            let req = test::TestRequest::get().uri("/perm01").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 302);
+            assert_eq!(resp.headers().get("Cache-Control").unwrap(), "no-store");
+
+            for (status, cache_control) in [
+                (301, "public, max-age=86400"),
+                (308, "public, max-age=86400"),
+                (307, "no-store"),
+            ] {
+                let req = test::TestRequest::patch()
+                    .uri("/api/urls/perm01/redirect-status")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"redirect_status": status}))
+                    .to_request();
+                let body: Value = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(body["effective_redirect_status"], status);
+
+                let req = test::TestRequest::get().uri("/perm01").to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status().as_u16(), status);
+                assert_eq!(
+                    resp.headers().get("Location").unwrap(),
+                    "https://example.com"
+                );
+                assert_eq!(resp.headers().get("Cache-Control").unwrap(), cache_control);
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["urls"][0]["redirect_status"], 307);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/perm01/redirect-status")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"redirect_status": null}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["redirect_status"], Value::Null);
+            assert_eq!(body["effective_redirect_status"], 302);
+        }
+
+        #[actix_web::test]
+        async fn permanent_redirects_are_not_cached_when_the_destination_can_change() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for extra in [
+                serde_json::json!({"max_clicks": 5}),
+                serde_json::json!({"expires_at": "2999-01-01T00:00:00Z"}),
+                serde_json::json!({"ios_url": "https://apps.apple.com/app/id123"}),
+            ] {
+                let mut payload = serde_json::json!({
+                    "url": "https://example.com",
+                    "redirect_status": 301
+                });
+                payload
+                    .as_object_mut()
+                    .unwrap()
+                    .extend(extra.as_object().unwrap().clone());
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(payload)
+                    .to_request();
+                let body: Value = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(body["redirect_status"], 301);
+
+                let uri = format!("/{}", body["short_code"].as_str().unwrap());
+                let req = test::TestRequest::get().uri(&uri).to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 301, "{extra}");
+                assert_eq!(
+                    resp.headers().get("Cache-Control").unwrap(),
+                    "no-store",
+                    "{extra}"
+                );
+            }
+        }
+
+        #[actix_web::test]
+        async fn invalid_redirect_statuses_are_refused() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com", "perm01");
+            let token = make_test_token("alice", uid, false);
+            let other_token = make_test_token("bob", other, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com", "redirect_status": 303}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/perm01/redirect-status")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"redirect_status": 200}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/perm01/redirect-status")
+                .insert_header(("Authorization", format!("Bearer {other_token}")))
+                .set_json(serde_json::json!({"redirect_status": 301}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+        }
+
+        #[actix_web::test]
+        async fn posts_follow_the_link_redirect_status() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/api", "keep01");
+            insert_test_url(&state, uid, "https://example.com/old", "plain1");
+            state
+                .db
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE urls SET redirect_status = 307 WHERE short_code = 'keep01'",
+                    [],
+                )
+                .unwrap();
+            let app = setup_app!(state);
+
+            // Not a form post, and no password involved
+            let req = test::TestRequest::post()
+                .uri("/keep01")
+                .set_json(serde_json::json!({"event": "signup"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 307);
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/api"
+            );
+
+            let req = test::TestRequest::put().uri("/keep01").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 307);
+
+            let req = test::TestRequest::post().uri("/plain1").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 302);
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/old"
+            );
+
+            let db = state.db.lock().unwrap();
+            let clicks: i64 = db
+                .query_row(
+                    "SELECT clicks FROM urls WHERE short_code = 'keep01'",
+                    [],
+                    |r| r.get(0),
+                )
+                .unwrap();
+            assert_eq!(clicks, 2);
+        }
+
+        #[actix_web::test]
+        async fn instance_default_redirect_status_applies_to_plain_links() {
+            let mut config = test_config();
+            config.default_redirect_status = 308;
+            let state = web::Data::new(AppState::new(config).unwrap());
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "perm01");
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get().uri("/perm01").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 308);
+            assert_eq!(
+                resp.headers().get("Cache-Control").unwrap(),
+                "public, max-age=86400"
+            );
+        }
+
+        // --- passthrough ---
+
+        #[actix_web::test]
+        async fn passthrough_appends_path_and_merges_query() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let mut codes = Vec::new();
+            for mode in ["keep", "replace"] {
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({
+                        "url": "https://example.com/docs?utm_source=rus",
+                        "passthrough": mode
+                    }))
+                    .to_request();
+                let body: Value = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(body["passthrough"], mode);
+                codes.push(body["short_code"].as_str().unwrap().to_string());
+            }
+
+            for (code, expected) in [
+                (
+                    &codes[0],
+                    "https://example.com/docs/guide/intro?utm_source=rus&page=2",
+                ),
+                (
+                    &codes[1],
+                    "https://example.com/docs/guide/intro?utm_source=x&page=2",
+                ),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri(&format!("/{code}/guide/intro?utm_source=x&page=2"))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 302);
+                assert_eq!(resp.headers().get("Location").unwrap(), expected);
+            }
+
+            // Both redirects were counted on their links
+            let db = state.db.lock().unwrap();
+            let clicks: i64 = db
+                .query_row("SELECT SUM(clicks) FROM urls", [], |r| r.get(0))
+                .unwrap();
+            assert_eq!(clicks, 2);
+        }
+
+        #[actix_web::test]
+        async fn extra_path_is_refused_without_passthrough_or_when_invalid() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/docs", "pass01");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            // Without passthrough the query is dropped and a path is not found
+            let req = test::TestRequest::get()
+                .uri("/pass01?utm_source=x")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/docs"
+            );
+            let req = test::TestRequest::get().uri("/pass01/extra").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pass01/passthrough")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"passthrough": "keep"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let req = test::TestRequest::get().uri("/pass01/extra").to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/docs/extra"
+            );
+            let req = test::TestRequest::get()
+                .uri("/pass01/%2e%2e/admin")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+            let long_query = format!("q={}", "a".repeat(3000));
+            let req = test::TestRequest::get()
+                .uri(&format!("/pass01?{long_query}"))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+            let db = state.db.lock().unwrap();
+            let clicks: i64 = db
+                .query_row(
+                    "SELECT clicks FROM urls WHERE short_code = 'pass01'",
+                    [],
+                    |r| r.get(0),
+                )
+                .unwrap();
+            assert_eq!(clicks, 2);
+        }
+
+        #[actix_web::test]
+        async fn update_passthrough_validates_the_mode() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com", "pass01");
+            let token = make_test_token("alice", uid, false);
+            let other_token = make_test_token("bob", other, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pass01/passthrough")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"passthrough": "merge"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pass01/passthrough")
+                .insert_header(("Authorization", format!("Bearer {other_token}")))
+                .set_json(serde_json::json!({"passthrough": "keep"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pass01/passthrough")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"passthrough": "replace"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["urls"][0]["passthrough"], "replace");
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pass01/passthrough")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"passthrough": null}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["passthrough"], Value::Null);
+            let req = test::TestRequest::get().uri("/pass01/extra").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+        }
+
+        // --- max_clicks ---
+
+        #[actix_web::test]
+        async fn one_time_link_redirects_once_then_returns_410() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com/secret", "max_clicks": 1}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["max_clicks"], 1);
+            let uri = format!("/{}", body["short_code"].as_str().unwrap());
+
+            let first =
+                test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(first.status(), 302);
+            let second =
+                test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(second.status(), 410);
+        }
+
+        #[actix_web::test]
+        async fn capped_link_never_exceeds_max_clicks() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "cap003");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET max_clicks = 3 WHERE short_code = 'cap003'",
+                    [],
+                )
+                .unwrap();
+            }
+            let app = setup_app!(state);
+
+            let mut redirects = 0;
+            for _ in 0..5 {
+                let resp =
+                    test::call_service(&app, test::TestRequest::get().uri("/cap003").to_request())
+                        .await;
+                if resp.status() == 302 {
+                    redirects += 1;
+                }
+            }
+            assert_eq!(redirects, 3);
+
+            let (clicks, history): (i64, i64) = {
+                let db = state.db.lock().unwrap();
+                db.query_row(
+                    "SELECT clicks, (SELECT COUNT(*) FROM click_history) FROM urls WHERE short_code = 'cap003'",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?)),
+                )
+                .unwrap()
+            };
+            assert_eq!(clicks, 3);
+            assert_eq!(history, 3);
+        }
+
+        #[actix_web::test]
+        async fn head_and_options_do_not_use_up_capped_links() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "once01");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET max_clicks = 1 WHERE short_code = 'once01'",
+                    [],
+                )
+                .unwrap();
+            }
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::default()
+                .method(Method::HEAD)
+                .uri("/once01")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 302);
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+            let req = test::TestRequest::default()
+                .method(Method::OPTIONS)
+                .uri("/once01")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+            let clicks = |state: &web::Data<AppState>| -> (i64, Option<i64>) {
+                let db = state.db.lock().unwrap();
+                db.query_row(
+                    "SELECT clicks, max_clicks FROM urls WHERE short_code = 'once01'",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?)),
+                )
+                .unwrap()
+            };
+            assert_eq!(clicks(&state), (0, Some(1)));
+
+            // The real recipient still gets through, after which a HEAD sees
+            // the link as used up
+            let req = test::TestRequest::get().uri("/once01").to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 302);
+            assert_eq!(clicks(&state), (1, Some(1)));
+            let req = test::TestRequest::default()
+                .method(Method::HEAD)
+                .uri("/once01")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 410);
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_zero_max_clicks_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com", "max_clicks": 0}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_max_clicks_beyond_i64_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com",
+                    "max_clicks": 18446744073709551615u64
+                }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(
+                body["error"],
+                "max_clicks must be at most 9223372036854775807"
+            );
+        }
+
+        #[actix_web::test]
+        async fn remaining_clicks_reported_in_stats_and_list() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "inv005");
+            insert_test_url(&state, uid, "https://example.org", "free01");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE urls SET max_clicks = 5 WHERE short_code = 'inv005'",
+                    [],
+                )
+                .unwrap();
+            }
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for _ in 0..2 {
+                test::call_service(&app, test::TestRequest::get().uri("/inv005").to_request())
+                    .await;
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/inv005")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["max_clicks"], 5);
+            assert_eq!(body["remaining_clicks"], 3);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let arr = body["urls"].as_array().unwrap();
+            let capped = arr.iter().find(|u| u["short_code"] == "inv005").unwrap();
+            let free = arr.iter().find(|u| u["short_code"] == "free01").unwrap();
+            assert_eq!(capped["remaining_clicks"], 3);
+            assert!(free["remaining_clicks"].is_null());
+        }
+
+        // --- password protection ---
+
+        async fn shorten_protected(
+            app: &impl actix_web::dev::Service<
+                actix_http::Request,
+                Response = actix_web::dev::ServiceResponse,
+                Error = actix_web::Error,
+            >,
+            token: &str,
+        ) -> String {
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com/private",
+                    "password": "open sesame"
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(app, req).await;
+            assert_eq!(body["password_protected"], true);
+            body["short_code"].as_str().unwrap().to_string()
+        }
+
+        fn clicks_for(state: &web::Data<AppState>, code: &str) -> i64 {
+            let db = state.db.lock().unwrap();
+            db.query_row(
+                "SELECT clicks FROM urls WHERE short_code = ?1",
+                params![code],
+                |r| r.get(0),
+            )
+            .unwrap()
+        }
+
+        #[actix_web::test]
+        async fn protected_link_shows_prompt_without_counting() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+            let code = shorten_protected(&app, &token).await;
+
+            let req = test::TestRequest::get()
+                .uri(&format!("/{code}"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+            assert!(resp.headers().get("Location").is_none());
+            let body = test::read_body(resp).await;
+            assert!(String::from_utf8_lossy(&body).contains("name=\"password\""));
+            assert_eq!(clicks_for(&state, &code), 0);
+        }
+
+        #[actix_web::test]
+        async fn unlock_with_wrong_password_returns_401() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+            let code = shorten_protected(&app, &token).await;
+
+            let req = test::TestRequest::post()
+                .uri(&format!("/{code}"))
+                .set_form([("password", "guess")])
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 401);
+            assert!(resp.headers().get("Location").is_none());
+            assert_eq!(clicks_for(&state, &code), 0);
+        }
+
+        #[actix_web::test]
+        async fn unlock_with_correct_password_redirects_and_counts() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+            let code = shorten_protected(&app, &token).await;
+
+            let req = test::TestRequest::post()
+                .uri(&format!("/{code}"))
+                .set_form([("password", "open sesame")])
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 303);
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/private"
+            );
+            assert_eq!(clicks_for(&state, &code), 1);
+        }
+
+        #[actix_web::test]
+        async fn shorten_with_short_password_returns_400() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com", "password": "abc"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+        }
+
+        #[actix_web::test]
+        async fn update_password_sets_and_removes_protection() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "pw0001");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pw0001/password")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"password": "hunter22"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/pw0001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["password_protected"], true);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/pw0001").to_request())
+                    .await;
+            assert_eq!(resp.status(), 200);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pw0001/password")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"password": null}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/pw0001").to_request())
+                    .await;
+            assert_eq!(resp.status(), 302);
+        }
+
+        #[actix_web::test]
+        async fn update_password_for_other_users_url_returns_404() {
+            let state = make_test_state();
+            let alice = insert_test_user(&state, "alice", false);
+            let bob = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, alice, "https://example.com", "pw0002");
+            let token = make_test_token("bob", bob, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/pw0002/password")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"password": "hunter22"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+        }
+
+        // --- destination changes ---
+
+        #[actix_web::test]
+        async fn update_destination_redirects_to_new_url_and_records_revision() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/old", "dest01");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/dest01/destination")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com/new"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/dest01").to_request())
+                    .await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com/new"
+            );
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/dest01/revisions")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let revisions = body.as_array().unwrap();
+            assert_eq!(revisions.len(), 1);
+            assert_eq!(revisions[0]["previous_url"], "https://example.com/old");
+            assert_eq!(revisions[0]["new_url"], "https://example.com/new");
+            assert_eq!(revisions[0]["changed_by"], "alice");
+        }
+
+        #[actix_web::test]
+        async fn update_destination_keeps_the_links_utm_parameters() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com/old",
+                    "alias": "camp01",
+                    "utm_source": "newsletter",
+                    "utm_campaign": "spring sale"
+                }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/camp01/destination")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.org/new?utm_source=old&id=7"}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let expected =
+                "https://example.org/new?id=7&utm_source=newsletter&utm_campaign=spring+sale";
+            assert_eq!(body["original_url"], expected);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/camp01").to_request())
+                    .await;
+            assert_eq!(resp.headers().get("Location").unwrap(), expected);
+
+            // Asking for the destination it already has changes nothing
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/camp01/destination")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.org/new?id=7"}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["message"], "URL destination unchanged");
+            let count: i64 = {
+                let db = state.db.lock().unwrap();
+                db.query_row("SELECT COUNT(*) FROM url_revisions", [], |r| r.get(0))
+                    .unwrap()
+            };
+            assert_eq!(count, 1);
+        }
+
+        #[actix_web::test]
+        async fn update_destination_to_same_url_records_nothing() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "dest02");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/dest02/destination")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let count: i64 = {
+                let db = state.db.lock().unwrap();
+                db.query_row("SELECT COUNT(*) FROM url_revisions", [], |r| r.get(0))
+                    .unwrap()
+            };
+            assert_eq!(count, 0);
+        }
+
+        #[actix_web::test]
+        async fn update_destination_with_invalid_url_returns_400() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "dest03");
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            let req = test::TestRequest::post()
-                .uri("/api/shorten")
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/dest03/destination")
                 .insert_header(("Authorization", format!("Bearer {token}")))
-                .set_json(serde_json::json!({"url": "https://example.com"}))
+                .set_json(serde_json::json!({"url": "javascript:alert(1)"}))
                 .to_request();
             let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 200);
+            assert_eq!(resp.status(), 400);
+        }
 
-            let body: Value = test::read_body_json(resp).await;
-            assert!(body["short_code"].is_string());
-            assert_eq!(body["original_url"], "https://example.com");
+        #[actix_web::test]
+        async fn update_destination_for_other_users_url_returns_404() {
+            let state = make_test_state();
+            let alice = insert_test_user(&state, "alice", false);
+            let bob = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, alice, "https://example.com", "dest04");
+            let token = make_test_token("bob", bob, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/dest04/destination")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://evil.example.com"}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/dest04/revisions")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404);
         }
 
+        // --- get_user_urls ---
+
         #[actix_web::test]
-        async fn shorten_url_short_code_is_6_chars() {
+        async fn get_user_urls_empty_list() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            let req = test::TestRequest::post()
-                .uri("/api/shorten")
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
                 .insert_header(("Authorization", format!("Bearer {token}")))
-                .set_json(serde_json::json!({"url": "https://example.com"}))
                 .to_request();
             let body: Value = test::call_and_read_body_json(&app, req).await;
-            assert_eq!(body["short_code"].as_str().unwrap().len(), 6);
+            assert_eq!(body, serde_json::json!({"urls": [], "total": 0}));
         }
 
         #[actix_web::test]
-        async fn shorten_same_url_twice_returns_same_code() {
+        async fn get_user_urls_returns_own_urls_only() {
+            let state = make_test_state();
+            let uid_a = insert_test_user(&state, "alice", false);
+            let uid_b = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid_a, "https://alice.com", "aaa111");
+            insert_test_url(&state, uid_b, "https://bob.com", "bbb222");
+            let token = make_test_token("alice", uid_a, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let arr = body["urls"].as_array().unwrap();
+            assert_eq!(arr.len(), 1);
+            assert_eq!(arr[0]["short_code"], "aaa111");
+        }
+
+        fn list_codes(body: &Value) -> Vec<&str> {
+            body["urls"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|u| u["short_code"].as_str().unwrap())
+                .collect()
+        }
+
+        #[actix_web::test]
+        async fn get_user_urls_paginates_with_next_page_token() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
+            for i in 0..5 {
+                insert_test_url(
+                    &state,
+                    uid,
+                    &format!("https://example.com/{i}"),
+                    &format!("pag00{i}"),
+                );
+            }
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            let shorten = |_app: &_| {
-                test::TestRequest::post()
-                    .uri("/api/shorten")
+            let mut seen = Vec::new();
+            let mut uri = "/api/urls?limit=2".to_string();
+            loop {
+                let req = test::TestRequest::get()
+                    .uri(&uri)
                     .insert_header(("Authorization", format!("Bearer {token}")))
-                    .set_json(serde_json::json!({"url": "https://example.com"}))
-                    .to_request()
-            };
-            let b1: Value = test::call_and_read_body_json(&app, shorten(&app)).await;
-            let b2: Value = test::call_and_read_body_json(&app, shorten(&app)).await;
-            assert_eq!(b1["short_code"], b2["short_code"]);
+                    .to_request();
+                let body: Value = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(body["total"], 5);
+                seen.extend(list_codes(&body).into_iter().map(str::to_string));
+                match body["next_page_token"].as_str() {
+                    Some(next) => uri = format!("/api/urls?limit=2&page_token={next}"),
+                    None => break,
+                }
+            }
+            seen.sort();
+            assert_eq!(seen, vec!["pag000", "pag001", "pag002", "pag003", "pag004"]);
         }
 
         #[actix_web::test]
-        async fn shorten_url_empty_returns_400() {
+        async fn get_user_urls_full_text_search() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://docs.rust-lang.org/book", "fts001");
+            insert_test_url(&state, uid, "https://example.com/pricing", "fts002");
+            insert_test_url(&state, uid, "https://example.com/other", "fts003");
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            let req = test::TestRequest::post()
-                .uri("/api/shorten")
+            // Names are searchable too, including after a rename
+            let req = test::TestRequest::patch()
+                .uri("/api/urls/fts003/name")
                 .insert_header(("Authorization", format!("Bearer {token}")))
-                .set_json(serde_json::json!({"url": ""}))
+                .set_json(serde_json::json!({"name": "Quarterly Report"}))
                 .to_request();
-            let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 400);
+            test::call_service(&app, req).await;
+
+            for (q, expected) in [
+                ("rust", vec!["fts001"]),
+                ("pric", vec!["fts002"]),
+                ("quarterly", vec!["fts003"]),
+                ("example.com", vec!["fts002", "fts003"]),
+                ("\"unbalanced", vec![]),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri(&format!(
+                        "/api/urls?sort=name&q={}",
+                        url::form_urlencoded::byte_serialize(q.as_bytes()).collect::<String>()
+                    ))
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .to_request();
+                let body: Value = test::call_and_read_body_json(&app, req).await;
+                let mut codes = list_codes(&body);
+                codes.sort();
+                assert_eq!(codes, expected, "query {q:?}");
+            }
         }
 
         #[actix_web::test]
-        async fn shorten_invalid_url_returns_400() {
+        async fn get_user_urls_sorts_and_filters_by_date() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com/a", "srt001");
+            insert_test_url(&state, uid, "https://example.com/b", "srt002");
+            insert_test_url(&state, uid, "https://example.com/c", "srt003");
+            {
+                let db = state.db.lock().unwrap();
+                db.execute_batch(
+                    "UPDATE urls SET clicks = 5, name = 'beta', created_at = '2026-01-10 12:00:00' WHERE short_code = 'srt001';
+                     UPDATE urls SET clicks = 9, name = 'Alpha', created_at = '2026-02-10 12:00:00' WHERE short_code = 'srt002';
+                     UPDATE urls SET clicks = 1, created_at = '2026-03-10 12:00:00' WHERE short_code = 'srt003';",
+                )
+                .unwrap();
+            }
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            let req = test::TestRequest::post()
-                .uri("/api/shorten")
-                .insert_header(("Authorization", format!("Bearer {token}")))
-                .set_json(serde_json::json!({"url": "ftp://bad-scheme.com"}))
-                .to_request();
-            let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 400);
+            for (query, expected) in [
+                ("", vec!["srt003", "srt002", "srt001"]),
+                ("?sort=clicks", vec!["srt002", "srt001", "srt003"]),
+                ("?sort=clicks&order=asc", vec!["srt003", "srt001", "srt002"]),
+                ("?sort=name", vec!["srt002", "srt001", "srt003"]),
+                ("?created_after=2026-02-01", vec!["srt003", "srt002"]),
+                (
+                    "?created_after=2026-02-01&created_before=2026-03-01T00:00:00Z",
+                    vec!["srt002"],
+                ),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri(&format!("/api/urls{query}"))
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .to_request();
+                let body: Value = test::call_and_read_body_json(&app, req).await;
+                assert_eq!(list_codes(&body), expected, "query {query:?}");
+            }
         }
 
         #[actix_web::test]
-        async fn shorten_url_without_auth_returns_401() {
+        async fn get_user_urls_rejects_bad_list_parameters() {
             let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
-            let req = test::TestRequest::post()
-                .uri("/api/shorten")
-                .set_json(serde_json::json!({"url": "https://example.com"}))
+
+            for query in [
+                "sort=popularity",
+                "order=sideways",
+                "created_after=yesterday",
+                "page_token=abc",
+                "page_token=-1",
+                "page_token=9223372036854775808",
+            ] {
+                let req = test::TestRequest::get()
+                    .uri(&format!("/api/urls?{query}"))
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "query {query:?}");
+            }
+
+            // The largest offset SQLite accepts is just past the end
+            let req = test::TestRequest::get()
+                .uri("/api/urls?page_token=9223372036854775807")
+                .insert_header(("Authorization", format!("Bearer {token}")))
                 .to_request();
-            let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 401);
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["urls"], serde_json::json!([]));
         }
 
-        // --- redirect_url ---
+        // --- get_stats ---
 
         #[actix_web::test]
-        async fn redirect_existing_code_returns_302() {
+        async fn get_stats_for_own_url() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
             insert_test_url(&state, uid, "https://example.com", "abc123");
+            let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            let req = test::TestRequest::get().uri("/abc123").to_request();
+            let req = test::TestRequest::get()
+                .uri("/api/stats/abc123")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
             let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 302);
-            assert_eq!(
-                resp.headers().get("Location").unwrap(),
-                "https://example.com"
-            );
+            assert_eq!(resp.status(), 200);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["short_code"], "abc123");
         }
 
         #[actix_web::test]
-        async fn redirect_nonexistent_code_returns_404() {
+        async fn repeat_clicks_by_one_visitor_count_as_one_unique() {
             let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
-            let req = test::TestRequest::get().uri("/xxxxxx").to_request();
-            let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 404);
+
+            let firefox =
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+            for (ua, ip) in [
+                (firefox, "203.0.113.1"),
+                (firefox, "203.0.113.1"),
+                (firefox, "203.0.113.2"),
+                ("Slackbot-LinkExpanding 1.0", "203.0.113.3"),
+            ] {
+                let req = test::TestRequest::get()
+                    .uri("/abc123")
+                    .insert_header(("User-Agent", ua))
+                    .peer_addr(peer(ip))
+                    .to_request();
+                test::call_service(&app, req).await;
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/abc123")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["clicks"], 3);
+            assert_eq!(body["unique_visitors"], 2);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/abc123/clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["total_clicks"], 3);
+            assert_eq!(body["unique_visitors"], 2);
         }
 
         #[actix_web::test]
-        async fn redirect_increments_click_counter() {
+        async fn redirect_records_click_details_and_stats_break_them_down() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
             insert_test_url(&state, uid, "https://example.com", "abc123");
+            let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
-            // Visit twice
-            for _ in 0..2 {
-                test::call_service(&app, test::TestRequest::get().uri("/abc123").to_request())
-                    .await;
+            let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) \
+                          AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 \
+                          Mobile/15E148 Safari/604.1";
+            let firefox =
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+            for (ua, referer, ip) in [
+                (
+                    iphone,
+                    Some("https://www.google.com/search?q=x"),
+                    "203.0.113.1",
+                ),
+                (iphone, Some("https://google.com/"), "203.0.113.2"),
+                (firefox, None, "203.0.113.3"),
+            ] {
+                let mut req = test::TestRequest::get()
+                    .uri("/abc123")
+                    .insert_header(("User-Agent", ua))
+                    .peer_addr(peer(ip));
+                if let Some(referer) = referer {
+                    req = req.insert_header(("Referer", referer));
+                }
+                test::call_service(&app, req.to_request()).await;
             }
 
-            let clicks: i64 = {
+            {
                 let db = state.db.lock().unwrap();
-                db.query_row(
-                    "SELECT clicks FROM urls WHERE short_code = 'abc123'",
+                let (hashes, distinct): (i64, i64) = db
+                    .query_row(
+                        "SELECT COUNT(visitor_hash), COUNT(DISTINCT visitor_hash) FROM click_history",
+                        [],
+                        |r| Ok((r.get(0)?, r.get(1)?)),
+                    )
+                    .unwrap();
+                assert_eq!((hashes, distinct), (3, 3));
+                let raw_ip: i64 = db
+                    .query_row(
+                        "SELECT COUNT(*) FROM click_history WHERE visitor_hash LIKE '%203.0.113%'",
+                        [],
+                        |r| r.get(0),
+                    )
+                    .unwrap();
+                assert_eq!(raw_ip, 0);
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/abc123")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["clicks"], 3);
+            let breakdown = &body["breakdown"];
+            assert_eq!(breakdown["referrers"][0]["value"], "google.com");
+            assert_eq!(breakdown["referrers"][0]["clicks"], 2);
+            assert_eq!(breakdown["referrers"][1]["value"], "direct");
+            assert_eq!(breakdown["browsers"][0]["value"], "Safari");
+            assert_eq!(breakdown["operating_systems"][1]["value"], "Windows");
+            assert_eq!(breakdown["devices"][0]["value"], "mobile");
+            assert_eq!(breakdown["devices"][1]["value"], "desktop");
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/abc123/clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["history"][0]["browser"], "Firefox");
+            assert!(body["history"][0].get("referrer_host").is_none());
+            assert_eq!(body["history"][2]["referrer_host"], "google.com");
+        }
+
+        #[actix_web::test]
+        async fn bots_are_redirected_but_counted_separately() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "cap001");
+            state
+                .db
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE urls SET max_clicks = 1 WHERE short_code = 'cap001'",
                     [],
-                    |r| r.get(0),
                 )
+                .unwrap();
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+            let firefox =
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+
+            // Unfurlers do not use up a single-use link...
+            for ua in [
+                "Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)",
+                "Mozilla/5.0 (compatible; Discordbot/2.0; +https://discordapp.com)",
+            ] {
+                let req = test::TestRequest::get()
+                    .uri("/cap001")
+                    .insert_header(("User-Agent", ua))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 302, "{ua}");
+            }
+            let req = test::TestRequest::get()
+                .uri("/cap001")
+                .insert_header(("User-Agent", firefox))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 302);
+
+            // ...but are not let through once a person has
+            let req = test::TestRequest::get()
+                .uri("/cap001")
+                .insert_header(("User-Agent", "Twitterbot/1.0"))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 410);
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/cap001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["clicks"], 1);
+            assert_eq!(body["bot_clicks"], 2);
+            assert_eq!(body["remaining_clicks"], 0);
+            assert_eq!(body["breakdown"]["browsers"][0]["value"], "Firefox");
+            assert_eq!(
+                body["breakdown"]["browsers"].as_array().unwrap().len(),
+                1,
+                "breakdowns only count people"
+            );
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/cap001/clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["total_clicks"], 1);
+            assert_eq!(body["bot_clicks"], 2);
+            let bots: Vec<bool> = body["history"]
+                .as_array()
                 .unwrap()
-            };
-            assert_eq!(clicks, 2);
+                .iter()
+                .map(|c| c["bot"].as_bool().unwrap())
+                .collect();
+            assert_eq!(bots, [false, true, true]);
         }
 
-        // --- get_user_urls ---
-
         #[actix_web::test]
-        async fn get_user_urls_empty_list() {
+        async fn stats_breakdown_survives_click_cleanup() {
             let state = make_test_state();
             let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
+            for referer in [
+                "https://news.ycombinator.com/",
+                "https://news.ycombinator.com/x",
+            ] {
+                let req = test::TestRequest::get()
+                    .uri("/abc123")
+                    .insert_header(("Referer", referer))
+                    .to_request();
+                test::call_service(&app, req).await;
+            }
+            {
+                let db = state.db.lock().unwrap();
+                db.execute(
+                    "UPDATE click_history SET clicked_at = datetime('now', '-60 days')",
+                    [],
+                )
+                .unwrap();
+                crate::db::cleanup_old_clicks(&db, 30);
+            }
+            let req = test::TestRequest::get()
+                .uri("/abc123")
+                .insert_header(("Referer", "https://example.org/"))
+                .to_request();
+            test::call_service(&app, req).await;
+
             let req = test::TestRequest::get()
-                .uri("/api/urls")
+                .uri("/api/urls/abc123/clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["history"].as_array().unwrap().len(), 1);
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/abc123")
                 .insert_header(("Authorization", format!("Bearer {token}")))
                 .to_request();
             let body: Value = test::call_and_read_body_json(&app, req).await;
-            assert_eq!(body, serde_json::json!([]));
+            let referrers = &body["breakdown"]["referrers"];
+            assert_eq!(referrers[0]["value"], "news.ycombinator.com");
+            assert_eq!(referrers[0]["clicks"], 2);
+            assert_eq!(referrers[1]["value"], "example.org");
+            assert_eq!(body["breakdown"]["devices"][0]["clicks"], 3);
         }
 
         #[actix_web::test]
-        async fn get_user_urls_returns_own_urls_only() {
-            let state = make_test_state();
-            let uid_a = insert_test_user(&state, "alice", false);
-            let uid_b = insert_test_user(&state, "bob", false);
-            insert_test_url(&state, uid_a, "https://alice.com", "aaa111");
-            insert_test_url(&state, uid_b, "https://bob.com", "bbb222");
-            let token = make_test_token("alice", uid_a, false);
+        async fn redirect_geolocates_clicks_when_geoip_is_configured() {
+            let mut config = test_config();
+            config.geoip_db_path = Some(GEOIP_FIXTURE.to_string());
+            let state = web::Data::new(AppState::new(config).unwrap());
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "abc123");
+            let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
+            for ip in ["81.2.69.142", "81.2.69.160", "216.160.83.56", "10.0.0.1"] {
+                let req = test::TestRequest::get()
+                    .uri("/abc123")
+                    .peer_addr(peer(ip))
+                    .to_request();
+                test::call_service(&app, req).await;
+            }
+
             let req = test::TestRequest::get()
-                .uri("/api/urls")
+                .uri("/api/stats/abc123")
                 .insert_header(("Authorization", format!("Bearer {token}")))
                 .to_request();
             let body: Value = test::call_and_read_body_json(&app, req).await;
-            let arr = body.as_array().unwrap();
-            assert_eq!(arr.len(), 1);
-            assert_eq!(arr[0]["short_code"], "aaa111");
-        }
+            let countries = &body["breakdown"]["countries"];
+            assert_eq!(countries[0]["value"], "GB");
+            assert_eq!(countries[0]["clicks"], 2);
+            assert_eq!(countries[1]["value"], "US");
+            assert_eq!(countries[2]["value"], "unknown");
+            assert_eq!(body["breakdown"]["regions"][0]["value"], "GB-ENG");
 
-        // --- get_stats ---
+            let req = test::TestRequest::get()
+                .uri("/api/urls/abc123/clicks")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert!(body["history"][0].get("country").is_none());
+            assert_eq!(body["history"][1]["country"], "US");
+            assert_eq!(body["history"][1]["region"], "US-WA");
+            assert_eq!(body["history"][1]["city"], "Milton");
+        }
 
         #[actix_web::test]
-        async fn get_stats_for_own_url() {
-            let state = make_test_state();
+        async fn forwarding_headers_are_only_believed_from_trusted_proxies() {
+            let mut config = test_config();
+            config.geoip_db_path = Some(GEOIP_FIXTURE.to_string());
+            config.trusted_proxies = crate::analytics::TrustedProxies::parse("10.0.0.1").unwrap();
+            let state = web::Data::new(AppState::new(config).unwrap());
             let uid = insert_test_user(&state, "alice", false);
             insert_test_url(&state, uid, "https://example.com", "abc123");
             let token = make_test_token("alice", uid, false);
             let app = setup_app!(state);
 
+            // A direct visitor from Australia claiming a London address, then
+            // a London visitor forwarded by the trusted proxy
+            for proxy in ["203.0.113.9", "10.0.0.1"] {
+                let req = test::TestRequest::get()
+                    .uri("/abc123")
+                    .peer_addr(peer(proxy))
+                    .insert_header(("X-Forwarded-For", "81.2.69.142"))
+                    .to_request();
+                test::call_service(&app, req).await;
+            }
+
             let req = test::TestRequest::get()
-                .uri("/api/stats/abc123")
+                .uri("/api/urls/abc123/clicks")
                 .insert_header(("Authorization", format!("Bearer {token}")))
                 .to_request();
-            let resp = test::call_service(&app, req).await;
-            assert_eq!(resp.status(), 200);
-            let body: Value = test::read_body_json(resp).await;
-            assert_eq!(body["short_code"], "abc123");
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            // Newest first
+            assert_eq!(body["history"][0]["country"], "GB");
+            assert_eq!(body["history"][1]["country"], "AU");
+            assert_eq!(body["unique_visitors"], 2);
         }
 
         #[actix_web::test]
@@ -1144,6 +4912,7 @@ mod tests {
                             web::scope("/api")
                                 .wrap(actix_web::middleware::from_fn(require_session))
                                 .route("/shorten", web::post().to(shorten_url))
+                                .route("/shorten/bulk", web::post().to(bulk_shorten_urls))
                                 .route("/urls", web::get().to(get_user_urls))
                                 .route("/urls/{code}", web::delete().to(delete_url))
                                 .route("/stats/{code}", web::get().to(get_stats)),
@@ -1286,7 +5055,7 @@ mod tests {
                 .insert_header(("Cookie", cookie(&token)))
                 .to_request();
             let body: Value = test::call_and_read_body_json(&app, req).await;
-            let arr = body.as_array().unwrap();
+            let arr = body["urls"].as_array().unwrap();
             assert_eq!(arr.len(), 1);
             assert_eq!(arr[0]["short_code"], "aaa111");
         }
@@ -0,0 +1,430 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use rusqlite::{params, OptionalExtension};
+use tracing::{error, info};
+
+use super::urls::get_user_id;
+use crate::db::AppState;
+use crate::models::{SaveUtmPresetRequest, UtmPreset};
+use crate::url::{validate_utm_preset_name, UtmParams};
+
+/// Parameters of the preset `user_id` saved as `name`, if there is one
+pub(crate) fn utm_preset_by_name(
+    db: &rusqlite::Connection,
+    user_id: i64,
+    name: &str,
+) -> rusqlite::Result<Option<UtmParams>> {
+    db.query_row(
+        "SELECT utm_source, utm_medium, utm_campaign, utm_term, utm_content
+         FROM utm_presets WHERE user_id = ?1 AND name = ?2",
+        params![user_id, name],
+        |row| {
+            Ok(UtmParams {
+                utm_source: row.get(0)?,
+                utm_medium: row.get(1)?,
+                utm_campaign: row.get(2)?,
+                utm_term: row.get(3)?,
+                utm_content: row.get(4)?,
+            })
+        },
+    )
+    .optional()
+}
+
+pub(crate) fn utm_preset_from_row(row: &rusqlite::Row) -> rusqlite::Result<UtmPreset> {
+    Ok(UtmPreset {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        utm: UtmParams {
+            utm_source: row.get(2)?,
+            utm_medium: row.get(3)?,
+            utm_campaign: row.get(4)?,
+            utm_term: row.get(5)?,
+            utm_content: row.get(6)?,
+        },
+        created_at: row.get(7)?,
+    })
+}
+
+pub(crate) const UTM_PRESET_COLUMNS: &str =
+    "id, name, utm_source, utm_medium, utm_campaign, utm_term, utm_content, created_at";
+
+/// Protected endpoint to list the current user's UTM presets by name
+pub async fn list_utm_presets(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut stmt = db
+        .prepare(&format!(
+            "SELECT {UTM_PRESET_COLUMNS} FROM utm_presets WHERE user_id = ?1 ORDER BY name"
+        ))
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+
+    let presets: Vec<UtmPreset> = stmt
+        .query_map(params![user_id], utm_preset_from_row)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(presets))
+}
+
+/// Protected endpoint to save a UTM preset. Saving under an existing name
+/// replaces that preset's parameters.
+pub async fn save_utm_preset(
+    data: web::Data<AppState>,
+    req_payload: web::Json<SaveUtmPresetRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let validated = validate_utm_preset_name(&req_payload.name).and_then(|name| {
+        let utm = req_payload.utm.normalized()?;
+        if utm.is_empty() {
+            return Err("A preset needs at least one utm_* value".to_string());
+        }
+        Ok((name, utm))
+    });
+    let (name, utm) = match validated {
+        Ok(validated) => validated,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let saved = db.query_row(
+        &format!(
+            "INSERT INTO utm_presets
+                 (user_id, name, utm_source, utm_medium, utm_campaign, utm_term, utm_content)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (user_id, name) DO UPDATE SET
+                 utm_source = excluded.utm_source,
+                 utm_medium = excluded.utm_medium,
+                 utm_campaign = excluded.utm_campaign,
+                 utm_term = excluded.utm_term,
+                 utm_content = excluded.utm_content
+             RETURNING {UTM_PRESET_COLUMNS}"
+        ),
+        params![
+            user_id,
+            &name,
+            utm.utm_source.as_deref(),
+            utm.utm_medium.as_deref(),
+            utm.utm_campaign.as_deref(),
+            utm.utm_term.as_deref(),
+            utm.utm_content.as_deref()
+        ],
+        utm_preset_from_row,
+    );
+    match saved {
+        Ok(preset) => {
+            info!(user_id, preset = %name, "UTM preset saved");
+            Ok(HttpResponse::Ok().json(preset))
+        }
+        Err(e) => {
+            error!(user_id, error = %e, "Failed to save UTM preset");
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to save UTM preset"
+            })))
+        }
+    }
+}
+
+/// Protected endpoint to delete one of the current user's UTM presets.
+/// Links created with it keep their parameters.
+pub async fn delete_utm_preset(
+    data: web::Data<AppState>,
+    preset_id: web::Path<i64>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    match db.execute(
+        "DELETE FROM utm_presets WHERE id = ?1 AND user_id = ?2",
+        params![*preset_id, user_id],
+    ) {
+        Ok(0) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "UTM preset not found"
+        }))),
+        Ok(_) => {
+            info!(user_id, preset_id = *preset_id, "UTM preset deleted");
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "message": "UTM preset deleted successfully"
+            })))
+        }
+        Err(_) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to delete UTM preset"
+        }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use serde_json::Value;
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use crate::handlers::urls::{bulk_shorten_urls, get_user_urls, shorten_url};
+        use crate::testing::{insert_test_user, make_test_state, make_test_token};
+        use actix_web_httpauth::middleware::HttpAuthentication;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new().app_data($state.clone()).service(
+                        web::scope("/api")
+                            .wrap(jwt)
+                            .route("/shorten", web::post().to(shorten_url))
+                            .route("/shorten/bulk", web::post().to(bulk_shorten_urls))
+                            .route("/urls", web::get().to(get_user_urls))
+                            .route("/utm-presets", web::get().to(list_utm_presets))
+                            .route("/utm-presets", web::post().to(save_utm_preset))
+                            .route("/utm-presets/{id}", web::delete().to(delete_utm_preset)),
+                    ),
+                )
+                .await
+            }};
+        }
+
+        #[actix_web::test]
+        async fn shorten_merges_utm_fields_into_the_url_and_stores_them() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "url": "https://example.com/shop?ref=home&utm_source=typo",
+                    "utm_source": " newsletter ",
+                    "utm_medium": "email",
+                    "utm_campaign": "spring sale"
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(
+                body["original_url"],
+                "https://example.com/shop?ref=home&utm_source=newsletter&utm_medium=email&utm_campaign=spring+sale"
+            );
+            assert_eq!(body["utm_source"], "newsletter");
+            assert_eq!(body["utm_campaign"], "spring sale");
+            assert!(body.get("utm_term").is_none());
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"url": "https://example.com/other"}))
+                .to_request();
+            test::call_service(&app, req).await;
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls?utm_campaign=spring%20sale")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["total"], 1);
+            assert_eq!(body["urls"][0]["utm_medium"], "email");
+        }
+
+        #[actix_web::test]
+        async fn invalid_utm_fields_are_refused() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for payload in [
+                serde_json::json!({"url": "https://example.com", "utm_source": "a\u{7}b"}),
+                serde_json::json!({"url": "https://example.com", "utm_content": "x".repeat(201)}),
+                serde_json::json!({"url": "https://example.com", "utm_preset": "missing"}),
+            ] {
+                let req = test::TestRequest::post()
+                    .uri("/api/shorten")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(&payload)
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{payload}");
+            }
+        }
+
+        #[actix_web::test]
+        async fn presets_fill_in_fields_the_request_leaves_out() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::post()
+                .uri("/api/utm-presets")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({
+                    "name": "Newsletter",
+                    "utm_source": "newsletter",
+                    "utm_medium": "email",
+                    "utm_campaign": "weekly"
+                }))
+                .to_request();
+            let preset: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(preset["name"], "Newsletter");
+
+            let req = test::TestRequest::post()
+                .uri("/api/shorten/bulk")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"items": [
+                    {"url": "https://example.com/a", "utm_preset": "Newsletter"},
+                    {"url": "https://example.com/b", "utm_preset": "Newsletter", "utm_campaign": "launch"}
+                ]}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(
+                body["results"][0]["original_url"],
+                "https://example.com/a?utm_source=newsletter&utm_medium=email&utm_campaign=weekly"
+            );
+            assert_eq!(body["results"][1]["utm_campaign"], "launch");
+            assert_eq!(body["results"][1]["utm_source"], "newsletter");
+        }
+
+        #[actix_web::test]
+        async fn presets_are_saved_by_name_listed_and_deleted_per_user() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            let token = make_test_token("alice", uid, false);
+            let other_token = make_test_token("bob", other, false);
+            let app = setup_app!(state);
+
+            let save = |name: &str, source: &str| {
+                test::TestRequest::post()
+                    .uri("/api/utm-presets")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({"name": name, "utm_source": source}))
+                    .to_request()
+            };
+            let first: Value = test::call_and_read_body_json(&app, save("Ads", "google")).await;
+            let second: Value = test::call_and_read_body_json(&app, save(" Ads ", "bing")).await;
+            assert_eq!(first["id"], second["id"]);
+            assert_eq!(second["utm_source"], "bing");
+
+            // A preset needs a name and at least one value
+            let req = test::TestRequest::post()
+                .uri("/api/utm-presets")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"name": "Empty", "utm_source": " "}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+            assert_eq!(
+                test::call_service(&app, save("", "google")).await.status(),
+                400
+            );
+
+            let req = test::TestRequest::get()
+                .uri("/api/utm-presets")
+                .insert_header(("Authorization", format!("Bearer {other_token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body, serde_json::json!([]));
+
+            let uri = format!("/api/utm-presets/{}", first["id"]);
+            let req = test::TestRequest::delete()
+                .uri(&uri)
+                .insert_header(("Authorization", format!("Bearer {other_token}")))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+            let req = test::TestRequest::delete()
+                .uri(&uri)
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/utm-presets")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body, serde_json::json!([]));
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+        use crate::testing::{insert_saas_user, make_saas_session, make_test_state};
+
+        #[actix_web::test]
+        async fn save_and_list_presets_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route("/utm-presets", web::get().to(list_utm_presets))
+                        .route("/utm-presets", web::post().to(save_utm_preset)),
+                ),
+            )
+            .await;
+            let cookie = format!("{RUS_SESSION_COOKIE}={token}");
+
+            let req = test::TestRequest::post()
+                .uri("/api/utm-presets")
+                .insert_header(("Cookie", cookie.clone()))
+                .set_json(serde_json::json!({"name": "Social", "utm_medium": "social"}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/utm-presets")
+                .insert_header(("Cookie", cookie))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body[0]["name"], "Social");
+            assert_eq!(body[0]["utm_medium"], "social");
+        }
+    }
+}
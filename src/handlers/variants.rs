@@ -0,0 +1,414 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use rusqlite::params;
+use tracing::{error, info};
+
+use super::tags::{owned_url_id, url_not_found};
+use super::urls::get_user_id;
+use crate::db::AppState;
+use crate::models::{UpdateUrlVariantsRequest, UrlVariant};
+use crate::url::validate_variants;
+
+/// A/B test variants of a URL, in the order they were added
+pub(crate) fn variants_for_url(
+    db: &rusqlite::Connection,
+    url_id: i64,
+) -> rusqlite::Result<Vec<UrlVariant>> {
+    let mut stmt = db.prepare(
+        "SELECT id, destination, weight, clicks FROM url_variants WHERE url_id = ?1 ORDER BY id",
+    )?;
+    let variants = stmt
+        .query_map(params![url_id], |row| {
+            Ok(UrlVariant {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                weight: row.get(2)?,
+                clicks: row.get(3)?,
+            })
+        })?
+        .collect();
+    variants
+}
+
+fn variants_response(db: &rusqlite::Connection, url_id: i64) -> Result<HttpResponse> {
+    let variants = variants_for_url(db, url_id)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    let sticky: bool = db
+        .query_row(
+            "SELECT sticky_variants FROM urls WHERE id = ?1",
+            params![url_id],
+            |row| row.get(0),
+        )
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Database error"))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "sticky": sticky,
+        "variants": variants
+    })))
+}
+
+/// Protected endpoint to list a URL's A/B test variants and their clicks
+pub async fn get_url_variants(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    match owned_url_id(&db, code.as_str(), user_id) {
+        Some(url_id) => variants_response(&db, url_id),
+        None => Ok(url_not_found()),
+    }
+}
+
+/// Protected endpoint to replace all of a URL's A/B test variants. A
+/// variant whose destination is kept keeps its click count; dropped
+/// variants are deleted along with theirs.
+pub async fn replace_url_variants(
+    data: web::Data<AppState>,
+    code: web::Path<String>,
+    req_payload: web::Json<UpdateUrlVariantsRequest>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let user_id = match get_user_id(&http_req) {
+        Some(id) => id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Unauthorized"
+            })));
+        }
+    };
+
+    let variants = match validate_variants(&req_payload.variants, data.config.max_url_length) {
+        Ok(variants) => variants,
+        Err(error_message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": error_message
+            })));
+        }
+    };
+
+    let mut db = data.db.lock().unwrap_or_else(|e| e.into_inner());
+
+    let url_id = match owned_url_id(&db, code.as_str(), user_id) {
+        Some(id) => id,
+        None => return Ok(url_not_found()),
+    };
+
+    let result = db.transaction().and_then(|tx| {
+        for existing in variants_for_url(&tx, url_id)? {
+            if !variants.iter().any(|(url, _)| *url == existing.url) {
+                tx.execute(
+                    "DELETE FROM url_variants WHERE id = ?1",
+                    params![existing.id],
+                )?;
+            }
+        }
+        for (url, weight) in &variants {
+            tx.execute(
+                "INSERT INTO url_variants (url_id, destination, weight) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (url_id, destination) DO UPDATE SET weight = excluded.weight",
+                params![url_id, url, weight],
+            )?;
+        }
+        tx.execute(
+            "UPDATE urls SET sticky_variants = ?1 WHERE id = ?2",
+            params![req_payload.sticky, url_id],
+        )?;
+        tx.commit()
+    });
+    if let Err(e) = result {
+        error!(user_id, short_code = %code.as_str(), error = %e, "Failed to replace variants");
+        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update variants"
+        })));
+    }
+
+    info!(user_id, short_code = %code.as_str(), variants = variants.len(), sticky = req_payload.sticky, "Variants replaced");
+    variants_response(&db, url_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use serde_json::Value;
+
+    #[cfg(feature = "standalone")]
+    mod standalone {
+        use super::*;
+        use crate::auth::middleware::jwt_validator;
+        use crate::handlers::urls::{get_stats, redirect_url};
+        use crate::testing::{insert_test_url, insert_test_user, make_test_state, make_test_token};
+        use crate::url::VARIANT_COOKIE;
+        use actix_web_httpauth::middleware::HttpAuthentication;
+
+        macro_rules! setup_app {
+            ($state:expr) => {{
+                let jwt = HttpAuthentication::bearer(jwt_validator);
+                test::init_service(
+                    App::new()
+                        .app_data($state.clone())
+                        .service(
+                            web::scope("/api")
+                                .wrap(jwt)
+                                .route("/urls/{code}/variants", web::get().to(get_url_variants))
+                                .route("/urls/{code}/variants", web::put().to(replace_url_variants))
+                                .route("/stats/{code}", web::get().to(get_stats)),
+                        )
+                        .route("/{code}", web::get().to(redirect_url)),
+                )
+                .await
+            }};
+        }
+
+        #[actix_web::test]
+        async fn redirects_are_split_and_counted_per_variant() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "ab0001");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::put()
+                .uri("/api/urls/ab0001/variants")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"variants": [
+                    {"url": "https://example.com/a"},
+                    {"url": "https://example.com/b", "weight": 1}
+                ]}))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["sticky"], false);
+            assert_eq!(body["variants"][0]["weight"], 1);
+
+            for _ in 0..40 {
+                let resp =
+                    test::call_service(&app, test::TestRequest::get().uri("/ab0001").to_request())
+                        .await;
+                let location = resp.headers().get("Location").unwrap().to_str().unwrap();
+                assert!(
+                    ["https://example.com/a", "https://example.com/b"].contains(&location),
+                    "{location}"
+                );
+                assert!(resp.headers().get("Set-Cookie").is_none());
+            }
+            // Bots are not part of the test
+            let req = test::TestRequest::get()
+                .uri("/ab0001")
+                .insert_header(("User-Agent", "Twitterbot/1.0"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+
+            let req = test::TestRequest::get()
+                .uri("/api/stats/ab0001")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let a = body["variants"][0]["clicks"].as_u64().unwrap();
+            let b = body["variants"][1]["clicks"].as_u64().unwrap();
+            assert_eq!(a + b, 40);
+            assert!(a > 0 && b > 0, "a = {a}, b = {b}");
+            assert_eq!(body["clicks"], 40);
+        }
+
+        #[actix_web::test]
+        async fn sticky_variant_is_remembered_by_cookie() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "ab0002");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            let req = test::TestRequest::put()
+                .uri("/api/urls/ab0002/variants")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .set_json(serde_json::json!({"sticky": true, "variants": [
+                    {"url": "https://example.com/a"},
+                    {"url": "https://example.com/b"}
+                ]}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/ab0002").to_request())
+                    .await;
+            let first = resp.headers().get("Location").unwrap().clone();
+            let cookie = resp
+                .response()
+                .cookies()
+                .find(|c| c.name() == VARIANT_COOKIE)
+                .unwrap()
+                .into_owned();
+            assert_eq!(cookie.path(), Some("/ab0002"));
+            assert_eq!(cookie.http_only(), Some(true));
+
+            for _ in 0..20 {
+                let req = test::TestRequest::get()
+                    .uri("/ab0002")
+                    .cookie(cookie.clone())
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.headers().get("Location").unwrap(), first);
+            }
+
+            // A cookie for a variant that no longer exists is ignored
+            let req = test::TestRequest::get()
+                .uri("/ab0002")
+                .cookie(actix_web::cookie::Cookie::new(VARIANT_COOKIE, "999999"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 302);
+            assert!(resp
+                .response()
+                .cookies()
+                .any(|c| c.name() == VARIANT_COOKIE));
+        }
+
+        #[actix_web::test]
+        async fn replacing_variants_keeps_counts_of_unchanged_destinations() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            insert_test_url(&state, uid, "https://example.com", "ab0003");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+            let put = |variants: Value| {
+                test::TestRequest::put()
+                    .uri("/api/urls/ab0003/variants")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({ "variants": variants }))
+                    .to_request()
+            };
+
+            let req = put(serde_json::json!([
+                {"url": "https://example.com/a", "weight": 1000},
+                {"url": "https://example.com/b", "weight": 1}
+            ]));
+            test::call_service(&app, req).await;
+            {
+                let db = state.db.lock().unwrap();
+                db.execute("UPDATE url_variants SET clicks = 7", [])
+                    .unwrap();
+            }
+
+            let req = put(serde_json::json!([
+                {"url": "https://example.com/a", "weight": 2},
+                {"url": "https://example.com/c"}
+            ]));
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            let variants = body["variants"].as_array().unwrap();
+            assert_eq!(variants.len(), 2);
+            assert_eq!(variants[0]["url"], "https://example.com/a");
+            assert_eq!(variants[0]["weight"], 2);
+            assert_eq!(variants[0]["clicks"], 7);
+            assert_eq!(variants[1]["url"], "https://example.com/c");
+            assert_eq!(variants[1]["clicks"], 0);
+
+            // An empty list ends the test
+            let req = put(serde_json::json!([]));
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["variants"], serde_json::json!([]));
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri("/ab0003").to_request())
+                    .await;
+            assert_eq!(
+                resp.headers().get("Location").unwrap(),
+                "https://example.com"
+            );
+        }
+
+        #[actix_web::test]
+        async fn invalid_variants_and_other_users_links_are_refused() {
+            let state = make_test_state();
+            let uid = insert_test_user(&state, "alice", false);
+            let other = insert_test_user(&state, "bob", false);
+            insert_test_url(&state, uid, "https://example.com", "ab0004");
+            insert_test_url(&state, other, "https://bob.example.com", "ab0005");
+            let token = make_test_token("alice", uid, false);
+            let app = setup_app!(state);
+
+            for variants in [
+                serde_json::json!([{"url": "https://example.com/a"}]),
+                serde_json::json!([{"url": "https://example.com/a"}, {"url": "ftp://example.com/b"}]),
+                serde_json::json!([{"url": "https://example.com/a", "weight": 0}, {"url": "https://example.com/b"}]),
+            ] {
+                let req = test::TestRequest::put()
+                    .uri("/api/urls/ab0004/variants")
+                    .insert_header(("Authorization", format!("Bearer {token}")))
+                    .set_json(serde_json::json!({ "variants": variants }))
+                    .to_request();
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), 400, "{variants}");
+            }
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/ab0005/variants")
+                .insert_header(("Authorization", format!("Bearer {token}")))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 404);
+        }
+    }
+
+    #[cfg(feature = "saas")]
+    mod saas {
+        use super::*;
+        use crate::oidc::require_session;
+        use crate::oidc::session::RUS_SESSION_COOKIE;
+        use crate::testing::{
+            insert_saas_url, insert_saas_user, make_saas_session, make_test_state,
+        };
+
+        #[actix_web::test]
+        async fn replace_and_list_variants_with_session_cookie() {
+            let state = make_test_state();
+            let uid = insert_saas_user(
+                &state,
+                "alice",
+                "11111111-1111-1111-1111-111111111111",
+                false,
+            );
+            insert_saas_url(&state, uid, "https://example.com", "sab001");
+            let token = make_saas_session(&state, uid);
+            let app = test::init_service(
+                App::new().app_data(state.clone()).service(
+                    web::scope("/api")
+                        .wrap(actix_web::middleware::from_fn(require_session))
+                        .route("/urls/{code}/variants", web::get().to(get_url_variants))
+                        .route("/urls/{code}/variants", web::put().to(replace_url_variants)),
+                ),
+            )
+            .await;
+            let cookie = format!("{RUS_SESSION_COOKIE}={token}");
+
+            let req = test::TestRequest::put()
+                .uri("/api/urls/sab001/variants")
+                .insert_header(("Cookie", cookie.clone()))
+                .set_json(serde_json::json!({"sticky": true, "variants": [
+                    {"url": "https://example.com/a", "weight": 3},
+                    {"url": "https://example.com/b"}
+                ]}))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+            let req = test::TestRequest::get()
+                .uri("/api/urls/sab001/variants")
+                .insert_header(("Cookie", cookie))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["sticky"], true);
+            assert_eq!(body["variants"][0]["weight"], 3);
+            assert_eq!(body["variants"][1]["url"], "https://example.com/b");
+        }
+    }
+}
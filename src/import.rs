@@ -0,0 +1,1035 @@
+//! Importing links exported from other URL shorteners.
+//!
+//! Bitly, YOURLS and Shlink all export the same handful of fields under
+//! different names. [`parse_import`] maps a CSV or JSON export onto
+//! [`ImportRecord`]s and [`import_links`] stores them for one user in a
+//! single transaction, keeping the original short codes where possible.
+//! The zip bundle exported by rus itself is read too, along with the UTM
+//! presets saved in it.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use rusqlite::{params, Connection};
+use serde_json::Value;
+
+use crate::models::{
+    ExportedVariant, GeoRule, ImportConflict, ImportReport, ImportRowError, VariantInput,
+};
+use crate::url::{
+    generate_short_code, normalize_tag, validate_alias, validate_geo_rules,
+    validate_redirect_status, validate_url, validate_utm_preset_name, validate_variants,
+    Passthrough, PlatformUrls, UtmParams, MAX_TAGS_PER_URL,
+};
+
+/// Largest export accepted in one request, in bytes
+pub const IMPORT_MAX_BYTES: usize = 10 * 1024 * 1024;
+
+/// Largest number of links accepted in one import
+pub const IMPORT_MAX_ROWS: usize = 50_000;
+
+// Column / field names used by the supported exports, normalised to
+// lowercase alphanumerics and listed in order of preference.
+const URL_FIELDS: &[&str] = &["longurl", "url", "originalurl", "destination", "target"];
+const CODE_FIELDS: &[&str] = &[
+    "shortcode",
+    "keyword",
+    "code",
+    "backhalf",
+    "bitlink",
+    "shorturl",
+    "link",
+    "id",
+];
+const NAME_FIELDS: &[&str] = &["title", "name"];
+const CREATED_FIELDS: &[&str] = &[
+    "createdat",
+    "datecreated",
+    "created",
+    "creationdate",
+    "timestamp",
+];
+const CLICK_FIELDS: &[&str] = &[
+    "clicks",
+    "visitscount",
+    "visitssummarytotal",
+    "visits",
+    "totalclicks",
+    "clickcount",
+];
+const TAGS_FIELDS: &[&str] = &["tags"];
+// Shlink keeps its limits under `meta`
+const EXPIRES_FIELDS: &[&str] = &["expiresat", "validuntil", "metavaliduntil"];
+const MAX_CLICKS_FIELDS: &[&str] = &["maxclicks", "maxvisits", "metamaxvisits"];
+const ACTIVE_FROM_FIELDS: &[&str] = &["activefrom", "validsince", "metavalidsince"];
+const ACTIVE_UNTIL_FIELDS: &[&str] = &["activeuntil"];
+const PASSWORD_PROTECTED_FIELDS: &[&str] = &["passwordprotected"];
+// Per-platform destinations, also found in Shlink's `deviceLongUrls`
+const IOS_URL_FIELDS: &[&str] = &["iosurl", "devicelongurlsios"];
+const ANDROID_URL_FIELDS: &[&str] = &["androidurl", "devicelongurlsandroid"];
+const DESKTOP_URL_FIELDS: &[&str] = &["desktopurl", "devicelongurlsdesktop"];
+// Link settings only found in exports from rus itself
+const PUBLIC_STATS_FIELDS: &[&str] = &["publicstats"];
+const GEO_RULES_FIELDS: &[&str] = &["georules"];
+const VARIANTS_FIELDS: &[&str] = &["variants"];
+const STICKY_VARIANTS_FIELDS: &[&str] = &["stickyvariants"];
+const REDIRECT_STATUS_FIELDS: &[&str] = &["redirectstatus"];
+const PASSTHROUGH_FIELDS: &[&str] = &["passthrough"];
+const UTM_SOURCE_FIELDS: &[&str] = &["utmsource"];
+const UTM_MEDIUM_FIELDS: &[&str] = &["utmmedium"];
+const UTM_CAMPAIGN_FIELDS: &[&str] = &["utmcampaign"];
+const UTM_TERM_FIELDS: &[&str] = &["utmterm"];
+const UTM_CONTENT_FIELDS: &[&str] = &["utmcontent"];
+
+// Files read from a rus export bundle
+const BUNDLE_LINKS_FILE: &str = "links.csv";
+const BUNDLE_UTM_PRESETS_FILE: &str = "utm_presets.json";
+
+/// Format of an uploaded export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+    /// The zip bundle from `GET /api/export`
+    Zip,
+}
+
+impl ImportFormat {
+    /// Parse a `format` parameter (`csv`, `json` or `zip`)
+    pub fn from_param(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the data itself: zip files start with `PK`,
+    /// JSON exports with `[` or `{`
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(b"PK\x03\x04") {
+            return Self::Zip;
+        }
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'[') | Some(b'{') => Self::Json,
+            _ => Self::Csv,
+        }
+    }
+}
+
+/// What to do with a link whose short code is already taken (or unusable here)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Import it under a newly generated code
+    #[default]
+    Rename,
+    /// Leave it out of the import
+    Skip,
+}
+
+impl OnConflict {
+    /// Parse an `on_conflict` parameter (`rename` or `skip`)
+    pub fn from_param(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "rename" => Some(Self::Rename),
+            "skip" => Some(Self::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// One link read from an export
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportRecord {
+    pub original_url: String,
+    pub short_code: Option<String>,
+    pub name: Option<String>,
+    /// Creation time as a UTC `YYYY-MM-DD HH:MM:SS` timestamp
+    pub created_at: Option<String>,
+    pub clicks: u64,
+    /// Normalised tag names
+    pub tags: Vec<String>,
+    /// Timestamps in the same format as `created_at`. Unlike a new link's,
+    /// they may be in the past: an expired link stays expired.
+    pub expires_at: Option<String>,
+    pub max_clicks: Option<u64>,
+    pub active_from: Option<String>,
+    pub active_until: Option<String>,
+    pub public_stats: bool,
+    /// Not validated yet; [`import_links`] checks them like the main URL
+    pub platform_urls: PlatformUrls,
+    /// Not validated yet, like `platform_urls`
+    pub geo_rules: Vec<GeoRule>,
+    pub variants: Vec<ExportedVariant>,
+    pub sticky_variants: bool,
+    /// Not validated yet
+    pub redirect_status: Option<u16>,
+    pub passthrough: Option<Passthrough>,
+    /// Not validated yet; [`import_links`] adds them to the destination
+    pub utm: UtmParams,
+}
+
+/// Parse an export into records. Problems with individual rows are kept as
+/// per-row errors; only an unreadable file is an error for the whole import.
+pub fn parse_import(
+    data: &[u8],
+    format: ImportFormat,
+) -> Result<Vec<Result<ImportRecord, String>>, String> {
+    let rows = match format {
+        ImportFormat::Csv => csv_rows(data)?,
+        ImportFormat::Json => json_rows(data)?,
+        ImportFormat::Zip => csv_rows(
+            &bundle_file(data, BUNDLE_LINKS_FILE)?
+                .ok_or_else(|| format!("The archive has no {}", BUNDLE_LINKS_FILE))?,
+        )?,
+    };
+    if rows.is_empty() {
+        return Err("The export contains no links".to_string());
+    }
+    if rows.len() > IMPORT_MAX_ROWS {
+        return Err(format!(
+            "An import may contain at most {} links",
+            IMPORT_MAX_ROWS
+        ));
+    }
+    Ok(rows.iter().map(record_from_fields).collect())
+}
+
+/// UTM presets saved in an export bundle, validated like those saved through
+/// the API. Other formats carry none.
+pub fn parse_utm_presets(
+    data: &[u8],
+    format: ImportFormat,
+) -> Result<Vec<(String, UtmParams)>, String> {
+    #[derive(serde::Deserialize)]
+    struct Preset {
+        name: String,
+        #[serde(flatten)]
+        utm: UtmParams,
+    }
+
+    if format != ImportFormat::Zip {
+        return Ok(Vec::new());
+    }
+    let Some(file) = bundle_file(data, BUNDLE_UTM_PRESETS_FILE)? else {
+        return Ok(Vec::new());
+    };
+    let presets: Vec<Preset> = serde_json::from_slice(&file)
+        .map_err(|e| format!("Invalid {}: {}", BUNDLE_UTM_PRESETS_FILE, e))?;
+    presets
+        .into_iter()
+        .map(|preset| {
+            let name = validate_utm_preset_name(&preset.name)?;
+            let utm = preset.utm.normalized()?;
+            if utm.is_empty() {
+                return Err(format!("UTM preset '{}' has no utm_* values", name));
+            }
+            Ok((name, utm))
+        })
+        .collect()
+}
+
+/// Contents of the file `name` in a zip archive, if it has one. Reading
+/// stops past [`IMPORT_MAX_BYTES`] so a small archive cannot unpack into
+/// an unbounded amount of memory.
+fn bundle_file(data: &[u8], name: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| format!("Invalid zip archive: {}", e))?;
+    let file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(format!("Invalid zip archive: {}", e)),
+    };
+    let mut contents = Vec::new();
+    file.take(IMPORT_MAX_BYTES as u64 + 1)
+        .read_to_end(&mut contents)
+        .map_err(|e| format!("Cannot read {}: {}", name, e))?;
+    if contents.len() > IMPORT_MAX_BYTES {
+        return Err(format!("{} is larger than the import limit", name));
+    }
+    Ok(Some(contents))
+}
+
+/// Lowercase a field name and drop everything but letters and digits, so
+/// `Long URL`, `long_url` and `longUrl` all become `longurl`
+fn normalize_field(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn csv_rows(data: &[u8]) -> Result<Vec<HashMap<String, String>>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Invalid CSV: {}", e))?
+        .iter()
+        .map(normalize_field)
+        .collect();
+    if !headers.iter().any(|h| URL_FIELDS.contains(&h.as_str())) {
+        return Err(format!(
+            "CSV header has no URL column (expected one of: {})",
+            URL_FIELDS.join(", ")
+        ));
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+        if rows.len() > IMPORT_MAX_ROWS {
+            break;
+        }
+        rows.push(
+            headers
+                .iter()
+                .cloned()
+                .zip(record.iter().map(str::to_string))
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+fn json_rows(data: &[u8]) -> Result<Vec<HashMap<String, String>>, String> {
+    let value: Value = serde_json::from_slice(data).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let items = json_items(value).ok_or_else(|| {
+        "JSON export must be an array of links or an object holding one".to_string()
+    })?;
+    Ok(items
+        .into_iter()
+        .map(|item| {
+            let mut fields = HashMap::new();
+            flatten_json("", item, &mut fields);
+            fields
+        })
+        .collect())
+}
+
+/// Find the list of links in a JSON export: a bare array, Shlink's
+/// `{"shortUrls": {"data": [...]}}`, YOURLS' `{"links": {"link_1": {...}}}`,
+/// or a similar wrapper
+fn json_items(value: Value) -> Option<Vec<Value>> {
+    match value {
+        Value::Array(items) => Some(items),
+        Value::Object(mut map) => {
+            for key in ["links", "shortUrls", "urls", "data"] {
+                if let Some(inner) = map.remove(key) {
+                    return json_items(inner);
+                }
+            }
+            // Links keyed by id
+            if !map.is_empty() && map.values().all(Value::is_object) {
+                Some(map.into_iter().map(|(_, v)| v).collect())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Collect the scalar fields of a JSON link, naming nested ones by their
+/// path (`visitsSummary.total` becomes `visitssummarytotal`)
+fn flatten_json(prefix: &str, value: Value, fields: &mut HashMap<String, String>) {
+    let Value::Object(map) = value else {
+        return;
+    };
+    for (key, value) in map {
+        let key = format!("{}{}", prefix, normalize_field(&key));
+        match value {
+            Value::String(s) => {
+                fields.insert(key, s);
+            }
+            Value::Number(n) => {
+                fields.insert(key, n.to_string());
+            }
+            Value::Bool(b) => {
+                fields.insert(key, b.to_string());
+            }
+            Value::Object(_) => flatten_json(&key, value, fields),
+            // Lists (such as rus' own `geo_rules`) are kept as JSON text
+            Value::Array(_) => {
+                fields.insert(key, value.to_string());
+            }
+            Value::Null => {}
+        }
+    }
+}
+
+/// First non-empty value among `names`
+fn first_field<'a>(fields: &'a HashMap<String, String>, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .filter_map(|name| fields.get(*name))
+        .map(|value| value.trim())
+        .find(|value| !value.is_empty())
+}
+
+fn record_from_fields(fields: &HashMap<String, String>) -> Result<ImportRecord, String> {
+    let original_url = first_field(fields, URL_FIELDS)
+        .ok_or("Missing destination URL")?
+        .to_string();
+    let short_code = first_field(fields, CODE_FIELDS).and_then(code_from_value);
+    let name = first_field(fields, NAME_FIELDS).map(str::to_string);
+    let created_at = first_field(fields, CREATED_FIELDS)
+        .map(|v| parse_timestamp("created date", v))
+        .transpose()?;
+    let clicks = first_field(fields, CLICK_FIELDS)
+        .map(parse_clicks)
+        .transpose()?
+        .unwrap_or(0);
+
+    // The export only says whether a link had a password, not what it was;
+    // importing it without one would make it public
+    let password_protected = first_field(fields, PASSWORD_PROTECTED_FIELDS)
+        .map(|v| parse_flag("password_protected", v))
+        .transpose()?
+        .unwrap_or(false);
+    if password_protected {
+        return Err(
+            "Password-protected links cannot be imported; the export does not contain passwords"
+                .to_string(),
+        );
+    }
+
+    let tags = first_field(fields, TAGS_FIELDS)
+        .map(parse_tags)
+        .transpose()?
+        .unwrap_or_default();
+    let timestamp = |field, names| {
+        first_field(fields, names)
+            .map(|v| parse_timestamp(field, v))
+            .transpose()
+    };
+    let expires_at = timestamp("expires_at", EXPIRES_FIELDS)?;
+    let active_from = timestamp("active_from", ACTIVE_FROM_FIELDS)?;
+    let active_until = timestamp("active_until", ACTIVE_UNTIL_FIELDS)?;
+    if let (Some(from), Some(until)) = (&active_from, &active_until) {
+        if from >= until {
+            return Err("active_from must be before active_until".to_string());
+        }
+    }
+    let max_clicks = first_field(fields, MAX_CLICKS_FIELDS)
+        .map(|v| match parse_clicks(v) {
+            Ok(0) => Err("max_clicks must be at least 1".to_string()),
+            other => other,
+        })
+        .transpose()?;
+    let public_stats = first_field(fields, PUBLIC_STATS_FIELDS)
+        .map(|v| parse_flag("public_stats", v))
+        .transpose()?
+        .unwrap_or(false);
+    let platform_url = |names| first_field(fields, names).map(str::to_string);
+    let platform_urls = PlatformUrls {
+        ios_url: platform_url(IOS_URL_FIELDS),
+        android_url: platform_url(ANDROID_URL_FIELDS),
+        desktop_url: platform_url(DESKTOP_URL_FIELDS),
+    };
+    let geo_rules = first_field(fields, GEO_RULES_FIELDS)
+        .map(|v| parse_json_list("geo_rules", v))
+        .transpose()?
+        .unwrap_or_default();
+    let variants: Vec<ExportedVariant> = first_field(fields, VARIANTS_FIELDS)
+        .map(|v| parse_json_list("variants", v))
+        .transpose()?
+        .unwrap_or_default();
+    // Stored like the link's own clicks, in a signed 64-bit integer
+    if let Some(variant) = variants.iter().find(|v| i64::try_from(v.clicks).is_err()) {
+        return Err(format!(
+            "Variant click count '{}' is too large",
+            variant.clicks
+        ));
+    }
+    let sticky_variants = first_field(fields, STICKY_VARIANTS_FIELDS)
+        .map(|v| parse_flag("sticky_variants", v))
+        .transpose()?
+        .unwrap_or(false);
+    let redirect_status = first_field(fields, REDIRECT_STATUS_FIELDS)
+        .map(|v| {
+            v.parse::<u16>()
+                .map_err(|_| format!("Invalid redirect_status: {}", v))
+        })
+        .transpose()?;
+    let passthrough = first_field(fields, PASSTHROUGH_FIELDS)
+        .map(Passthrough::parse)
+        .transpose()?;
+    let utm_field = |names| first_field(fields, names).map(str::to_string);
+    let utm = UtmParams {
+        utm_source: utm_field(UTM_SOURCE_FIELDS),
+        utm_medium: utm_field(UTM_MEDIUM_FIELDS),
+        utm_campaign: utm_field(UTM_CAMPAIGN_FIELDS),
+        utm_term: utm_field(UTM_TERM_FIELDS),
+        utm_content: utm_field(UTM_CONTENT_FIELDS),
+    };
+
+    Ok(ImportRecord {
+        original_url,
+        short_code,
+        name,
+        created_at,
+        clicks,
+        tags,
+        expires_at,
+        max_clicks,
+        active_from,
+        active_until,
+        public_stats,
+        platform_urls,
+        geo_rules,
+        variants,
+        sticky_variants,
+        redirect_status,
+        passthrough,
+        utm,
+    })
+}
+
+/// Short code from a code or a full short URL (`https://bit.ly/abc123`)
+fn code_from_value(value: &str) -> Option<String> {
+    let path = value.split(['?', '#']).next().unwrap_or_default();
+    let code = path.trim_end_matches('/').rsplit('/').next()?;
+    (!code.is_empty()).then(|| code.to_string())
+}
+
+/// Accept RFC 3339, `YYYY-MM-DD HH:MM:SS`, `YYYY-MM-DD` and Unix seconds
+fn parse_timestamp(field: &str, value: &str) -> Result<String, String> {
+    let parsed = DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc).naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default())
+        })
+        .ok()
+        .or_else(|| {
+            value
+                .parse::<i64>()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                .map(|dt| dt.naive_utc())
+        })
+        .ok_or_else(|| format!("Invalid {} '{}'", field, value))?;
+    Ok(parsed.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// Tags as a JSON array (Shlink, rus' JSON export) or separated by `;`
+/// (rus' CSV export)
+fn parse_tags(value: &str) -> Result<Vec<String>, String> {
+    let raw: Vec<String> = if value.starts_with('[') {
+        parse_json_list("tags", value)?
+    } else {
+        value.split(';').map(str::to_string).collect()
+    };
+    let mut tags = Vec::new();
+    for tag in raw.iter().filter(|t| !t.trim().is_empty()) {
+        let tag = normalize_tag(tag)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if tags.len() > MAX_TAGS_PER_URL {
+        return Err(format!("A link can have at most {} tags", MAX_TAGS_PER_URL));
+    }
+    Ok(tags)
+}
+
+/// A list exported by rus as a JSON array
+fn parse_json_list<T: serde::de::DeserializeOwned>(
+    field: &str,
+    value: &str,
+) -> Result<Vec<T>, String> {
+    serde_json::from_str(value).map_err(|e| format!("Invalid {}: {}", field, e))
+}
+
+fn parse_flag(field: &str, value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => Err(format!("Invalid {} value '{}'", field, value)),
+    }
+}
+
+/// Parse a click count, which has to fit SQLite's signed 64-bit integers
+fn parse_clicks(value: &str) -> Result<u64, String> {
+    let clicks: u64 = value
+        .replace(',', "")
+        .parse()
+        .map_err(|_| format!("Invalid click count '{}'", value))?;
+    if i64::try_from(clicks).is_err() {
+        return Err(format!("Click count '{}' is too large", value));
+    }
+    Ok(clicks)
+}
+
+/// Store parsed records and UTM presets for `user_id` in one transaction, so
+/// a failure leaves nothing half imported. Row numbers in the report are
+/// 1-based positions in the export.
+pub fn import_links(
+    db: &mut Connection,
+    user_id: i64,
+    records: Vec<Result<ImportRecord, String>>,
+    presets: &[(String, UtmParams)],
+    on_conflict: OnConflict,
+    max_url_length: usize,
+) -> rusqlite::Result<ImportReport> {
+    let tx = db.transaction()?;
+    let mut report = ImportReport::default();
+
+    for (index, record) in records.into_iter().enumerate() {
+        let row = index + 1;
+        let record = match record.and_then(|mut r| {
+            validate_url(&r.original_url, max_url_length)?;
+            r.platform_urls = PlatformUrls::parse(
+                r.platform_urls.ios_url.as_deref(),
+                r.platform_urls.android_url.as_deref(),
+                r.platform_urls.desktop_url.as_deref(),
+                max_url_length,
+            )?;
+            r.geo_rules = validate_geo_rules(&r.geo_rules, max_url_length)?;
+            if let Some(status) = r.redirect_status {
+                validate_redirect_status(status)?;
+            }
+            // Already in the URL for rus' own exports; added for anything else
+            r.utm = r.utm.normalized()?;
+            r.original_url = r.utm.apply(&r.original_url, max_url_length)?;
+            let inputs: Vec<VariantInput> = r
+                .variants
+                .iter()
+                .map(|v| VariantInput {
+                    url: v.url.clone(),
+                    weight: Some(v.weight),
+                })
+                .collect();
+            // Validation keeps the order, so the click counts still line up
+            for (variant, (url, _)) in r
+                .variants
+                .iter_mut()
+                .zip(validate_variants(&inputs, max_url_length)?)
+            {
+                variant.url = url;
+            }
+            Ok(r)
+        }) {
+            Ok(record) => record,
+            Err(error) => {
+                report.errors.push(ImportRowError { row, error });
+                continue;
+            }
+        };
+
+        let code_taken = |code: &str| -> rusqlite::Result<bool> {
+            tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM urls WHERE short_code = ?1)",
+                params![code],
+                |r| r.get(0),
+            )
+        };
+
+        let conflict = match &record.short_code {
+            Some(code) => match validate_alias(code) {
+                Err(reason) => Some((code.clone(), reason)),
+                Ok(()) if code_taken(code)? => {
+                    Some((code.clone(), "Short code is already taken".to_string()))
+                }
+                Ok(()) => None,
+            },
+            None => None,
+        };
+
+        let short_code = match (&record.short_code, conflict) {
+            (_, Some((short_code, reason))) if on_conflict == OnConflict::Skip => {
+                report.skipped += 1;
+                report.conflicts.push(ImportConflict {
+                    row,
+                    short_code,
+                    reason,
+                    assigned_code: None,
+                });
+                continue;
+            }
+            (Some(code), None) => code.clone(),
+            (_, conflict) => {
+                let mut generated = generate_short_code();
+                while code_taken(&generated)? {
+                    generated = generate_short_code();
+                }
+                if let Some((short_code, reason)) = conflict {
+                    report.conflicts.push(ImportConflict {
+                        row,
+                        short_code,
+                        reason,
+                        assigned_code: Some(generated.clone()),
+                    });
+                }
+                generated
+            }
+        };
+
+        tx.execute(
+            "INSERT INTO urls
+                 (user_id, original_url, short_code, name, clicks, created_at, public_stats,
+                  ios_url, android_url, desktop_url, sticky_variants, redirect_status,
+                  passthrough, utm_source, utm_medium, utm_campaign, utm_term, utm_content,
+                  expires_at, max_clicks, active_from, active_until)
+             VALUES (?1, ?2, ?3, ?4, ?5, COALESCE(?6, CURRENT_TIMESTAMP), ?7, ?8, ?9, ?10, ?11,
+                     ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
+            params![
+                user_id,
+                record.original_url,
+                short_code,
+                record.name,
+                record.clicks,
+                record.created_at,
+                record.public_stats,
+                record.platform_urls.ios_url,
+                record.platform_urls.android_url,
+                record.platform_urls.desktop_url,
+                record.sticky_variants,
+                record.redirect_status,
+                record.passthrough.map(Passthrough::as_str),
+                record.utm.utm_source,
+                record.utm.utm_medium,
+                record.utm.utm_campaign,
+                record.utm.utm_term,
+                record.utm.utm_content,
+                record.expires_at,
+                record.max_clicks,
+                record.active_from,
+                record.active_until
+            ],
+        )?;
+        let url_id = tx.last_insert_rowid();
+        for rule in &record.geo_rules {
+            tx.execute(
+                "INSERT INTO geo_rules (url_id, country, destination) VALUES (?1, ?2, ?3)",
+                params![url_id, rule.country, rule.url],
+            )?;
+        }
+        for tag in &record.tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (user_id, name) VALUES (?1, ?2)",
+                params![user_id, tag],
+            )?;
+            tx.execute(
+                "INSERT INTO url_tags (url_id, tag_id)
+                 SELECT ?1, id FROM tags WHERE user_id = ?2 AND name = ?3",
+                params![url_id, user_id, tag],
+            )?;
+        }
+        for variant in &record.variants {
+            tx.execute(
+                "INSERT INTO url_variants (url_id, destination, weight, clicks)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![url_id, variant.url, variant.weight, variant.clicks],
+            )?;
+        }
+        report.imported += 1;
+    }
+
+    report.utm_presets = import_utm_presets(&tx, user_id, presets)?;
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Store UTM presets from [`parse_utm_presets`] for `user_id`, returning how
+/// many were added. A preset the user already has under the same name is
+/// left as it is.
+fn import_utm_presets(
+    tx: &Connection,
+    user_id: i64,
+    presets: &[(String, UtmParams)],
+) -> rusqlite::Result<usize> {
+    let mut added = 0;
+    for (name, utm) in presets {
+        added += tx.execute(
+            "INSERT INTO utm_presets
+                 (user_id, name, utm_source, utm_medium, utm_campaign, utm_term, utm_content)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (user_id, name) DO NOTHING",
+            params![
+                user_id,
+                name,
+                utm.utm_source,
+                utm.utm_medium,
+                utm.utm_campaign,
+                utm.utm_term,
+                utm.utm_content
+            ],
+        )?;
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_ok(data: &str, format: ImportFormat) -> Vec<ImportRecord> {
+        parse_import(data.as_bytes(), format)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn parses_bitly_csv() {
+        let data = "Title,Bitlink,Long URL,Created,Clicks\n\
+                    Launch,https://bit.ly/3abcDEF,https://example.com/launch,2023-04-01T10:00:00Z,\"1,204\"\n";
+        let records = parse_ok(data, ImportFormat::Csv);
+        assert_eq!(
+            records,
+            vec![ImportRecord {
+                original_url: "https://example.com/launch".to_string(),
+                short_code: Some("3abcDEF".to_string()),
+                name: Some("Launch".to_string()),
+                created_at: Some("2023-04-01 10:00:00".to_string()),
+                clicks: 1204,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_yourls_json() {
+        let data = r#"{"result":"success","links":{"link_1":{
+            "shorturl":"https://sho.rt/promo","url":"https://example.com/promo",
+            "title":"Promo","timestamp":"2022-01-05 08:30:00","ip":"127.0.0.1","clicks":"17"}}}"#;
+        let records = parse_ok(data, ImportFormat::Json);
+        assert_eq!(records[0].short_code.as_deref(), Some("promo"));
+        assert_eq!(
+            records[0].created_at.as_deref(),
+            Some("2022-01-05 08:30:00")
+        );
+        assert_eq!(records[0].clicks, 17);
+    }
+
+    #[test]
+    fn parses_shlink_json() {
+        let data = r#"{"shortUrls":{"data":[{"shortCode":"docs","shortUrl":"https://s.test/docs",
+            "longUrl":"https://example.com/docs","dateCreated":"2021-07-01T12:00:00+02:00",
+            "visitsSummary":{"total":42,"nonBots":40},"title":null,"tags":["Docs","launch"],
+            "meta":{"validSince":null,"validUntil":"2030-01-01T00:00:00+00:00","maxVisits":100},
+            "deviceLongUrls":{"android":"https://play.google.com/docs","ios":null}}]}}"#;
+        let records = parse_ok(data, ImportFormat::Json);
+        assert_eq!(records[0].short_code.as_deref(), Some("docs"));
+        assert_eq!(records[0].name, None);
+        assert_eq!(
+            records[0].created_at.as_deref(),
+            Some("2021-07-01 10:00:00")
+        );
+        assert_eq!(records[0].clicks, 42);
+        assert_eq!(records[0].tags, ["docs", "launch"]);
+        assert_eq!(
+            records[0].expires_at.as_deref(),
+            Some("2030-01-01 00:00:00")
+        );
+        assert_eq!(records[0].max_clicks, Some(100));
+        assert_eq!(
+            records[0].platform_urls,
+            PlatformUrls {
+                android_url: Some("https://play.google.com/docs".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn bad_rows_are_reported_individually() {
+        let data = "url,code,clicks\nhttps://a.com,aaa1,-3\n,bbb2,1\nhttps://c.com,ccc3,2\n";
+        let rows = parse_import(data.as_bytes(), ImportFormat::Csv).unwrap();
+        assert!(rows[0].is_err());
+        assert!(rows[1].is_err());
+        assert!(rows[2].is_ok());
+    }
+
+    #[test]
+    fn oversized_counts_are_rejected_rows() {
+        let data = "url,code,clicks,max_clicks
+                    https://a.com,aaa1,18446744073709551615,
+                    https://b.com,bbb2,1,9223372036854775808
+                    https://c.com,ccc3,9223372036854775807,9223372036854775807
+";
+        let rows = parse_import(data.as_bytes(), ImportFormat::Csv).unwrap();
+        assert!(rows[0].as_ref().unwrap_err().contains("too large"));
+        assert!(rows[1].as_ref().unwrap_err().contains("too large"));
+
+        let (state, uid) = test_state();
+        let mut db = state.db.lock().unwrap();
+        let report = import_links(&mut db, uid, rows, &[], OnConflict::Rename, 2048).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(
+            report.errors.iter().map(|e| e.row).collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn oversized_variant_counts_are_rejected_rows() {
+        let data = r#"[
+            {"url": "https://a.com", "variants": [
+                {"url": "https://a.com/b", "weight": 1, "clicks": 18446744073709551615},
+                {"url": "https://a.com/c", "weight": 1}
+            ]},
+            {"url": "https://c.com", "variants": [
+                {"url": "https://c.com/d", "weight": 1, "clicks": 9223372036854775807},
+                {"url": "https://c.com/e", "weight": 1}
+            ]}
+        ]"#;
+        let rows = parse_import(data.as_bytes(), ImportFormat::Json).unwrap();
+        assert!(rows[0].as_ref().unwrap_err().contains("too large"));
+
+        let (state, uid) = test_state();
+        let mut db = state.db.lock().unwrap();
+        let report = import_links(&mut db, uid, rows, &[], OnConflict::Rename, 2048).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].row, 1);
+    }
+
+    #[test]
+    fn link_limits_are_read_and_protected_links_refused() {
+        let data = "url,tags,expires_at,max_clicks,active_from,active_until,password_protected
+                    https://a.com,clients/acme;Launch,2020-01-01 00:00:00,3,,,false
+                    https://b.com,,,0,,,false
+                    https://c.com,,,,2031-01-01,2030-01-01,false
+                    https://d.com,,,,,,true
+";
+        let rows = parse_import(data.as_bytes(), ImportFormat::Csv).unwrap();
+        let record = rows[0].as_ref().unwrap();
+        assert_eq!(record.tags, ["clients/acme", "launch"]);
+        // Already expired, and imported that way
+        assert_eq!(record.expires_at.as_deref(), Some("2020-01-01 00:00:00"));
+        assert_eq!(record.max_clicks, Some(3));
+        assert_eq!(
+            rows[1].as_ref().unwrap_err(),
+            "max_clicks must be at least 1"
+        );
+        assert!(rows[2].is_err());
+        assert!(rows[3].as_ref().unwrap_err().contains("Password-protected"));
+    }
+
+    #[test]
+    fn unreadable_exports_are_rejected() {
+        assert!(parse_import(b"code,clicks\nabc,1\n", ImportFormat::Csv).is_err());
+        assert!(parse_import(b"{\"url\": 1", ImportFormat::Json).is_err());
+        assert!(parse_import(b"[]", ImportFormat::Json).is_err());
+    }
+
+    #[test]
+    fn detects_format_from_content() {
+        assert_eq!(ImportFormat::detect(b"  [{}]"), ImportFormat::Json);
+        assert_eq!(ImportFormat::detect(b"url,code\n"), ImportFormat::Csv);
+        assert_eq!(ImportFormat::detect(b"PK\x03\x04\x14\0"), ImportFormat::Zip);
+    }
+
+    fn test_state() -> (actix_web::web::Data<crate::db::AppState>, i64) {
+        let state = crate::testing::make_test_state();
+        let user_id = {
+            let db = state.db.lock().unwrap();
+            db.execute(
+                "INSERT INTO users (username, password) VALUES ('alice', 'x')",
+                [],
+            )
+            .unwrap();
+            db.last_insert_rowid()
+        };
+        (state, user_id)
+    }
+
+    fn record(url: &str, code: &str, clicks: u64) -> Result<ImportRecord, String> {
+        Ok(ImportRecord {
+            original_url: url.to_string(),
+            short_code: Some(code.to_string()),
+            clicks,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn import_preserves_codes_and_click_totals() {
+        let (state, uid) = test_state();
+        let mut db = state.db.lock().unwrap();
+        let report = import_links(
+            &mut db,
+            uid,
+            vec![record("https://example.com", "kept1", 12)],
+            &[],
+            OnConflict::Rename,
+            2048,
+        )
+        .unwrap();
+        assert_eq!(report.imported, 1);
+        assert!(report.conflicts.is_empty());
+
+        let clicks: i64 = db
+            .query_row(
+                "SELECT clicks FROM urls WHERE short_code = 'kept1'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(clicks, 12);
+    }
+
+    #[test]
+    fn links_and_presets_are_imported_together() {
+        let (state, uid) = test_state();
+        let mut db = state.db.lock().unwrap();
+        let presets = [(
+            "Newsletter".to_string(),
+            UtmParams {
+                utm_source: Some("newsletter".to_string()),
+                ..Default::default()
+            },
+        )];
+        let records = || vec![record("https://example.com", "both01", 0)];
+
+        let report =
+            import_links(&mut db, uid, records(), &presets, OnConflict::Skip, 2048).unwrap();
+        assert_eq!((report.imported, report.utm_presets), (1, 1));
+
+        // A failure storing the presets leaves no links behind either
+        db.execute("DELETE FROM urls", []).unwrap();
+        db.execute("DROP TABLE utm_presets", []).unwrap();
+        assert!(import_links(&mut db, uid, records(), &presets, OnConflict::Skip, 2048).is_err());
+        let links: i64 = db
+            .query_row("SELECT COUNT(*) FROM urls", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(links, 0);
+    }
+
+    #[test]
+    fn import_renames_or_skips_conflicting_codes() {
+        let (state, uid) = test_state();
+        let mut db = state.db.lock().unwrap();
+        let records = || {
+            vec![
+                record("https://one.com", "dupe1", 0),
+                record("https://two.com", "dupe1", 0),
+                record("https://three.com", "api", 0),
+                record("not a url", "bad01", 0),
+            ]
+        };
+
+        let report = import_links(&mut db, uid, records(), &[], OnConflict::Rename, 2048).unwrap();
+        assert_eq!(report.imported, 3);
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(report.conflicts[0].row, 2);
+        assert!(report.conflicts[0].assigned_code.is_some());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].row, 4);
+
+        let report = import_links(&mut db, uid, records(), &[], OnConflict::Skip, 2048).unwrap();
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.skipped, 3);
+        assert!(report.conflicts.iter().all(|c| c.assigned_code.is_none()));
+    }
+}
@@ -4,11 +4,14 @@
 //! (in `tests/`) can build test applications without duplicating module
 //! declarations.
 
+pub mod analytics;
 #[cfg(feature = "standalone")]
 pub mod auth;
 pub mod config;
 pub mod db;
+pub mod export;
 pub mod handlers;
+pub mod import;
 pub mod models;
 #[cfg(feature = "saas")]
 pub mod oidc;
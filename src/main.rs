@@ -1,15 +1,20 @@
 use actix_governor::{Governor, GovernorConfigBuilder};
-use actix_web::{middleware, web, App, HttpServer};
+use actix_web::http::Method;
+use actix_web::{guard, middleware, web, App, HttpServer};
 #[cfg(feature = "standalone")]
 use actix_web_httpauth::middleware::HttpAuthentication;
 use tracing::info;
 use tracing_subscriber::{fmt, EnvFilter};
 
+mod analytics;
 #[cfg(feature = "standalone")]
 mod auth;
+mod cli;
 mod config;
 mod db;
+mod export;
 mod handlers;
+mod import;
 mod models;
 #[cfg(feature = "saas")]
 mod oidc;
@@ -25,6 +30,7 @@ use auth::middleware::{admin_validator, jwt_validator};
 use config::Config;
 use db::AppState;
 use handlers::*;
+use import::IMPORT_MAX_BYTES;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -44,6 +50,12 @@ async fn main() -> std::io::Result<()> {
     // Load configuration from environment
     let config = Config::from_env();
 
+    // `rus import ...` runs the importer instead of starting the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("import") {
+        std::process::exit(cli::run_import(config, &args[1..]));
+    }
+
     // Print startup banner with configuration
     config.print_banner();
 
@@ -91,6 +103,13 @@ async fn main() -> std::io::Result<()> {
             .finish()
             .unwrap();
 
+        // Rate limiter: password attempts on protected links (10 per minute)
+        let link_password_rate_limit = GovernorConfigBuilder::default()
+            .seconds_per_request(6)
+            .burst_size(10)
+            .finish()
+            .unwrap();
+
         let app = App::new()
             .app_data(app_state.clone())
             .wrap(tracing_actix_web::TracingLogger::default())
@@ -120,6 +139,7 @@ async fn main() -> std::io::Result<()> {
             .route("/api/refresh", web::post().to(refresh_token))
             .route("/api/config", web::get().to(get_config))
             .route("/api/version", web::get().to(get_version))
+            .route("/api/public/stats/{code}", web::get().to(get_public_stats))
             .route("/api/setup/required", web::get().to(check_setup_required))
             .service(
                 web::resource("/api/report-abuse")
@@ -146,11 +166,70 @@ async fn main() -> std::io::Result<()> {
                     .wrap(auth)
                     .route("/me", web::get().to(get_current_user))
                     .route("/shorten", web::post().to(shorten_url))
+                    .route("/shorten/bulk", web::post().to(bulk_shorten_urls))
+                    .route("/export", web::get().to(export_urls))
+                    .service(
+                        web::resource("/import")
+                            .app_data(web::PayloadConfig::new(IMPORT_MAX_BYTES))
+                            .route(web::post().to(import_urls)),
+                    )
                     .route("/stats/{code}", web::get().to(get_stats))
                     .route("/urls", web::get().to(get_user_urls))
                     .route("/urls/{code}", web::delete().to(delete_url))
                     .route("/urls/{code}/name", web::patch().to(update_url_name))
+                    .route("/urls/{code}/expiry", web::patch().to(update_url_expiry))
+                    .route(
+                        "/urls/{code}/schedule",
+                        web::patch().to(update_url_schedule),
+                    )
+                    .route(
+                        "/urls/{code}/platform-urls",
+                        web::patch().to(update_url_platform_urls),
+                    )
+                    .route(
+                        "/urls/{code}/redirect-status",
+                        web::patch().to(update_url_redirect_status),
+                    )
+                    .route(
+                        "/urls/{code}/passthrough",
+                        web::patch().to(update_url_passthrough),
+                    )
+                    .route("/urls/{code}/geo-rules", web::get().to(get_geo_rules))
+                    .route("/urls/{code}/geo-rules", web::put().to(replace_geo_rules))
+                    .route("/urls/{code}/variants", web::get().to(get_url_variants))
+                    .route("/urls/{code}/variants", web::put().to(replace_url_variants))
+                    .route("/urls/{code}/tags", web::post().to(add_url_tags))
+                    .route(
+                        "/urls/{code}/tags/{tag:.*}",
+                        web::delete().to(remove_url_tag),
+                    )
+                    .route("/tags", web::get().to(list_tags))
+                    .route("/utm-presets", web::get().to(list_utm_presets))
+                    .route("/utm-presets", web::post().to(save_utm_preset))
+                    .route("/utm-presets/{id}", web::delete().to(delete_utm_preset))
+                    .route(
+                        "/urls/{code}/destination",
+                        web::patch().to(update_url_destination),
+                    )
+                    .route("/urls/{code}/revisions", web::get().to(get_url_revisions))
+                    .route(
+                        "/urls/{code}/password",
+                        web::patch().to(update_url_password),
+                    )
+                    .route(
+                        "/urls/{code}/public-stats",
+                        web::patch().to(update_url_public_stats),
+                    )
                     .route("/urls/{code}/clicks", web::get().to(get_click_history))
+                    .route(
+                        "/urls/{code}/clicks/timeseries",
+                        web::get().to(get_click_timeseries),
+                    )
+                    .route(
+                        "/clicks/timeseries",
+                        web::get().to(get_account_click_timeseries),
+                    )
+                    .route("/clicks/stream", web::get().to(click_stream))
                     .route("/urls/{code}/qr/{format}", web::get().to(get_qr_code)),
             )
             // Public page routes
@@ -165,8 +244,29 @@ async fn main() -> std::io::Result<()> {
             .route("/k9f3x2m7.js", web::get().to(serve_auth_js))
             .route("/theme.js", web::get().to(serve_theme_js))
             .route("/health", web::get().to(health_check))
+            // Public stats page (before the catch-all, which would also match "abc123+")
+            .route("/{code}+", web::get().to(public_stats_page))
             // Catch-all route for short code redirects (MUST BE LAST)
-            .route("/{code}", web::get().to(redirect_url));
+            .service(
+                web::resource(["/{code}", "/{code}/{tail:.*}"])
+                    .route(web::get().to(redirect_url))
+                    // Previews of the redirect; they are not counted as clicks
+                    .route(web::head().to(redirect_url))
+                    .route(web::method(Method::OPTIONS).to(redirect_url))
+                    // Only password forms are rate limited; other links redirect
+                    // POST, PUT, PATCH and DELETE with their own status
+                    .route(
+                        web::post()
+                            .guard(guard::fn_guard(password_protected_link))
+                            .to(unlock_url)
+                            .wrap(Governor::new(&link_password_rate_limit)),
+                    )
+                    .route(
+                        web::route()
+                            .guard(guard::fn_guard(forwarded_method))
+                            .to(redirect_url),
+                    ),
+            );
 
         #[cfg(feature = "saas")]
         let app = {
@@ -180,6 +280,7 @@ async fn main() -> std::io::Result<()> {
                 // Public API
                 .route("/api/config", web::get().to(get_config))
                 .route("/api/version", web::get().to(get_version))
+                .route("/api/public/stats/{code}", web::get().to(get_public_stats))
                 .service(
                     web::resource("/api/report-abuse")
                         .wrap(Governor::new(&moderate_rate_limit))
@@ -191,11 +292,70 @@ async fn main() -> std::io::Result<()> {
                         .wrap(actix_web::middleware::from_fn(oidc::require_session))
                         .route("/me", web::get().to(saas_me))
                         .route("/shorten", web::post().to(shorten_url))
+                        .route("/shorten/bulk", web::post().to(bulk_shorten_urls))
+                        .route("/export", web::get().to(export_urls))
+                        .service(
+                            web::resource("/import")
+                                .app_data(web::PayloadConfig::new(IMPORT_MAX_BYTES))
+                                .route(web::post().to(import_urls)),
+                        )
                         .route("/stats/{code}", web::get().to(get_stats))
                         .route("/urls", web::get().to(get_user_urls))
                         .route("/urls/{code}", web::delete().to(delete_url))
                         .route("/urls/{code}/name", web::patch().to(update_url_name))
+                        .route("/urls/{code}/expiry", web::patch().to(update_url_expiry))
+                        .route(
+                            "/urls/{code}/schedule",
+                            web::patch().to(update_url_schedule),
+                        )
+                        .route(
+                            "/urls/{code}/platform-urls",
+                            web::patch().to(update_url_platform_urls),
+                        )
+                        .route(
+                            "/urls/{code}/redirect-status",
+                            web::patch().to(update_url_redirect_status),
+                        )
+                        .route(
+                            "/urls/{code}/passthrough",
+                            web::patch().to(update_url_passthrough),
+                        )
+                        .route("/urls/{code}/geo-rules", web::get().to(get_geo_rules))
+                        .route("/urls/{code}/geo-rules", web::put().to(replace_geo_rules))
+                        .route("/urls/{code}/variants", web::get().to(get_url_variants))
+                        .route("/urls/{code}/variants", web::put().to(replace_url_variants))
+                        .route("/urls/{code}/tags", web::post().to(add_url_tags))
+                        .route(
+                            "/urls/{code}/tags/{tag:.*}",
+                            web::delete().to(remove_url_tag),
+                        )
+                        .route("/tags", web::get().to(list_tags))
+                        .route("/utm-presets", web::get().to(list_utm_presets))
+                        .route("/utm-presets", web::post().to(save_utm_preset))
+                        .route("/utm-presets/{id}", web::delete().to(delete_utm_preset))
+                        .route(
+                            "/urls/{code}/destination",
+                            web::patch().to(update_url_destination),
+                        )
+                        .route("/urls/{code}/revisions", web::get().to(get_url_revisions))
+                        .route(
+                            "/urls/{code}/password",
+                            web::patch().to(update_url_password),
+                        )
+                        .route(
+                            "/urls/{code}/public-stats",
+                            web::patch().to(update_url_public_stats),
+                        )
                         .route("/urls/{code}/clicks", web::get().to(get_click_history))
+                        .route(
+                            "/urls/{code}/clicks/timeseries",
+                            web::get().to(get_click_timeseries),
+                        )
+                        .route(
+                            "/clicks/timeseries",
+                            web::get().to(get_account_click_timeseries),
+                        )
+                        .route("/clicks/stream", web::get().to(click_stream))
                         .route("/urls/{code}/qr/{format}", web::get().to(get_qr_code)),
                 )
                 // OIDC RP routes
@@ -231,8 +391,29 @@ async fn main() -> std::io::Result<()> {
                 .route("/k9f3x2m7.js", web::get().to(serve_auth_js))
                 .route("/theme.js", web::get().to(serve_theme_js))
                 .route("/health", web::get().to(health_check))
+                // Public stats page (before the catch-all, which would also match "abc123+")
+                .route("/{code}+", web::get().to(public_stats_page))
                 // Catch-all route for short code redirects (MUST BE LAST)
-                .route("/{code}", web::get().to(redirect_url))
+                .service(
+                    web::resource(["/{code}", "/{code}/{tail:.*}"])
+                        .route(web::get().to(redirect_url))
+                        // Previews of the redirect; they are not counted as clicks
+                        .route(web::head().to(redirect_url))
+                        .route(web::method(Method::OPTIONS).to(redirect_url))
+                        // Only password forms are rate limited; other links redirect
+                        // POST, PUT, PATCH and DELETE with their own status
+                        .route(
+                            web::post()
+                                .guard(guard::fn_guard(password_protected_link))
+                                .to(unlock_url)
+                                .wrap(Governor::new(&link_password_rate_limit)),
+                        )
+                        .route(
+                            web::route()
+                                .guard(guard::fn_guard(forwarded_method))
+                                .to(redirect_url),
+                        ),
+                )
                 // Maintenance guard: outermost middleware
                 .wrap(actix_web::middleware::from_fn(maintenance_guard))
         };
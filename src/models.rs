@@ -1,9 +1,44 @@
 use serde::{Deserialize, Serialize};
 
+use crate::url::UtmParams;
+
 /// Request to shorten a URL
 #[derive(Serialize, Deserialize)]
 pub struct ShortenRequest {
     pub url: String,
+    /// Optional vanity short code (e.g. `q3-report`) used instead of a random one
+    pub alias: Option<String>,
+    /// Optional display name for the link
+    pub name: Option<String>,
+    /// Optional RFC 3339 timestamp after which the link stops redirecting
+    pub expires_at: Option<String>,
+    /// Optional number of redirects after which the link stops working
+    pub max_clicks: Option<u64>,
+    /// Optional password visitors must enter before being redirected
+    pub password: Option<String>,
+    /// Optional RFC 3339 timestamp before which the link does not redirect yet
+    pub active_from: Option<String>,
+    /// Optional RFC 3339 timestamp after which the link stops redirecting
+    pub active_until: Option<String>,
+    /// Optional destination for iPhone and iPad visitors
+    pub ios_url: Option<String>,
+    /// Optional destination for Android visitors
+    pub android_url: Option<String>,
+    /// Optional destination for desktop visitors
+    pub desktop_url: Option<String>,
+    /// Optional redirect status (301, 302, 307 or 308) instead of the
+    /// instance default
+    pub redirect_status: Option<u16>,
+    /// Optional passthrough of the path and query after the short code:
+    /// `keep` (the destination's parameters win) or `replace` (the
+    /// visitor's do)
+    pub passthrough: Option<String>,
+    /// Optional campaign parameters added to the URL's query string
+    #[serde(flatten)]
+    pub utm: UtmParams,
+    /// Optional name of a saved UTM preset supplying any `utm_*` field not
+    /// given here
+    pub utm_preset: Option<String>,
 }
 
 /// Response after shortening a URL
@@ -12,6 +47,113 @@ pub struct ShortenResponse {
     pub short_code: String,
     pub short_url: String,
     pub original_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub password_protected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_until: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ios_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub android_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desktop_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passthrough: Option<String>,
+    #[serde(flatten)]
+    pub utm: UtmParams,
+}
+
+/// Request to shorten many URLs at once
+#[derive(Serialize, Deserialize)]
+pub struct BulkShortenRequest {
+    pub items: Vec<ShortenRequest>,
+}
+
+/// Outcome of one item in a bulk shorten request
+#[derive(Serialize)]
+pub struct BulkShortenResult {
+    /// Position of the item in the request
+    pub index: usize,
+    /// HTTP status the item would have received from `/api/shorten`
+    pub status: u16,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub link: Option<ShortenResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Query parameters accepted by the click time-series endpoints
+#[derive(Serialize, Deserialize, Default)]
+pub struct TimeseriesQuery {
+    /// `hour`, `day` (default), `week` or `month`
+    pub interval: Option<String>,
+    /// Start of the range, RFC 3339 or `YYYY-MM-DD`; defaults to the first recorded click
+    pub from: Option<String>,
+    /// End of the range, RFC 3339 or `YYYY-MM-DD` (inclusive); defaults to now
+    pub to: Option<String>,
+    /// IANA timezone buckets and dates are in; defaults to `UTC`
+    pub tz: Option<String>,
+}
+
+/// Query parameters accepted by `POST /api/import`
+#[derive(Serialize, Deserialize, Default)]
+pub struct ImportQuery {
+    /// `csv`, `json` or `zip`; detected from the Content-Type or the data when omitted
+    pub format: Option<String>,
+    /// `rename` (default) imports a conflicting link under a new code, `skip` leaves it out
+    pub on_conflict: Option<String>,
+}
+
+/// Query parameters accepted by `GET /api/export`
+#[derive(Serialize, Deserialize, Default)]
+pub struct ExportQuery {
+    /// `zip` (default), `csv` or `jsonl`
+    pub format: Option<String>,
+    /// For CSV only: `links` (default) or `clicks`
+    pub data: Option<String>,
+}
+
+/// Outcome of importing links from another shortener
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: usize,
+    /// Links whose original short code could not be kept
+    pub conflicts: Vec<ImportConflict>,
+    /// Rows that could not be imported at all
+    pub errors: Vec<ImportRowError>,
+    /// UTM presets added from a rus export bundle
+    pub utm_presets: usize,
+}
+
+/// A link whose original short code was taken or not allowed here
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportConflict {
+    /// 1-based position of the link in the export
+    pub row: usize,
+    pub short_code: String,
+    pub reason: String,
+    /// Code the link was imported under, unless it was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_code: Option<String>,
+}
+
+/// A row of an export that was rejected
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportRowError {
+    /// 1-based position of the link in the export
+    pub row: usize,
+    pub error: String,
 }
 
 /// URL entry stored in database
@@ -20,7 +162,77 @@ pub struct UrlEntry {
     pub original_url: String,
     pub short_code: String,
     pub name: Option<String>,
+    /// Clicks by people; bots are counted in `bot_clicks` and do not use up
+    /// `max_clicks`
     pub clicks: u64,
+    /// Redirects served to crawlers, link unfurlers, monitors and scanners
+    pub bot_clicks: u64,
+    pub expires_at: Option<String>,
+    pub max_clicks: Option<u64>,
+    /// Redirects left before the click cap is reached (`None` when uncapped)
+    pub remaining_clicks: Option<u64>,
+    /// Whether visitors must enter a password before being redirected
+    pub password_protected: bool,
+    /// Start of the activation window (UTC); the link is pending before it
+    pub active_from: Option<String>,
+    /// End of the activation window (UTC)
+    pub active_until: Option<String>,
+    /// Whether anyone can see the link's stats at `/{code}+`
+    pub public_stats: bool,
+    /// Destinations used instead of `original_url` on iOS, Android and desktop
+    pub ios_url: Option<String>,
+    pub android_url: Option<String>,
+    pub desktop_url: Option<String>,
+    /// Redirect status set on the link; `None` uses the instance default
+    pub redirect_status: Option<u16>,
+    /// `keep` or `replace` when extra path and query are passed on to the
+    /// destination; `None` when they are not
+    pub passthrough: Option<String>,
+    /// Campaign parameters added when the link was created (unset ones are
+    /// left out)
+    #[serde(flatten)]
+    pub utm: UtmParams,
+    /// Tag names, sorted alphabetically
+    pub tags: Vec<String>,
+    pub created_at: String,
+}
+
+/// Query parameters accepted by `GET /api/urls`
+#[derive(Serialize, Deserialize, Default)]
+pub struct UrlListQuery {
+    /// Only return links carrying this tag
+    pub tag: Option<String>,
+    /// Full-text search over destination and name
+    pub q: Option<String>,
+    /// `created` (default), `clicks` or `name`
+    pub sort: Option<String>,
+    /// `asc` or `desc`; defaults to `desc`, or `asc` when sorting by name
+    pub order: Option<String>,
+    /// Only links created at or after this RFC 3339 timestamp or `YYYY-MM-DD` date
+    pub created_after: Option<String>,
+    /// Only links created before this RFC 3339 timestamp or `YYYY-MM-DD` date
+    pub created_before: Option<String>,
+    /// Only links created with these campaign parameters (exact match)
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+    /// Page size (default 50, max 200)
+    pub limit: Option<u32>,
+    /// Opaque token from a previous response's `next_page_token`
+    pub page_token: Option<String>,
+}
+
+/// One page of the current user's URLs
+#[derive(Serialize)]
+pub struct UrlListResponse {
+    pub urls: Vec<UrlEntry>,
+    /// Number of links matching the filters across all pages
+    pub total: u64,
+    /// Pass as `page_token` to fetch the next page; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
 }
 
 /// User registration request - standalone only
@@ -54,6 +266,170 @@ pub struct UpdateUrlNameRequest {
     pub name: Option<String>,
 }
 
+/// Request to set, extend or clear a URL's expiry (`null` clears it). The
+/// field is required, so an empty body cannot clear the expiry by accident.
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlExpiryRequest {
+    /// `None` when the field is missing, `Some(None)` when it is `null`
+    #[serde(
+        default,
+        deserialize_with = "deserialize_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<Option<String>>,
+}
+
+/// Deserialize a nullable field so that `null` (`Some(None)`) can be told
+/// apart from a missing field (`None`, via `#[serde(default)]`)
+fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Request to replace a URL's activation window (`null` leaves that side open)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlScheduleRequest {
+    pub active_from: Option<String>,
+    pub active_until: Option<String>,
+}
+
+/// Request to add one or more tags to a URL
+#[derive(Serialize, Deserialize)]
+pub struct AddTagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// A tag and the number of the user's links carrying it
+#[derive(Serialize, Deserialize)]
+pub struct TagSummary {
+    pub name: String,
+    pub url_count: u64,
+}
+
+/// Request to point an existing short link at a new destination
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlDestinationRequest {
+    pub url: String,
+}
+
+/// A previous destination of a short link
+#[derive(Serialize, Deserialize)]
+pub struct UrlRevision {
+    pub previous_url: String,
+    pub new_url: String,
+    /// Username of whoever made the change (`None` if that account is gone)
+    pub changed_by: Option<String>,
+    pub changed_at: String,
+}
+
+/// Request to set, change or remove a URL's password (`null` removes it)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlPasswordRequest {
+    pub password: Option<String>,
+}
+
+/// Request to turn a URL's public stats page on or off
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlPublicStatsRequest {
+    pub enabled: bool,
+}
+
+/// Request to replace a URL's per-platform destinations (`null` removes one)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlPlatformUrlsRequest {
+    pub ios_url: Option<String>,
+    pub android_url: Option<String>,
+    pub desktop_url: Option<String>,
+}
+
+/// Request to set a URL's redirect status (`null` uses the instance default)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlRedirectStatusRequest {
+    pub redirect_status: Option<u16>,
+}
+
+/// Request to set a URL's passthrough mode (`null` turns it off)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlPassthroughRequest {
+    pub passthrough: Option<String>,
+}
+
+/// Destination for visitors from one country
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GeoRule {
+    /// ISO 3166-1 alpha-2 country code, e.g. `DE`
+    pub country: String,
+    pub url: String,
+}
+
+/// Request to replace all of a URL's geo rules (an empty list removes them)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateGeoRulesRequest {
+    pub rules: Vec<GeoRule>,
+}
+
+/// One destination of an A/B test, as submitted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VariantInput {
+    pub url: String,
+    /// Relative share of visitors sent here; defaults to 1
+    pub weight: Option<u32>,
+}
+
+/// Request to replace all of a URL's variants (an empty list ends the test)
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUrlVariantsRequest {
+    pub variants: Vec<VariantInput>,
+    /// Send returning visitors to the variant they got the first time
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+/// A destination variant with the human clicks it has received
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UrlVariant {
+    pub id: i64,
+    pub url: String,
+    pub weight: u32,
+    pub clicks: u64,
+}
+
+/// A destination variant in an export, which has no use for the row id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedVariant {
+    pub url: String,
+    pub weight: u32,
+    #[serde(default)]
+    pub clicks: u64,
+}
+
+/// A saved set of UTM parameters
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UtmPreset {
+    pub id: i64,
+    pub name: String,
+    #[serde(flatten)]
+    pub utm: UtmParams,
+    pub created_at: String,
+}
+
+/// Request to save a UTM preset, replacing any existing one with the same name
+#[derive(Serialize, Deserialize)]
+pub struct SaveUtmPresetRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub utm: UtmParams,
+}
+
+/// Password submitted from the protected link prompt
+#[derive(Serialize, Deserialize)]
+pub struct UnlockUrlRequest {
+    pub password: String,
+}
+
 /// Token refresh request - standalone only
 #[cfg(feature = "standalone")]
 #[derive(Serialize, Deserialize)]
@@ -70,15 +446,119 @@ pub struct RefreshResponse {
 }
 
 /// Click history entry
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ClickHistoryEntry {
     pub clicked_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer_host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// ISO country code, when a GeoIP database is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// Whether the request came from a bot rather than a person
+    #[serde(default)]
+    pub bot: bool,
+    /// Country of the geo rule that chose the destination, if one did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geo_rule: Option<String>,
+}
+
+/// Clicks for one value of a breakdown dimension
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BreakdownEntry {
+    pub value: String,
+    pub clicks: u64,
+}
+
+/// Recorded clicks grouped by where they came from
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ClickBreakdown {
+    /// Referring host, or `direct` when there was none
+    pub referrers: Vec<BreakdownEntry>,
+    pub browsers: Vec<BreakdownEntry>,
+    pub operating_systems: Vec<BreakdownEntry>,
+    /// `desktop`, `mobile`, `tablet`, `bot` or `other`
+    pub devices: Vec<BreakdownEntry>,
+    /// ISO 3166-1 country code, or `unknown` without a GeoIP match
+    pub countries: Vec<BreakdownEntry>,
+    /// ISO 3166-2 subdivision code such as `US-WA`
+    pub regions: Vec<BreakdownEntry>,
+    /// Country of the geo rule that chose the destination, or `fallback`
+    /// when none matched
+    pub geo_rules: Vec<BreakdownEntry>,
+}
+
+/// Statistics for one URL: its entry plus click breakdowns
+#[derive(Serialize)]
+pub struct UrlStats {
+    #[serde(flatten)]
+    pub entry: UrlEntry,
+    /// Distinct people per UTC day, summed over all days
+    pub unique_visitors: u64,
+    pub breakdown: ClickBreakdown,
+    /// A/B test destinations with their clicks (empty when not split)
+    pub variants: Vec<UrlVariant>,
+}
+
+/// Clicks in one time bucket
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TimeseriesBucket {
+    /// RFC 3339 start of the bucket, in the requested timezone
+    pub start: String,
+    /// Clicks by people
+    pub clicks: u64,
+    /// Requests from crawlers, link unfurlers, monitors and scanners
+    pub bot_clicks: u64,
+    /// Distinct people per UTC day within the bucket, summed
+    pub unique_visitors: u64,
+}
+
+/// Click counts over time, one entry per bucket including empty ones
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClickTimeseries {
+    pub interval: String,
+    pub timezone: String,
+    pub from: String,
+    pub to: String,
+    pub total: u64,
+    pub bot_total: u64,
+    /// Distinct people per UTC day over the whole range, summed
+    pub unique_visitors: u64,
+    pub buckets: Vec<TimeseriesBucket>,
+}
+
+/// Read-only stats of a link whose owner made them public. The destination
+/// is left out so password-protected links stay protected.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PublicStats {
+    pub short_code: String,
+    pub name: Option<String>,
+    pub created_at: String,
+    /// All-time clicks by people
+    pub clicks: u64,
+    pub unique_visitors: u64,
+    /// Clicks per UTC day over the last 30 days, oldest first
+    pub daily: Vec<TimeseriesBucket>,
+    pub top_referrers: Vec<BreakdownEntry>,
 }
 
 /// Click statistics for a URL
 #[derive(Serialize, Deserialize)]
 pub struct ClickStats {
+    /// Clicks by people
     pub total_clicks: u64,
+    pub bot_clicks: u64,
+    /// Distinct people per UTC day, summed over all days
+    pub unique_visitors: u64,
     pub history: Vec<ClickHistoryEntry>,
 }
 
@@ -16,6 +16,19 @@ pub const TEST_WEBHOOK_SECRET: &str = "test-webhook-secret-32-chars-pad!";
 #[cfg(feature = "standalone")]
 pub const TEST_PASSWORD: &str = "TestPass1!";
 
+/// Small GeoIP2-City style database, generated by
+/// `tests/fixtures/make_geoip_fixture.py`.
+pub const GEOIP_FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/geoip-test.mmdb"
+);
+
+/// Socket address of a test client connecting from `ip`
+#[cfg(feature = "standalone")]
+pub fn peer(ip: &str) -> std::net::SocketAddr {
+    std::net::SocketAddr::new(ip.parse().expect("peer: invalid IP"), 4711)
+}
+
 /// Create a Config suitable for testing (in-memory SQLite).
 pub fn test_config() -> Config {
     Config {
@@ -25,6 +38,9 @@ pub fn test_config() -> Config {
         db_path: ":memory:".to_string(),
         host: "127.0.0.1".to_string(),
         port: 4001,
+        geoip_db_path: None,
+        default_redirect_status: 302,
+        trusted_proxies: Default::default(),
         #[cfg(feature = "standalone")]
         jwt_secret: TEST_JWT_SECRET.to_string(),
         #[cfg(feature = "standalone")]
@@ -0,0 +1,116 @@
+//! Country-to-destination rules: visitors from a listed country are sent to
+//! that country's URL, everyone else to the link's own URL.
+
+use super::validate_url;
+use crate::models::GeoRule;
+
+/// Maximum number of geo rules on a single link
+pub const MAX_GEO_RULES_PER_URL: usize = 50;
+
+/// Normalise an ISO 3166-1 alpha-2 country code: trimmed and uppercased, as
+/// stored by the GeoIP lookup
+pub fn normalize_country(country: &str) -> Result<String, String> {
+    let country = country.trim().to_ascii_uppercase();
+    if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!(
+            "'{}' is not a two-letter ISO country code",
+            country
+        ));
+    }
+    Ok(country)
+}
+
+/// Normalise a full set of rules, validating each destination with
+/// [`validate_url`]. A country may only appear once.
+pub fn validate_geo_rules(
+    rules: &[GeoRule],
+    max_url_length: usize,
+) -> Result<Vec<GeoRule>, String> {
+    if rules.len() > MAX_GEO_RULES_PER_URL {
+        return Err(format!(
+            "A link can have at most {} geo rules",
+            MAX_GEO_RULES_PER_URL
+        ));
+    }
+
+    let mut validated: Vec<GeoRule> = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let country = normalize_country(&rule.country)?;
+        if validated.iter().any(|r| r.country == country) {
+            return Err(format!("Country {} has more than one geo rule", country));
+        }
+        let url = rule.url.trim();
+        validate_url(url, max_url_length).map_err(|e| format!("{}: {}", country, e))?;
+        validated.push(GeoRule {
+            country,
+            url: url.to_string(),
+        });
+    }
+    Ok(validated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(country: &str, url: &str) -> GeoRule {
+        GeoRule {
+            country: country.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn normalize_country_uppercases_two_letter_codes() {
+        assert_eq!(normalize_country(" de ").unwrap(), "DE");
+        assert!(normalize_country("DEU").is_err());
+        assert!(normalize_country("D1").is_err());
+        assert!(normalize_country("").is_err());
+    }
+
+    #[test]
+    fn rules_are_normalised_and_validated() {
+        let rules = validate_geo_rules(
+            &[
+                rule("de", " https://example.de "),
+                rule("FR", "https://example.fr"),
+            ],
+            2048,
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            [
+                rule("DE", "https://example.de"),
+                rule("FR", "https://example.fr")
+            ]
+        );
+
+        let err = validate_geo_rules(&[rule("GB", "javascript:alert(1)")], 2048).unwrap_err();
+        assert!(err.starts_with("GB: "), "{err}");
+    }
+
+    #[test]
+    fn duplicate_countries_and_too_many_rules_are_refused() {
+        assert!(validate_geo_rules(
+            &[
+                rule("de", "https://example.de"),
+                rule("DE", "https://example.com/de")
+            ],
+            2048
+        )
+        .is_err());
+
+        let many: Vec<GeoRule> = (0..=MAX_GEO_RULES_PER_URL)
+            .map(|i| {
+                let code: String = [b'A' + (i / 26) as u8, b'A' + (i % 26) as u8]
+                    .iter()
+                    .map(|&b| b as char)
+                    .collect();
+                rule(&code, "https://example.com")
+            })
+            .collect();
+        assert!(validate_geo_rules(&many, 2048).is_err());
+        assert!(validate_geo_rules(&many[1..], 2048).is_ok());
+    }
+}
@@ -1,5 +1,21 @@
+pub mod geo_rule;
+pub mod passthrough;
+pub mod password;
+pub mod platform;
 pub mod qr;
+pub mod redirect_status;
 pub mod shortener;
+pub mod tag;
+pub mod utm;
+pub mod variant;
 
+pub use geo_rule::validate_geo_rules;
+pub use passthrough::Passthrough;
+pub use password::{hash_link_password, validate_link_password, verify_link_password};
+pub use platform::PlatformUrls;
 pub use qr::{generate_qr_code_png, generate_qr_code_svg};
-pub use shortener::{generate_short_code, validate_url};
+pub use redirect_status::{redirect_cache_control, validate_redirect_status};
+pub use shortener::{generate_short_code, validate_alias, validate_url};
+pub use tag::{normalize_tag, MAX_TAGS_PER_URL};
+pub use utm::{validate_utm_preset_name, UtmParams};
+pub use variant::{pick_weighted, validate_variants, VARIANT_COOKIE};
@@ -0,0 +1,203 @@
+//! Passthrough of whatever follows a short code: with it on, visiting
+//! `/{code}/docs/intro?ref=x` appends `/docs/intro` to the destination's path
+//! and `ref=x` to its query string.
+
+use url::Url;
+
+use super::validate_url;
+
+/// How incoming query parameters are merged with the destination's own when
+/// both have one with the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Passthrough {
+    /// The destination's value wins; the incoming one is dropped
+    Keep,
+    /// The incoming value replaces the destination's
+    Replace,
+}
+
+impl Passthrough {
+    /// Parse the stored / API form, `keep` or `replace`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "keep" => Ok(Passthrough::Keep),
+            "replace" => Ok(Passthrough::Replace),
+            other => Err(format!(
+                "passthrough must be 'keep' or 'replace', not '{}'",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Passthrough::Keep => "keep",
+            Passthrough::Replace => "replace",
+        }
+    }
+
+    /// Append `tail` (the path after `/{code}/`) and `query` (the raw
+    /// incoming query string) to `destination`. Parameters are copied as
+    /// they were written, so the destination's query is left byte for byte
+    /// as it was apart from the incoming pairs. The result must still pass
+    /// [`validate_url`]; `.` and `..` segments are refused so the tail
+    /// cannot climb out of the destination's path.
+    pub fn apply(
+        self,
+        destination: &str,
+        tail: &str,
+        query: &str,
+        max_url_length: usize,
+    ) -> Result<String, String> {
+        if tail.is_empty() && query.is_empty() {
+            return Ok(destination.to_string());
+        }
+        let mut url = Url::parse(destination).map_err(|e| format!("Invalid destination: {e}"))?;
+
+        if !tail.is_empty() {
+            if tail.split('/').any(is_dot_segment) {
+                return Err("The extra path cannot contain '.' or '..' segments".to_string());
+            }
+            let path = format!("{}/{}", url.path().trim_end_matches('/'), tail);
+            url.set_path(&path);
+        }
+
+        let incoming = query_params(query);
+        if !incoming.is_empty() {
+            let existing = query_params(url.query().unwrap_or(""));
+            let has_name =
+                |params: &[&str], name: &str| params.iter().any(|param| param_name(param) == name);
+            let merged: Vec<&str> = match self {
+                Passthrough::Keep => existing
+                    .iter()
+                    .copied()
+                    .chain(
+                        incoming
+                            .iter()
+                            .copied()
+                            .filter(|param| !has_name(&existing, &param_name(param))),
+                    )
+                    .collect(),
+                Passthrough::Replace => existing
+                    .iter()
+                    .copied()
+                    .filter(|param| !has_name(&incoming, &param_name(param)))
+                    .chain(incoming.iter().copied())
+                    .collect(),
+            };
+            url.set_query(Some(&merged.join("&")));
+        }
+
+        let url = String::from(url);
+        validate_url(&url, max_url_length)?;
+        Ok(url)
+    }
+}
+
+/// The `name=value` parameters of a raw query string, exactly as written
+pub(super) fn query_params(query: &str) -> Vec<&str> {
+    query.split('&').filter(|param| !param.is_empty()).collect()
+}
+
+/// Decoded name of a raw query parameter (`flag` for a bare `flag`)
+pub(super) fn param_name(param: &str) -> String {
+    url::form_urlencoded::parse(param.as_bytes())
+        .next()
+        .map(|(name, _)| name.into_owned())
+        .unwrap_or_default()
+}
+
+/// Whether a path segment means "this" or "parent" directory, including the
+/// percent-encoded forms URL parsers also resolve
+fn is_dot_segment(segment: &str) -> bool {
+    matches!(
+        segment.to_ascii_lowercase().as_str(),
+        "." | ".." | "%2e" | "%2e%2e" | ".%2e" | "%2e."
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trips() {
+        for mode in [Passthrough::Keep, Passthrough::Replace] {
+            assert_eq!(Passthrough::parse(mode.as_str()), Ok(mode));
+        }
+        assert!(Passthrough::parse("merge").is_err());
+    }
+
+    #[test]
+    fn appends_the_tail_and_merges_the_query() {
+        let destination = "https://example.com/docs/?utm_source=rus&lang=en";
+        assert_eq!(
+            Passthrough::Keep
+                .apply(destination, "guide/intro", "utm_source=x&page=2", 2048)
+                .unwrap(),
+            "https://example.com/docs/guide/intro?utm_source=rus&lang=en&page=2"
+        );
+        assert_eq!(
+            Passthrough::Replace
+                .apply(destination, "", "utm_source=x&page=2", 2048)
+                .unwrap(),
+            "https://example.com/docs/?lang=en&utm_source=x&page=2"
+        );
+        assert_eq!(
+            Passthrough::Keep
+                .apply("https://example.com", "a b", "", 2048)
+                .unwrap(),
+            "https://example.com/a%20b"
+        );
+    }
+
+    #[test]
+    fn the_destination_query_is_kept_as_written() {
+        let destination = "https://example.com/search?flag&q=a%20b&x=1";
+        assert_eq!(
+            Passthrough::Keep
+                .apply(destination, "", "x=2&y=c%20d", 2048)
+                .unwrap(),
+            "https://example.com/search?flag&q=a%20b&x=1&y=c%20d"
+        );
+        assert_eq!(
+            Passthrough::Replace
+                .apply(destination, "", "x=2&flag=on", 2048)
+                .unwrap(),
+            "https://example.com/search?q=a%20b&x=2&flag=on"
+        );
+        assert_eq!(
+            Passthrough::Replace
+                .apply(destination, "more", "x=%31", 2048)
+                .unwrap(),
+            "https://example.com/search/more?flag&q=a%20b&x=%31"
+        );
+    }
+
+    #[test]
+    fn nothing_to_pass_through_leaves_the_destination_untouched() {
+        let destination = "https://example.com?q=a+b";
+        assert_eq!(
+            Passthrough::Replace
+                .apply(destination, "", "", 2048)
+                .unwrap(),
+            destination
+        );
+    }
+
+    #[test]
+    fn dot_segments_and_overlong_results_are_refused() {
+        for tail in ["..", "a/../../admin", "%2E%2e/admin", "./x"] {
+            assert!(
+                Passthrough::Keep
+                    .apply("https://example.com/docs", tail, "", 2048)
+                    .is_err(),
+                "{tail}"
+            );
+        }
+        let long_query = format!("q={}", "a".repeat(100));
+        assert!(Passthrough::Keep
+            .apply("https://example.com", "", &long_query, 60)
+            .is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// Minimum length of a link password
+pub const LINK_PASSWORD_MIN_LENGTH: usize = 4;
+
+/// Maximum length of a link password
+pub const LINK_PASSWORD_MAX_LENGTH: usize = 128;
+
+/// Validate a passphrase chosen for a protected link
+pub fn validate_link_password(password: &str) -> Result<(), String> {
+    let len = password.chars().count();
+    if !(LINK_PASSWORD_MIN_LENGTH..=LINK_PASSWORD_MAX_LENGTH).contains(&len) {
+        return Err(format!(
+            "Link password must be between {} and {} characters",
+            LINK_PASSWORD_MIN_LENGTH, LINK_PASSWORD_MAX_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Hash a link password using Argon2id
+pub fn hash_link_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Verify a submitted link password against its stored Argon2id hash
+pub fn verify_link_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_then_verify_round_trips() {
+        let hash = hash_link_password("open sesame").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_link_password("open sesame", &hash));
+    }
+
+    #[test]
+    fn verify_rejects_wrong_password() {
+        let hash = hash_link_password("open sesame").unwrap();
+        assert!(!verify_link_password("open sesame!", &hash));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_hash() {
+        assert!(!verify_link_password("anything", "not-a-hash"));
+    }
+
+    #[test]
+    fn validate_enforces_length_bounds() {
+        assert!(validate_link_password("abc").is_err());
+        assert!(validate_link_password("abcd").is_ok());
+        assert!(validate_link_password(&"x".repeat(LINK_PASSWORD_MAX_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-platform destinations: one short link that sends iPhone users to the
+//! App Store, Android users to Google Play and everyone else to the website.
+
+use serde::{Deserialize, Serialize};
+
+use super::validate_url;
+use crate::analytics::client::Client;
+
+/// Optional destinations that replace a link's URL for some visitors
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformUrls {
+    /// Used for iPhone and iPad visitors
+    pub ios_url: Option<String>,
+    /// Used for Android phone and tablet visitors
+    pub android_url: Option<String>,
+    /// Used for desktop visitors on any other operating system
+    pub desktop_url: Option<String>,
+}
+
+impl PlatformUrls {
+    /// Validate each override with [`validate_url`]. Empty values are treated
+    /// as unset.
+    pub fn parse(
+        ios_url: Option<&str>,
+        android_url: Option<&str>,
+        desktop_url: Option<&str>,
+        max_length: usize,
+    ) -> Result<Self, String> {
+        let parse_one = |field: &str, value: Option<&str>| {
+            let Some(url) = value.map(str::trim).filter(|u| !u.is_empty()) else {
+                return Ok(None);
+            };
+            validate_url(url, max_length)
+                .map(|()| Some(url.to_string()))
+                .map_err(|e| format!("{field}: {e}"))
+        };
+        Ok(Self {
+            ios_url: parse_one("ios_url", ios_url)?,
+            android_url: parse_one("android_url", android_url)?,
+            desktop_url: parse_one("desktop_url", desktop_url)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ios_url.is_none() && self.android_url.is_none() && self.desktop_url.is_none()
+    }
+
+    /// Override for a visitor using `client`, if one is set for its platform
+    pub fn for_client(&self, client: &Client) -> Option<&str> {
+        match (client.os.as_deref(), client.device) {
+            (Some("iOS"), _) => self.ios_url.as_deref(),
+            (Some("Android"), _) => self.android_url.as_deref(),
+            (_, "desktop") => self.desktop_url.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) \
+        AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1";
+    const IPAD: &str = "Mozilla/5.0 (iPad; CPU OS 17_1 like Mac OS X) AppleWebKit/605.1.15 \
+        (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1";
+    const ANDROID: &str = "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 \
+        (KHTML, like Gecko) Chrome/119.0.0.0 Mobile Safari/537.36";
+    const WINDOWS: &str =
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+
+    fn all_set() -> PlatformUrls {
+        PlatformUrls::parse(
+            Some("https://apps.apple.com/app/id1"),
+            Some("https://play.google.com/store/apps/details?id=x"),
+            Some("https://example.com/desktop"),
+            2048,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn picks_the_override_for_each_platform() {
+        let urls = all_set();
+        for (user_agent, expected) in [
+            (IPHONE, "https://apps.apple.com/app/id1"),
+            (IPAD, "https://apps.apple.com/app/id1"),
+            (ANDROID, "https://play.google.com/store/apps/details?id=x"),
+            (WINDOWS, "https://example.com/desktop"),
+        ] {
+            let client = Client::from_user_agent(user_agent);
+            assert_eq!(urls.for_client(&client), Some(expected), "{user_agent}");
+        }
+    }
+
+    #[test]
+    fn unset_platforms_and_unknown_clients_get_no_override() {
+        let urls =
+            PlatformUrls::parse(Some("https://apps.apple.com/app/id1"), None, None, 2048).unwrap();
+        assert_eq!(urls.for_client(&Client::from_user_agent(ANDROID)), None);
+        assert_eq!(urls.for_client(&Client::from_user_agent(WINDOWS)), None);
+        assert_eq!(urls.for_client(&Client::from_user_agent("")), None);
+        assert_eq!(
+            all_set().for_client(&Client::from_user_agent("curl/8.4.0")),
+            None
+        );
+    }
+
+    #[test]
+    fn overrides_are_validated_and_blanks_ignored() {
+        let err = PlatformUrls::parse(None, Some("javascript:alert(1)"), None, 2048).unwrap_err();
+        assert!(err.starts_with("android_url: "), "{err}");
+        assert!(PlatformUrls::parse(Some("ftp://example.com/app"), None, None, 2048).is_err());
+
+        let urls = PlatformUrls::parse(Some("  "), Some(""), None, 2048).unwrap();
+        assert!(urls.is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+//! Which HTTP redirect a short link answers with: permanent (301/308) for
+//! links that should pass on search ranking, temporary (302/307) for
+//! everything else, with 307/308 keeping the request method and body.
+
+use actix_web::http::StatusCode;
+
+/// Redirect statuses a link can be set to
+pub const REDIRECT_STATUSES: [u16; 4] = [301, 302, 307, 308];
+
+/// How long browsers and shared caches may keep a permanent redirect. Kept
+/// to a day so a changed destination still reaches returning visitors.
+pub const PERMANENT_REDIRECT_MAX_AGE: u32 = 24 * 60 * 60;
+
+/// Check that `status` is one of [`REDIRECT_STATUSES`]
+pub fn validate_redirect_status(status: u16) -> Result<StatusCode, String> {
+    if !REDIRECT_STATUSES.contains(&status) {
+        return Err(format!(
+            "redirect_status must be one of 301, 302, 307 or 308, not {status}"
+        ));
+    }
+    StatusCode::from_u16(status).map_err(|e| e.to_string())
+}
+
+/// `Cache-Control` for a redirect. Permanent redirects may be cached when
+/// every visitor gets the same destination and the link has no expiry,
+/// schedule or click cap; anything else must reach the server each time so
+/// the click is counted and the right destination chosen.
+pub fn redirect_cache_control(status: StatusCode, cacheable: bool) -> String {
+    let permanent = matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+    );
+    if permanent && cacheable {
+        format!("public, max-age={PERMANENT_REDIRECT_MAX_AGE}")
+    } else {
+        "no-store".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_redirect_statuses_are_accepted() {
+        for status in REDIRECT_STATUSES {
+            assert_eq!(validate_redirect_status(status).unwrap().as_u16(), status);
+        }
+        for status in [200, 303, 304, 404, 999] {
+            assert!(validate_redirect_status(status).is_err(), "{status}");
+        }
+    }
+
+    #[test]
+    fn only_cacheable_permanent_redirects_are_cached() {
+        let cached = format!("public, max-age={PERMANENT_REDIRECT_MAX_AGE}");
+        assert_eq!(
+            redirect_cache_control(StatusCode::MOVED_PERMANENTLY, true),
+            cached
+        );
+        assert_eq!(
+            redirect_cache_control(StatusCode::PERMANENT_REDIRECT, true),
+            cached
+        );
+        assert_eq!(
+            redirect_cache_control(StatusCode::PERMANENT_REDIRECT, false),
+            "no-store"
+        );
+        for status in [
+            StatusCode::FOUND,
+            StatusCode::SEE_OTHER,
+            StatusCode::TEMPORARY_REDIRECT,
+        ] {
+            assert_eq!(redirect_cache_control(status, true), "no-store");
+        }
+    }
+}
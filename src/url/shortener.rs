@@ -15,6 +15,61 @@ pub fn generate_short_code() -> String {
         .collect()
 }
 
+/// Minimum length of a user-chosen alias
+pub const ALIAS_MIN_LENGTH: usize = 3;
+
+/// Maximum length of a user-chosen alias
+pub const ALIAS_MAX_LENGTH: usize = 32;
+
+/// Path segments served by the app itself. An alias may not shadow any of
+/// them (compared case-insensitively). File routes such as `dashboard.html`
+/// are already excluded by the charset since `.` is not allowed.
+const RESERVED_ALIASES: &[&str] = &[
+    "admin",
+    "api",
+    "dashboard",
+    "dev",
+    "health",
+    "index",
+    "login",
+    "oauth2",
+    "report",
+    "setup",
+    "signup",
+    "static",
+    "styles",
+    "theme",
+    "webhooks",
+];
+
+/// Validate a user-chosen alias (vanity short code)
+pub fn validate_alias(alias: &str) -> Result<(), String> {
+    if alias.len() < ALIAS_MIN_LENGTH || alias.len() > ALIAS_MAX_LENGTH {
+        return Err(format!(
+            "Alias must be between {} and {} characters",
+            ALIAS_MIN_LENGTH, ALIAS_MAX_LENGTH
+        ));
+    }
+
+    if !alias
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("Alias may only contain letters, numbers, hyphens and underscores".to_string());
+    }
+
+    if !alias.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("Alias must start with a letter or number".to_string());
+    }
+
+    let lower = alias.to_lowercase();
+    if RESERVED_ALIASES.contains(&lower.as_str()) {
+        return Err(format!("Alias '{}' is reserved", alias));
+    }
+
+    Ok(())
+}
+
 /// Validate URL for shortening
 pub fn validate_url(url_str: &str, max_length: usize) -> Result<(), String> {
     if url_str.len() > max_length {
@@ -77,6 +132,44 @@ mod tests {
         );
     }
 
+    // --- validate_alias ---
+
+    #[test]
+    fn alias_accepts_campaign_style_code() {
+        assert!(validate_alias("q3-report").is_ok());
+        assert!(validate_alias("Launch_2026").is_ok());
+    }
+
+    #[test]
+    fn alias_rejects_too_short() {
+        assert!(validate_alias("ab").is_err());
+    }
+
+    #[test]
+    fn alias_rejects_too_long() {
+        assert!(validate_alias(&"a".repeat(ALIAS_MAX_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn alias_rejects_invalid_characters() {
+        assert!(validate_alias("bad/code").is_err());
+        assert!(validate_alias("dashboard.html").is_err());
+        assert!(validate_alias("spaced out").is_err());
+    }
+
+    #[test]
+    fn alias_rejects_leading_separator() {
+        assert!(validate_alias("-leading").is_err());
+        assert!(validate_alias("_leading").is_err());
+    }
+
+    #[test]
+    fn alias_rejects_reserved_words_case_insensitively() {
+        assert!(validate_alias("api").is_err());
+        assert!(validate_alias("Health").is_err());
+        assert!(validate_alias("DASHBOARD").is_err());
+    }
+
     // --- validate_url ---
 
     #[test]
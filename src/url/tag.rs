@@ -0,0 +1,66 @@
+/// Maximum length of a tag name
+pub const TAG_MAX_LENGTH: usize = 32;
+
+/// Maximum number of tags on a single link
+pub const MAX_TAGS_PER_URL: usize = 20;
+
+/// Normalise a tag name: trimmed and lowercased so `Launch` and `launch` are
+/// the same tag. A `/` may be used to group tags into folders
+/// (e.g. `clients/acme`).
+pub fn normalize_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+
+    if tag.is_empty() || tag.chars().count() > TAG_MAX_LENGTH {
+        return Err(format!(
+            "Tag must be between 1 and {} characters",
+            TAG_MAX_LENGTH
+        ));
+    }
+
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '/' | ' '))
+    {
+        return Err(
+            "Tag may only contain letters, numbers, spaces, hyphens, underscores and slashes"
+                .to_string(),
+        );
+    }
+
+    if tag.starts_with('/') || tag.ends_with('/') || tag.contains("//") {
+        return Err("Tag folders must not be empty".to_string());
+    }
+
+    Ok(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_trims_and_lowercases() {
+        assert_eq!(normalize_tag("  Launch ").unwrap(), "launch");
+        assert_eq!(normalize_tag("Clients/ACME").unwrap(), "clients/acme");
+    }
+
+    #[test]
+    fn normalize_rejects_empty_and_too_long() {
+        assert!(normalize_tag("   ").is_err());
+        assert!(normalize_tag(&"x".repeat(TAG_MAX_LENGTH + 1)).is_err());
+        assert!(normalize_tag(&"x".repeat(TAG_MAX_LENGTH)).is_ok());
+    }
+
+    #[test]
+    fn normalize_rejects_bad_characters() {
+        assert!(normalize_tag("a,b").is_err());
+        assert!(normalize_tag("<script>").is_err());
+    }
+
+    #[test]
+    fn normalize_rejects_empty_folders() {
+        assert!(normalize_tag("/clients").is_err());
+        assert!(normalize_tag("clients/").is_err());
+        assert!(normalize_tag("clients//acme").is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Campaign (UTM) parameters: given as separate fields when shortening, added
+//! to the destination's query string and stored alongside the link so links
+//! can be filtered by campaign.
+
+use serde::{Deserialize, Serialize};
+use url::{form_urlencoded, Url};
+
+use super::passthrough::{param_name, query_params};
+use super::validate_url;
+
+/// Longest accepted value for a single UTM field
+pub const MAX_UTM_VALUE_LENGTH: usize = 200;
+
+/// Longest accepted UTM preset name
+pub const MAX_UTM_PRESET_NAME_LENGTH: usize = 64;
+
+/// The five standard `utm_*` parameters, each optional
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtmParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utm_source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utm_medium: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utm_campaign: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utm_term: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utm_content: Option<String>,
+}
+
+impl UtmParams {
+    /// Trim every field, treating blank ones as unset. Values are limited to
+    /// [`MAX_UTM_VALUE_LENGTH`] characters without control characters.
+    pub fn normalized(&self) -> Result<Self, String> {
+        let normalize = |field: &str, value: &Option<String>| {
+            let Some(value) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) else {
+                return Ok(None);
+            };
+            if value.chars().count() > MAX_UTM_VALUE_LENGTH {
+                return Err(format!(
+                    "{field} must be at most {MAX_UTM_VALUE_LENGTH} characters"
+                ));
+            }
+            if value.chars().any(char::is_control) {
+                return Err(format!("{field} cannot contain control characters"));
+            }
+            Ok(Some(value.to_string()))
+        };
+        Ok(Self {
+            utm_source: normalize("utm_source", &self.utm_source)?,
+            utm_medium: normalize("utm_medium", &self.utm_medium)?,
+            utm_campaign: normalize("utm_campaign", &self.utm_campaign)?,
+            utm_term: normalize("utm_term", &self.utm_term)?,
+            utm_content: normalize("utm_content", &self.utm_content)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs().iter().all(|(_, value)| value.is_none())
+    }
+
+    /// These parameters, with any unset field taken from `preset`
+    pub fn or(self, preset: UtmParams) -> Self {
+        Self {
+            utm_source: self.utm_source.or(preset.utm_source),
+            utm_medium: self.utm_medium.or(preset.utm_medium),
+            utm_campaign: self.utm_campaign.or(preset.utm_campaign),
+            utm_term: self.utm_term.or(preset.utm_term),
+            utm_content: self.utm_content.or(preset.utm_content),
+        }
+    }
+
+    fn pairs(&self) -> [(&'static str, Option<&str>); 5] {
+        [
+            ("utm_source", self.utm_source.as_deref()),
+            ("utm_medium", self.utm_medium.as_deref()),
+            ("utm_campaign", self.utm_campaign.as_deref()),
+            ("utm_term", self.utm_term.as_deref()),
+            ("utm_content", self.utm_content.as_deref()),
+        ]
+    }
+
+    /// Add the set fields to `url`'s query string, replacing any parameter
+    /// of the same name already there. Every other parameter is kept byte
+    /// for byte as written. A URL that already has every set field with the
+    /// same value is returned as it is, so applying the fields again (as
+    /// re-importing a rus export does) changes nothing. The result must pass
+    /// [`validate_url`].
+    pub fn apply(&self, url: &str, max_url_length: usize) -> Result<String, String> {
+        if self.is_empty() {
+            return Ok(url.to_string());
+        }
+        let mut parsed = Url::parse(url).map_err(|e| format!("Invalid URL: {e}"))?;
+        let set: Vec<(&str, &str)> = self
+            .pairs()
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|v| (name, v)))
+            .collect();
+        let already_applied = set
+            .iter()
+            .all(|(name, value)| parsed.query_pairs().any(|(n, v)| n == *name && v == *value));
+        if already_applied {
+            validate_url(url, max_url_length)?;
+            return Ok(url.to_string());
+        }
+        let added = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&set)
+            .finish();
+        let existing = parsed.query().unwrap_or("").to_string();
+        let query: Vec<&str> = query_params(&existing)
+            .into_iter()
+            .filter(|param| {
+                let name = param_name(param);
+                !set.iter().any(|(n, _)| *n == name)
+            })
+            .chain(std::iter::once(added.as_str()))
+            .collect();
+        parsed.set_query(Some(&query.join("&")));
+
+        let url = String::from(parsed);
+        validate_url(&url, max_url_length)?;
+        Ok(url)
+    }
+}
+
+/// Trim a preset name and check it is 1-[`MAX_UTM_PRESET_NAME_LENGTH`]
+/// characters long
+pub fn validate_utm_preset_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_UTM_PRESET_NAME_LENGTH {
+        return Err(format!(
+            "Preset name must be at most {} characters",
+            MAX_UTM_PRESET_NAME_LENGTH
+        ));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utm(source: &str, medium: &str, campaign: &str) -> UtmParams {
+        UtmParams {
+            utm_source: Some(source.to_string()),
+            utm_medium: Some(medium.to_string()),
+            utm_campaign: Some(campaign.to_string()),
+            ..UtmParams::default()
+        }
+    }
+
+    #[test]
+    fn fields_are_trimmed_and_blanks_dropped() {
+        let params = utm(" newsletter ", "", "spring sale").normalized().unwrap();
+        assert_eq!(params.utm_source.as_deref(), Some("newsletter"));
+        assert_eq!(params.utm_medium, None);
+        assert_eq!(params.utm_campaign.as_deref(), Some("spring sale"));
+        assert!(UtmParams::default().normalized().unwrap().is_empty());
+
+        assert!(utm(&"a".repeat(MAX_UTM_VALUE_LENGTH + 1), "email", "x")
+            .normalized()
+            .is_err());
+        assert!(utm("news\nletter", "email", "x").normalized().is_err());
+    }
+
+    #[test]
+    fn apply_encodes_values_and_replaces_existing_parameters() {
+        let url = utm("newsletter", "email", "spring sale & more")
+            .apply("https://example.com/shop?ref=home&utm_source=old#top", 2048)
+            .unwrap();
+        assert_eq!(
+            url,
+            "https://example.com/shop?ref=home&utm_source=newsletter&utm_medium=email\
+             &utm_campaign=spring+sale+%26+more#top"
+        );
+        assert_eq!(
+            UtmParams::default()
+                .apply("https://example.com", 2048)
+                .unwrap(),
+            "https://example.com"
+        );
+        assert!(utm("a", "b", "c").apply("https://example.com", 30).is_err());
+    }
+
+    #[test]
+    fn apply_keeps_other_parameters_as_written() {
+        let params = UtmParams {
+            utm_source: Some("newsletter".to_string()),
+            ..UtmParams::default()
+        };
+        assert_eq!(
+            params.apply("https://example.com/?flag", 2048).unwrap(),
+            "https://example.com/?flag&utm_source=newsletter"
+        );
+        assert_eq!(
+            params
+                .apply("https://example.com/?q=a%20b&sig=x%2Fy", 2048)
+                .unwrap(),
+            "https://example.com/?q=a%20b&sig=x%2Fy&utm_source=newsletter"
+        );
+        assert_eq!(
+            params
+                .apply(
+                    "https://example.com/?utm_source=old&a=%20&utm_medium=email",
+                    2048
+                )
+                .unwrap(),
+            "https://example.com/?a=%20&utm_medium=email&utm_source=newsletter"
+        );
+    }
+
+    #[test]
+    fn apply_leaves_urls_that_already_have_the_fields_alone() {
+        let params = utm("newsletter", "email", "spring sale");
+        let url = "https://example.com/?utm_source=newsletter&utm_medium=email\
+                   &utm_campaign=spring+sale&ref=home";
+        assert_eq!(params.apply(url, 2048).unwrap(), url);
+        assert_eq!(
+            params
+                .apply(
+                    &params.apply("https://example.com/?ref=home", 2048).unwrap(),
+                    2048
+                )
+                .unwrap(),
+            params.apply("https://example.com/?ref=home", 2048).unwrap()
+        );
+        // A different value is still replaced
+        assert_eq!(
+            params
+                .apply(
+                    "https://example.com/?utm_source=old&utm_medium=email&utm_campaign=spring+sale",
+                    2048
+                )
+                .unwrap(),
+            "https://example.com/?utm_source=newsletter&utm_medium=email&utm_campaign=spring+sale"
+        );
+    }
+
+    #[test]
+    fn explicit_fields_win_over_the_preset() {
+        let merged = UtmParams {
+            utm_campaign: Some("launch".to_string()),
+            ..UtmParams::default()
+        }
+        .or(utm("newsletter", "email", "spring"));
+        assert_eq!(merged, utm("newsletter", "email", "launch"));
+    }
+
+    #[test]
+    fn preset_names_are_trimmed_and_limited() {
+        assert_eq!(
+            validate_utm_preset_name(" Newsletter ").unwrap(),
+            "Newsletter"
+        );
+        assert!(validate_utm_preset_name("  ").is_err());
+        assert!(validate_utm_preset_name(&"n".repeat(MAX_UTM_PRESET_NAME_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Weighted destinations for A/B tests and rotation: each redirect picks one
+//! of a link's variants with probability proportional to its weight.
+
+use super::validate_url;
+use crate::models::VariantInput;
+
+/// Maximum number of destination variants on a single link
+pub const MAX_VARIANTS_PER_URL: usize = 10;
+
+/// Largest accepted variant weight
+pub const MAX_VARIANT_WEIGHT: u32 = 1000;
+
+/// Cookie remembering the variant a visitor was sent to, scoped to the
+/// link's path so each link has its own
+pub const VARIANT_COOKIE: &str = "rus_variant";
+
+/// Validate a full set of variants, returning each destination with its
+/// weight (1 when not given). An empty list is allowed and removes the test;
+/// otherwise at least two distinct destinations are needed.
+pub fn validate_variants(
+    variants: &[VariantInput],
+    max_url_length: usize,
+) -> Result<Vec<(String, u32)>, String> {
+    if variants.len() == 1 {
+        return Err("An A/B test needs at least two variants".to_string());
+    }
+    if variants.len() > MAX_VARIANTS_PER_URL {
+        return Err(format!(
+            "A link can have at most {} variants",
+            MAX_VARIANTS_PER_URL
+        ));
+    }
+
+    let mut validated: Vec<(String, u32)> = Vec::with_capacity(variants.len());
+    for variant in variants {
+        let url = variant.url.trim();
+        validate_url(url, max_url_length)?;
+        if validated.iter().any(|(existing, _)| existing == url) {
+            return Err(format!("{} is listed as more than one variant", url));
+        }
+        let weight = variant.weight.unwrap_or(1);
+        if !(1..=MAX_VARIANT_WEIGHT).contains(&weight) {
+            return Err(format!(
+                "Variant weight must be between 1 and {}",
+                MAX_VARIANT_WEIGHT
+            ));
+        }
+        validated.push((url.to_string(), weight));
+    }
+    Ok(validated)
+}
+
+/// Index of the variant a roll in `0..weights.iter().sum()` lands on
+pub fn pick_weighted(weights: &[u32], roll: u32) -> usize {
+    let mut remaining = roll;
+    for (index, &weight) in weights.iter().enumerate() {
+        if remaining < weight {
+            return index;
+        }
+        remaining -= weight;
+    }
+    weights.len().saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(url: &str, weight: Option<u32>) -> VariantInput {
+        VariantInput {
+            url: url.to_string(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn weights_default_to_one_and_urls_are_validated() {
+        let variants = validate_variants(
+            &[
+                variant(" https://example.com/a ", Some(3)),
+                variant("https://example.com/b", None),
+            ],
+            2048,
+        )
+        .unwrap();
+        assert_eq!(
+            variants,
+            [
+                ("https://example.com/a".to_string(), 3),
+                ("https://example.com/b".to_string(), 1)
+            ]
+        );
+        assert!(validate_variants(
+            &[
+                variant("https://example.com/a", None),
+                variant("javascript:alert(1)", None)
+            ],
+            2048
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn refuses_lone_duplicate_and_zero_weight_variants() {
+        assert!(validate_variants(&[], 2048).unwrap().is_empty());
+        assert!(validate_variants(&[variant("https://example.com/a", None)], 2048).is_err());
+        assert!(validate_variants(
+            &[
+                variant("https://example.com/a", None),
+                variant("https://example.com/a", Some(2))
+            ],
+            2048
+        )
+        .is_err());
+        assert!(validate_variants(
+            &[
+                variant("https://example.com/a", Some(0)),
+                variant("https://example.com/b", None)
+            ],
+            2048
+        )
+        .is_err());
+        let too_many: Vec<VariantInput> = (0..=MAX_VARIANTS_PER_URL)
+            .map(|i| variant(&format!("https://example.com/{i}"), None))
+            .collect();
+        assert!(validate_variants(&too_many, 2048).is_err());
+    }
+
+    #[test]
+    fn rolls_land_in_proportion_to_weight() {
+        let weights = [3, 1];
+        let picks: Vec<usize> = (0..4).map(|roll| pick_weighted(&weights, roll)).collect();
+        assert_eq!(picks, [0, 0, 0, 1]);
+        assert_eq!(pick_weighted(&[1, 1, 1], 2), 2);
+    }
+}
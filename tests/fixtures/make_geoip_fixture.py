@@ -0,0 +1,121 @@
+#!/usr/bin/env python3
+"""Write geoip-test.mmdb, the tiny GeoIP2-City style database used by tests.
+
+Only a few IPv4 networks are included (see NETWORKS). Run from anywhere:
+
+    python3 tests/fixtures/make_geoip_fixture.py
+"""
+
+import ipaddress
+import os
+import struct
+
+NETWORKS = [
+    ("81.2.69.0/24", {"country": "GB", "subdivision": "ENG", "city": "London"}),
+    ("89.160.20.0/24", {"country": "SE", "subdivision": "E", "city": "Linköping"}),
+    ("216.160.83.0/24", {"country": "US", "subdivision": "WA", "city": "Milton"}),
+    ("203.0.113.0/24", {"country": "AU"}),
+]
+
+RECORD_SIZE = 24
+
+
+def control(type_id, size):
+    if size < 29:
+        first, extra = size, b""
+    elif size < 285:
+        first, extra = 29, bytes([size - 29])
+    elif size < 65821:
+        first, extra = 30, struct.pack(">H", size - 285)
+    else:
+        first, extra = 31, struct.pack(">I", size - 65821)[1:]
+    if type_id <= 7:
+        return bytes([(type_id << 5) | first]) + extra
+    return bytes([first, type_id - 7]) + extra
+
+
+def encode(value, type_id=None):
+    if isinstance(value, str):
+        data = value.encode("utf-8")
+        return control(2, len(data)) + data
+    if isinstance(value, dict):
+        out = control(7, len(value))
+        for key, item in value.items():
+            out += encode(key) + encode(item)
+        return out
+    if isinstance(value, list):
+        out = control(11, len(value))
+        for item in value:
+            out += encode(item)
+        return out
+    if isinstance(value, int):
+        data = value.to_bytes((value.bit_length() + 7) // 8, "big")
+        return control(type_id or 6, len(data)) + data
+    raise TypeError(value)
+
+
+def record(info):
+    city = {"country": {"iso_code": info["country"], "names": {"en": info["country"]}}}
+    if "subdivision" in info:
+        city["subdivisions"] = [{"iso_code": info["subdivision"]}]
+    if "city" in info:
+        city["city"] = {"names": {"en": info["city"]}}
+    return city
+
+
+def build():
+    # Each node is [left, right]; ints are node indexes, tuples are ("data", offset)
+    nodes = [[None, None]]
+    data = b""
+    for cidr, info in NETWORKS:
+        net = ipaddress.ip_network(cidr)
+        offset = len(data)
+        data += encode(record(info))
+        bits = int(net.network_address)
+        node = 0
+        for i in range(net.prefixlen):
+            bit = (bits >> (31 - i)) & 1
+            if i == net.prefixlen - 1:
+                nodes[node][bit] = ("data", offset)
+            else:
+                if nodes[node][bit] is None:
+                    nodes.append([None, None])
+                    nodes[node][bit] = len(nodes) - 1
+                node = nodes[node][bit]
+
+    node_count = len(nodes)
+    tree = b""
+    for node in nodes:
+        for rec in node:
+            if rec is None:
+                value = node_count
+            elif isinstance(rec, tuple):
+                value = node_count + 16 + rec[1]
+            else:
+                value = rec
+            tree += value.to_bytes(3, "big")
+
+    metadata = {
+        "binary_format_major_version": 2,
+        "binary_format_minor_version": 0,
+        "build_epoch": 1_700_000_000,
+        "database_type": "GeoIP2-City",
+        "description": {"en": "rus test fixture"},
+        "ip_version": 4,
+        "languages": ["en"],
+        "node_count": node_count,
+        "record_size": RECORD_SIZE,
+    }
+    meta = control(7, len(metadata))
+    for key, value in metadata.items():
+        type_id = {"build_epoch": 9, "node_count": 6}.get(key, 5)
+        meta += encode(key) + encode(value, type_id)
+
+    return tree + bytes(16) + data + b"\xab\xcd\xefMaxMind.com" + meta
+
+
+if __name__ == "__main__":
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "geoip-test.mmdb")
+    with open(path, "wb") as f:
+        f.write(build())
+    print("wrote", path)
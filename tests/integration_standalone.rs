@@ -5,7 +5,7 @@
 
 #![cfg(feature = "standalone")]
 
-use actix_web::{test, web, App};
+use actix_web::{guard, test, web, App};
 use actix_web_httpauth::middleware::HttpAuthentication;
 use serde_json::Value;
 
@@ -14,6 +14,7 @@ use rus::auth::middleware::{admin_validator, jwt_validator};
 use rus::config::Config;
 use rus::db::AppState;
 use rus::handlers::*;
+use rus::import::IMPORT_MAX_BYTES;
 
 const TEST_PASSWORD: &str = "TestPass1!";
 
@@ -25,6 +26,9 @@ fn test_config() -> Config {
         db_path: ":memory:".to_string(),
         host: "127.0.0.1".to_string(),
         port: 4001,
+        geoip_db_path: None,
+        default_redirect_status: 302,
+        trusted_proxies: Default::default(),
         jwt_secret: "test-secret-at-least-32-chars-ok!".to_string(),
         jwt_expiry_hours: 1,
         refresh_token_expiry_days: 7,
@@ -66,6 +70,7 @@ async fn build_app_with_state(
             .route("/api/refresh", web::post().to(refresh_token))
             .route("/api/config", web::get().to(get_config))
             .route("/api/version", web::get().to(get_version))
+            .route("/api/public/stats/{code}", web::get().to(get_public_stats))
             .route("/api/setup/required", web::get().to(check_setup_required))
             .route("/api/report-abuse", web::post().to(submit_abuse_report))
             .service(
@@ -86,11 +91,70 @@ async fn build_app_with_state(
                     .wrap(auth)
                     .route("/me", web::get().to(get_current_user))
                     .route("/shorten", web::post().to(shorten_url))
+                    .route("/shorten/bulk", web::post().to(bulk_shorten_urls))
+                    .route("/export", web::get().to(export_urls))
+                    .service(
+                        web::resource("/import")
+                            .app_data(web::PayloadConfig::new(IMPORT_MAX_BYTES))
+                            .route(web::post().to(import_urls)),
+                    )
                     .route("/stats/{code}", web::get().to(get_stats))
                     .route("/urls", web::get().to(get_user_urls))
                     .route("/urls/{code}", web::delete().to(delete_url))
                     .route("/urls/{code}/name", web::patch().to(update_url_name))
+                    .route("/urls/{code}/expiry", web::patch().to(update_url_expiry))
+                    .route(
+                        "/urls/{code}/schedule",
+                        web::patch().to(update_url_schedule),
+                    )
+                    .route(
+                        "/urls/{code}/platform-urls",
+                        web::patch().to(update_url_platform_urls),
+                    )
+                    .route(
+                        "/urls/{code}/redirect-status",
+                        web::patch().to(update_url_redirect_status),
+                    )
+                    .route(
+                        "/urls/{code}/passthrough",
+                        web::patch().to(update_url_passthrough),
+                    )
+                    .route("/urls/{code}/geo-rules", web::get().to(get_geo_rules))
+                    .route("/urls/{code}/geo-rules", web::put().to(replace_geo_rules))
+                    .route("/urls/{code}/variants", web::get().to(get_url_variants))
+                    .route("/urls/{code}/variants", web::put().to(replace_url_variants))
+                    .route("/urls/{code}/tags", web::post().to(add_url_tags))
+                    .route(
+                        "/urls/{code}/tags/{tag:.*}",
+                        web::delete().to(remove_url_tag),
+                    )
+                    .route("/tags", web::get().to(list_tags))
+                    .route("/utm-presets", web::get().to(list_utm_presets))
+                    .route("/utm-presets", web::post().to(save_utm_preset))
+                    .route("/utm-presets/{id}", web::delete().to(delete_utm_preset))
+                    .route(
+                        "/urls/{code}/destination",
+                        web::patch().to(update_url_destination),
+                    )
+                    .route("/urls/{code}/revisions", web::get().to(get_url_revisions))
+                    .route(
+                        "/urls/{code}/password",
+                        web::patch().to(update_url_password),
+                    )
+                    .route(
+                        "/urls/{code}/public-stats",
+                        web::patch().to(update_url_public_stats),
+                    )
                     .route("/urls/{code}/clicks", web::get().to(get_click_history))
+                    .route(
+                        "/urls/{code}/clicks/timeseries",
+                        web::get().to(get_click_timeseries),
+                    )
+                    .route(
+                        "/clicks/timeseries",
+                        web::get().to(get_account_click_timeseries),
+                    )
+                    .route("/clicks/stream", web::get().to(click_stream))
                     .route("/urls/{code}/qr/{format}", web::get().to(get_qr_code)),
             )
             .route("/", web::get().to(index))
@@ -103,7 +167,17 @@ async fn build_app_with_state(
             .route("/styles.css", web::get().to(serve_css))
             .route("/k9f3x2m7.js", web::get().to(serve_auth_js))
             .route("/health", web::get().to(health_check))
-            .route("/{code}", web::get().to(redirect_url)),
+            .route("/{code}+", web::get().to(public_stats_page))
+            .service(
+                web::resource(["/{code}", "/{code}/{tail:.*}"])
+                    .route(web::get().to(redirect_url))
+                    .route(
+                        web::post()
+                            .guard(guard::fn_guard(password_protected_link))
+                            .to(unlock_url),
+                    )
+                    .route(web::route().to(redirect_url)),
+            ),
     )
     .await
 }
@@ -254,7 +328,7 @@ async fn e2e_shorten_rename_list_delete() {
         .insert_header(("Authorization", format!("Bearer {token}")))
         .to_request();
     let urls: Value = test::call_and_read_body_json(&app, req).await;
-    let arr = urls.as_array().unwrap();
+    let arr = urls["urls"].as_array().unwrap();
     assert_eq!(arr.len(), 1);
     assert_eq!(arr[0]["name"], "My Link");
 
@@ -272,7 +346,7 @@ async fn e2e_shorten_rename_list_delete() {
         .insert_header(("Authorization", format!("Bearer {token}")))
         .to_request();
     let urls: Value = test::call_and_read_body_json(&app, req).await;
-    assert_eq!(urls.as_array().unwrap().len(), 0);
+    assert_eq!(urls["urls"].as_array().unwrap().len(), 0);
 
     // Redirect now 404
     let req = test::TestRequest::get()
@@ -350,7 +424,7 @@ async fn e2e_users_cannot_see_each_others_urls() {
         .insert_header(("Authorization", format!("Bearer {bob_token}")))
         .to_request();
     let urls: Value = test::call_and_read_body_json(&app, req).await;
-    assert_eq!(urls.as_array().unwrap().len(), 0);
+    assert_eq!(urls["urls"].as_array().unwrap().len(), 0);
 }
 
 // =============================================================================